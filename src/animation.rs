@@ -0,0 +1,301 @@
+use std::fs;
+use super::utils::{ vec3::Vec3, Float };
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Interpolation
+{
+    Linear,
+    CatmullRom
+}
+
+impl Interpolation
+{
+    pub fn from_name(i_name: &str) -> Option<Self>
+    {
+        match i_name
+        {
+            "linear"      => Some(Self::Linear),
+            "catmull-rom" => Some(Self::CatmullRom),
+            _             => None
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CameraKeyframe
+{
+    pub time:     Float, // Seconds
+    pub position: Vec3,
+    pub target:   Vec3,
+    pub fov:      Float  // Vertical, in degrees
+}
+
+pub struct CameraPath
+{
+    keyframes:     Vec<CameraKeyframe>,
+    interpolation: Interpolation
+}
+
+impl CameraPath
+{
+    pub fn new(interpolation: Interpolation) -> Self
+    {
+        Self{ keyframes: Vec::new(), interpolation }
+    }
+
+    // Keyframes are kept sorted by time
+    pub fn add_keyframe(&mut self, key: CameraKeyframe)
+    {
+        let idx = self.keyframes
+                      .iter()
+                      .position(|k| k.time > key.time)
+                      .unwrap_or(self.keyframes.len());
+
+        self.keyframes.insert(idx, key);
+    }
+
+    // A full orbit around the target, starting and ending at the same point
+    pub fn turntable(target: Vec3, start: Vec3, fov: Float, duration: Float, interpolation: Interpolation) -> Self
+    {
+        const STEPS: i32 = 8;
+
+        let mut result = Self::new(interpolation);
+        let offset     = start - target;
+
+        for i in 0..=STEPS
+        {
+            let angle = super::utils::TAU * i as Float / STEPS as Float;
+            let (sin, cos) = angle.sin_cos();
+            let rotated    = Vec3::new(offset.x() * cos - offset.z() * sin,
+                                       offset.y(),
+                                       offset.x() * sin + offset.z() * cos);
+
+            result.add_keyframe( CameraKeyframe{ time: duration * i as Float / STEPS as Float,
+                                                 position: target + rotated,
+                                                 target,
+                                                 fov } );
+        }
+        return result;
+    }
+
+    // One keyframe per line: "time px py pz tx ty tz fov". Empty lines and lines starting with # are skipped.
+    pub fn from_file(i_file_name: &str, interpolation: Interpolation) -> Result<Self, String>
+    {
+        let contents = fs::read_to_string(i_file_name).map_err(|e| format!("{}: {}", i_file_name, e))?;
+        return Self::parse(&contents, interpolation);
+    }
+
+    pub fn parse(i_contents: &str, interpolation: Interpolation) -> Result<Self, String>
+    {
+        let mut result = Self::new(interpolation);
+
+        for (line_idx, line) in i_contents.lines().enumerate()
+        {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue; }
+
+            let values: Vec<Float> = line.split_whitespace()
+                                       .map(str::parse::<Float>)
+                                       .collect::<Result<_, _>>()
+                                       .map_err(|e| format!("Line {}: {}", line_idx+1, e))?;
+
+            if values.len() != 8
+            {
+                return Err(format!("Line {}: expected 8 values, found {}", line_idx+1, values.len()));
+            }
+
+            result.add_keyframe( CameraKeyframe{ time:     values[0],
+                                                 position: Vec3::new(values[1], values[2], values[3]),
+                                                 target:   Vec3::new(values[4], values[5], values[6]),
+                                                 fov:      values[7] } );
+        }
+
+        if result.keyframes.is_empty() { return Err(String::from("No keyframes found")); }
+        return Ok(result);
+    }
+
+    pub fn duration(&self) -> Float
+    {
+        match (self.keyframes.first(), self.keyframes.last())
+        {
+            (Some(first), Some(last)) => last.time - first.time,
+            _                         => 0.0
+        }
+    }
+
+    // Times outside the path hold the first/last keyframe
+    pub fn evaluate(&self, time: Float) -> CameraKeyframe
+    {
+        let keys = &self.keyframes;
+        assert!( !keys.is_empty(), "Evaluating an empty camera path" );
+
+        if time <= keys[0].time            { return CameraKeyframe{ time, ..keys[0] }; }
+        if time >= keys[keys.len()-1].time { return CameraKeyframe{ time, ..keys[keys.len()-1] }; }
+
+        let i1 = keys.iter().rposition(|k| k.time <= time).unwrap();
+        let i2 = i1 + 1;
+        let s  = (time - keys[i1].time) / (keys[i2].time - keys[i1].time);
+
+        match self.interpolation
+        {
+            Interpolation::Linear => CameraKeyframe
+            {
+                time,
+                position: Vec3::lerp(keys[i1].position, keys[i2].position, s),
+                target:   Vec3::lerp(keys[i1].target,   keys[i2].target,   s),
+                fov:      keys[i1].fov + (keys[i2].fov - keys[i1].fov) * s
+            },
+            Interpolation::CatmullRom =>
+            {
+                // The tangents at both ends use the end keyframes themselves as neighbours
+                let k0 = &keys[i1.saturating_sub(1)];
+                let k1 = &keys[i1];
+                let k2 = &keys[i2];
+                let k3 = &keys[(i2 + 1).min(keys.len() - 1)];
+
+                CameraKeyframe
+                {
+                    time,
+                    position: Self::catmull_rom(k0.position, k1.position, k2.position, k3.position, s),
+                    target:   Self::catmull_rom(k0.target,   k1.target,   k2.target,   k3.target,   s),
+                    fov:      Self::catmull_rom(Vec3::one() * k0.fov,
+                                                Vec3::one() * k1.fov,
+                                                Vec3::one() * k2.fov,
+                                                Vec3::one() * k3.fov, s).x()
+                }
+            }
+        }
+    }
+
+    fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, s: Float) -> Vec3
+    {
+        let s2 = s * s;
+        let s3 = s2 * s;
+
+        return ( p1 * 2.0
+               + (p2 - p0) * s
+               + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * s2
+               + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * s3 ) * 0.5;
+    }
+}
+
+// Which frames to render and how they map to time
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FrameRange
+{
+    pub first:   u32,
+    pub last:    u32, // Inclusive
+    pub fps:     Float,
+    pub shutter: Float  // Fraction of the frame the shutter stays open, 0.5 is a 180 degree shutter
+}
+
+impl FrameRange
+{
+    pub fn frame_time(&self, frame: u32) -> Float { frame as Float / self.fps }
+
+    pub fn shutter_interval(&self, frame: u32) -> (Float, Float)
+    {
+        let t0 = self.frame_time(frame);
+        return (t0, t0 + self.shutter / self.fps);
+    }
+
+    // out.ppm -> out_0001.ppm
+    pub fn file_name(i_output: &str, frame: u32) -> String
+    {
+        match i_output.rfind('.')
+        {
+            Some(dot) => format!("{}_{:04}{}", &i_output[..dot], frame, &i_output[dot..]),
+            None      => format!("{}_{:04}", i_output, frame)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use float_cmp::approx_eq;
+
+    fn key(time: Float, x: Float, fov: Float) -> CameraKeyframe
+    {
+        CameraKeyframe{ time, position: Vec3::new(x, 0.0, 0.0), target: Vec3::zero(), fov }
+    }
+
+    fn path(interpolation: Interpolation) -> CameraPath
+    {
+        let mut result = CameraPath::new(interpolation);
+        // Out of order on purpose
+        result.add_keyframe( key(2.0, 4.0, 60.0) );
+        result.add_keyframe( key(0.0, 0.0, 90.0) );
+        result.add_keyframe( key(1.0, 1.0, 90.0) );
+        result.add_keyframe( key(3.0, 9.0, 30.0) );
+        return result;
+    }
+
+    #[test]
+    fn linear()
+    {
+        let path = path(Interpolation::Linear);
+
+        assert_eq!( path.duration(), 3.0 );
+        assert_eq!( path.evaluate(1.5).position, Vec3::new(2.5, 0.0, 0.0) );
+        assert_eq!( path.evaluate(2.5).fov, 45.0 );
+
+        // Clamped outside the keyframes
+        assert_eq!( path.evaluate(-1.0).position, Vec3::zero() );
+        assert_eq!( path.evaluate(10.0).position, Vec3::new(9.0, 0.0, 0.0) );
+    }
+
+    #[test]
+    fn catmull_rom_goes_through_keyframes()
+    {
+        let path = path(Interpolation::CatmullRom);
+
+        for (time, x) in &[(0.0, 0.0), (1.0, 1.0), (2.0, 4.0), (3.0, 9.0)]
+        {
+            assert!( approx_eq!(Float, path.evaluate(*time).position.x(), *x, epsilon = 0.0001) );
+        }
+
+        // Smooth, unlike linear which would give 2.5
+        let mid = path.evaluate(1.5).position.x();
+        assert!( approx_eq!(Float, mid, 2.25, epsilon = 0.0001) );
+    }
+
+    #[test]
+    fn turntable_loops()
+    {
+        let path  = CameraPath::turntable(Vec3::zero(), Vec3::new(5.0, 1.0, 0.0), 60.0, 4.0, Interpolation::Linear);
+        let start = path.evaluate(0.0).position;
+
+        assert!( (path.evaluate(4.0).position - start).norm() < 0.0001 );
+        assert!( (path.evaluate(2.0).position - Vec3::new(-5.0, 1.0, 0.0)).norm() < 0.0001 );
+    }
+
+    #[test]
+    fn parse_keyframes()
+    {
+        let contents = "# time pos target fov\n\
+                        0 0 1 -5  0 0 0  60\n\
+                        \n\
+                        1 5 1 0   0 0 0  45\n";
+
+        let path = CameraPath::parse(contents, Interpolation::Linear).unwrap();
+        assert_eq!( path.duration(), 1.0 );
+        assert_eq!( path.evaluate(1.0).fov, 45.0 );
+
+        assert!( CameraPath::parse("0 1 2", Interpolation::Linear).is_err() );
+        assert!( CameraPath::parse("# nothing", Interpolation::Linear).is_err() );
+    }
+
+    #[test]
+    fn frames()
+    {
+        let range = FrameRange{ first: 1, last: 48, fps: 24.0, shutter: 0.5 };
+        let (t0, t1) = range.shutter_interval(12);
+
+        assert_eq!( t0, 0.5 );
+        assert!( approx_eq!(Float, t1 - t0, 1.0 / 48.0) );
+        assert_eq!( FrameRange::file_name("out.ppm", 7), "out_0007.ppm" );
+        assert_eq!( FrameRange::file_name("out", 12),    "out_0012" );
+    }
+}
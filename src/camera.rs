@@ -1,92 +1,456 @@
-use super::utils::vec3::Vec3;
+use super::utils::{ vec3::Vec3, Float };
 use super::ray::Ray;
 
 use super::utils;
+use super::hittables::{ Intersectionable, closest_hit };
+use super::animation::{ CameraKeyframe, CameraPath };
 
+use std::rc::Rc;
+
+const DEGENERATE_EPSILON: Float = 1e-8;
+
+#[derive(Copy, Clone)]
 pub struct Rect
 {
-    pub width:  f32,
-    pub height: f32
+    pub width:  Float,
+    pub height: Float
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FisheyeMapping
+{
+    Equidistant, // Distance from the image center proportional to the angle
+    Equisolid    // Preserves relative areas
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection
+{
+    Perspective,
+    Orthographic{ height: Option<Float> }, // World units covered vertically, None for what perspective covers in focus
+    Fisheye(FisheyeMapping),               // The image circle spans the vertical FOV
+    Equirectangular                        // Full 360x180 degrees panorama
+}
+
+impl Projection
+{
+    pub fn from_name(i_name: &str) -> Option<Self>
+    {
+        match i_name
+        {
+            "perspective"       => Some(Self::Perspective),
+            "orthographic"      => Some(Self::Orthographic{ height: None }),
+            "fisheye"           => Some(Self::Fisheye(FisheyeMapping::Equidistant)),
+            "fisheye-equisolid" => Some(Self::Fisheye(FisheyeMapping::Equisolid)),
+            "equirectangular"   => Some(Self::Equirectangular),
+            _                   => None
+        }
+    }
+}
+
+// Imperfections of a real lens, all disabled by default
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Lens
+{
+    pub blades:               u32, // Aperture polygon sides, less than 3 means a perfect circle
+    pub blade_rotation:       Float, // Degrees
+    pub vignetting:           Float, // Cat-eye strength, 0 disables it
+    pub chromatic_aberration: Float  // Relative magnification difference between the red and blue channels
+}
+
+impl Default for Lens
+{
+    fn default() -> Self
+    {
+        Self{ blades: 0, blade_rotation: 0.0, vignetting: 0.0, chromatic_aberration: 0.0 }
+    }
 }
 
+#[derive(Clone)]
 pub struct Camera
 {
-    t0:                f32, // Shutter open time
-    t1:                f32, // Shutter close time
-    pub lens_radius:   f32,
-    pub focus_dist:    f32,
-    pub focal_len:     f32,
-    pub viewport:      Rect,
-    pub origin:        Vec3,
-    pub forward:       Vec3,
-    pub up:            Vec3,
-    pub left:          Vec3,
-    lower_left_corner: Vec3
+    projection:        Projection,
+    lens:              Lens,
+    t0:                Float, // Shutter open time
+    t1:                Float, // Shutter close time
+    vfov:              Float, // Degrees
+    aspect_ratio:      Float,
+    lens_radius:       Float,
+    focus_dist:        Float,
+    focal_len:         Float,
+    viewport:          Rect,
+    origin:            Vec3,
+    forward:           Vec3,
+    up:                Vec3,
+    left:              Vec3,
+    horizontal:        Vec3, // Left before the roll
+    world_up:          Vec3,
+    roll:              Float, // Degrees
+    lower_left_corner: Vec3,
+    path:              Option<Rc<CameraPath>> // Moves the camera while the shutter is open
 }
 
 impl Camera
 {
-    pub fn new(vfov: f32, aspect_ratio: f32, aperture: f32, focus_dist: f32, t0:f32, t1: f32) -> Self
+    pub fn new(vfov: Float, aspect_ratio: Float, aperture: Float, focus_dist: Float, t0:Float, t1: Float) -> Self
     {
-        let h      = (utils::degrees_to_radians(vfov) * 0.5).tan();
-        let height = 2.0 * h;
-        let width  = aspect_ratio * height;
-
         let mut result = Self
         {
             t0, t1,
+            projection : Projection::Perspective,
+            lens       : Lens::default(),
+            vfov,
+            aspect_ratio,
             focus_dist,
             lens_radius: aperture*0.5,
             focal_len  : 1.0,
-            viewport   : Rect{width, height},
+            viewport   : Rect{width: 0.0, height: 0.0},
             origin     : Vec3::zero(),
             forward    : Vec3::new(0.0, 0.0, 1.0),
             up         : Vec3::new(0.0, 1.0, 0.0),
             left       : Vec3::new(-1.0, 0.0, 0.0),
-            lower_left_corner: Vec3::zero()
+            horizontal : Vec3::new(-1.0, 0.0, 0.0),
+            world_up   : Vec3::new(0.0, 1.0, 0.0),
+            roll       : 0.0,
+            lower_left_corner: Vec3::zero(),
+            path       : None
         };
-        result.recalculate_lower_left_corner();
+        result.recalculate_viewport();
 
         return result;
     }
 
-    //pub fn resize(&mut self, width: f32, height: f32)
-    //{
-    //    self.viewport = Rect{width, height};
-    //    self.recalculate_lower_left_corner();
-    //}
+    pub fn forward(&self)    -> Vec3 { self.forward }
+    pub fn focus_dist(&self) -> Float  { self.focus_dist }
 
-    pub fn look_at(&mut self, target: Vec3)
+    pub fn set_vfov(&mut self, vfov: Float)
     {
-        let look_dir = (target - self.origin).normalized();
+        self.vfov = vfov;
+        self.recalculate_viewport();
+    }
 
-        self.forward = look_dir;
-        self.left = Vec3::new(0.0, 1.0, 0.0) // World's UP
-                        .cross(look_dir)
-                        .normalized();
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: Float)
+    {
+        self.aspect_ratio = aspect_ratio;
+        self.recalculate_viewport();
+    }
 
-        self.up = look_dir.cross(self.left);
+    // Output resolution in pixels, only its proportions matter
+    pub fn resize(&mut self, width: u32, height: u32)
+    {
+        self.set_aspect_ratio( width as Float / height as Float );
+    }
 
+    pub fn set_aperture(&mut self, aperture: Float)
+    {
+        self.lens_radius = aperture * 0.5;
+    }
+
+    pub fn set_focus_dist(&mut self, focus_dist: Float)
+    {
+        self.focus_dist = focus_dist;
         self.recalculate_lower_left_corner();
     }
 
+    // A zero up vector has no direction, so it's ignored
+    pub fn set_up(&mut self, world_up: Vec3)
+    {
+        if world_up.norm2() < DEGENERATE_EPSILON { return; }
+
+        self.world_up = world_up.normalized();
+        self.recalculate_basis();
+    }
+
+    // Focuses on whatever is at the center of the frame, keeping the current distance if there's nothing
+    pub fn auto_focus(&mut self, i_scene: &[Intersectionable])
+    {
+        let center_ray = Ray::new(self.origin, self.forward, self.t0);
+        if let Some((_, hit)) = closest_hit(i_scene, &center_ray, 0.0, Float::MAX)
+        {
+            self.set_focus_dist(hit.distance);
+        }
+    }
+
+    // Looking at the camera's own position has no direction, so it's ignored
+    pub fn look_at(&mut self, target: Vec3)
+    {
+        let look_dir = target - self.origin;
+        if look_dir.norm2() < DEGENERATE_EPSILON { return; }
+
+        self.forward = look_dir.normalized();
+        self.recalculate_basis();
+    }
+
+    // Same as look_at, keeping the current up vector if world_up is zero
+    pub fn look_at_with_up(&mut self, target: Vec3, world_up: Vec3)
+    {
+        self.set_up(world_up);
+        self.look_at(target);
+    }
+
+    // Rotation around the view direction, counter-clockwise in degrees
+    pub fn set_roll(&mut self, roll: Float)
+    {
+        self.roll = roll;
+        self.recalculate_basis();
+    }
+
     pub fn move_to(&mut self, new_pos: Vec3)
     {
         self.origin = new_pos;
         self.recalculate_lower_left_corner();
     }
 
-    pub fn get_ray(&self, u: f32, v: f32) -> Ray
+    pub fn set_projection(&mut self, projection: Projection)
+    {
+        self.projection = projection;
+    }
+
+    pub fn set_shutter(&mut self, t0: Float, t1: Float)
+    {
+        self.t0 = t0;
+        self.t1 = t1;
+    }
+
+    pub fn set_lens(&mut self, lens: Lens)
+    {
+        self.lens = lens;
+    }
+
+    // Every ray is shot from where the path is at the ray's time. Outside of that the camera stays
+    // where the path is at t0, focused on its target.
+    pub fn set_path(&mut self, path: Rc<CameraPath>)
     {
+        let key = path.evaluate(self.t0);
+        self.pose(&key);
+        self.set_focus_dist( (key.position - key.target).norm() );
+
+        self.path = Some(path);
+    }
+
+    // The focus distance stays as it is
+    fn pose(&mut self, key: &CameraKeyframe)
+    {
+        self.move_to( key.position );
+        self.look_at( key.target );
+        self.set_vfov( key.fov );
+    }
+
+    // u,v in [0,1] with 0,0 being the lower left corner of the image.
+    // None means that point of the image isn't covered by the projection (e.g. outside a fisheye's circle)
+    #[allow(dead_code)]
+    pub fn get_ray(&self, u: Float, v: Float) -> Option<Ray>
+    {
+        return self.sample_ray(u, v).map(|(ray, _)| ray);
+    }
+
+    // Same as get_ray, but also returns the per channel weight the ray's radiance has to be scaled by.
+    // It's only not white when the lens disperses light, as then each ray carries a single channel.
+    pub fn sample_ray(&self, u: Float, v: Float) -> Option<(Ray, Vec3)>
+    {
+        let time = utils::rand_in_range(self.t0, self.t1);
+
+        match &self.path
+        {
+            Some(path) =>
+            {
+                let mut posed = self.clone();
+                posed.pose(&path.evaluate(time));
+                posed.sample_ray_at(u, v, time)
+            },
+            None => self.sample_ray_at(u, v, time)
+        }
+    }
+
+    fn sample_ray_at(&self, u: Float, v: Float, time: Float) -> Option<(Ray, Vec3)>
+    {
+        match self.projection
+        {
+            Projection::Perspective            =>
+            {
+                let (dispersion, weight) = self.pick_channel();
+                self.get_perspective_ray(u, v, dispersion, time).map(|ray| (ray, weight))
+            },
+            Projection::Orthographic{ height } =>
+            {
+                let height = height.unwrap_or(self.viewport.height * self.focus_dist);
+                Some((self.get_orthographic_ray(u, v, height, time), Vec3::one()))
+            },
+            Projection::Fisheye(mapping)       => self.get_fisheye_ray(u, v, mapping, time).map(|ray| (ray, Vec3::one())),
+            Projection::Equirectangular        => Some((self.get_equirectangular_ray(u, v, time), Vec3::one()))
+        }
+    }
+
+    // Light tracing can only reach cameras whose rays depend on nothing but the point on the lens
+    pub fn is_connectible(&self) -> bool
+    {
+        self.projection == Projection::Perspective && self.lens.vignetting == 0.0 && self.lens.chromatic_aberration == 0.0 && self.path.is_none()
+    }
+
+    // Where i_point shows up on the image seen from a random point of the lens, for tracing light towards
+    // the camera: the point on the lens and u,v as in get_ray. None if it's behind the camera.
+    pub fn sample_towards(&self, i_point: Vec3) -> Option<(Vec3, Float, Float)>
+    {
+        let rand_dir = self.sample_aperture() * self.lens_radius;
+        let origin   = self.origin + self.left * rand_dir.x() + self.up * rand_dir.y();
+
+        let direction = i_point - origin;
+        let depth     = direction.dot(self.forward);
+        if depth <= 0.0 { return None; }
+
+        // Where it crosses the plane in focus, which camera rays are aimed at
+        let offset = origin + direction * (self.focal_len * self.focus_dist / depth) - self.lower_left_corner;
+        let u      = offset.dot(self.left) / (self.viewport.width  * self.focus_dist);
+        let v      = offset.dot(self.up)   / (self.viewport.height * self.focus_dist);
+
+        return Some((origin, u, v));
+    }
+
+    // Density per unit solid angle of perspective rays going along i_direction, if u,v were uniform in [0,1]
+    pub fn importance(&self, i_direction: Vec3) -> Float
+    {
+        let cos = i_direction.normalized().dot(self.forward);
+        if cos <= 0.0 { return 0.0; }
+
+        return self.focal_len * self.focal_len / (self.viewport.width * self.viewport.height * cos * cos * cos);
+    }
+
+    // Red is magnified the least and blue the most, green stays put
+    fn pick_channel(&self) -> (Float, Vec3)
+    {
+        if self.lens.chromatic_aberration == 0.0 { return (0.0, Vec3::one()); }
+
+        match (rand::random::<Float>() * 3.0) as u32
+        {
+            0 => (-1.0, Vec3::new(3.0, 0.0, 0.0)),
+            1 => ( 0.0, Vec3::new(0.0, 3.0, 0.0)),
+            _ => ( 1.0, Vec3::new(0.0, 0.0, 3.0))
+        }
+    }
+
+    fn sample_aperture(&self) -> Vec3
+    {
+        if self.lens.blades < 3 { return utils::rand_point_in_unit_disk(); }
+
+        let rotation = utils::degrees_to_radians(self.lens.blade_rotation);
+        return utils::rand_point_in_regular_polygon(self.lens.blades, rotation);
+    }
+
+    // i_dispersion: -1 to 1, how much of the chromatic aberration applies to this ray
+    fn get_perspective_ray(&self, u: Float, v: Float, i_dispersion: Float, time: Float) -> Option<Ray>
+    {
+        let magnification = 1.0 + self.lens.chromatic_aberration * i_dispersion;
+        let u = 0.5 + (u - 0.5) * magnification;
+        let v = 0.5 + (v - 0.5) * magnification;
+
         let hrz       = self.left * self.viewport.width  * self.focus_dist;
         let vrt       = self.up   * self.viewport.height * self.focus_dist;
         let pixel_pos = self.lower_left_corner + (hrz*u + vrt*v);
 
-        let rand_dir = utils::rand_point_in_unit_disk() * self.lens_radius ;
+        let lens_point = self.sample_aperture();
+
+        // Cat-eye: off-axis, the rear of the lens barrel clips the aperture into a lemon shape
+        if self.lens.vignetting > 0.0
+        {
+            let clip_center = Vec3::new(2.0*u - 1.0, 2.0*v - 1.0, 0.0) * self.lens.vignetting;
+            if (lens_point - clip_center).norm2() > 1.0 { return None; }
+        }
+
+        let rand_dir = lens_point * self.lens_radius;
         let offset   = self.left * rand_dir.x() + self.up * rand_dir.y();
         let origin   = self.origin + offset;
 
-        return Ray::new(origin, pixel_pos - origin, utils::rand_f32_in_range(self.t0, self.t1));
+        return Some(Ray::new(origin, pixel_pos - origin, time));
+    }
+
+    fn get_orthographic_ray(&self, u: Float, v: Float, height: Float, time: Float) -> Ray
+    {
+        let width  = height * self.aspect_ratio;
+        let origin = self.origin
+                   + self.left * (width  * (u - 0.5))
+                   + self.up   * (height * (v - 0.5));
+
+        return Ray::new(origin, self.forward, time);
+    }
+
+    fn get_fisheye_ray(&self, u: Float, v: Float, mapping: FisheyeMapping, time: Float) -> Option<Ray>
+    {
+        // Centered coordinates where the image circle has radius 1
+        let x = (2.0 * u - 1.0) * self.aspect_ratio;
+        let y =  2.0 * v - 1.0;
+        let r = (x*x + y*y).sqrt();
+
+        if r > 1.0 { return None; }
+
+        let half_fov = utils::degrees_to_radians(self.vfov) * 0.5;
+        let theta    = match mapping
+        {
+            FisheyeMapping::Equidistant => r * half_fov,
+            FisheyeMapping::Equisolid   => 2.0 * (r * (half_fov * 0.5).sin()).asin()
+        };
+        let phi = y.atan2(x);
+
+        let radial    = self.left * phi.cos() + self.up * phi.sin();
+        let direction = self.forward * theta.cos() + radial * theta.sin();
+
+        return Some(Ray::new(self.origin, direction, time));
+    }
+
+    fn get_equirectangular_ray(&self, u: Float, v: Float, time: Float) -> Ray
+    {
+        let longitude = (u - 0.5) * utils::TAU;
+        let latitude  = (v - 0.5) * utils::PI;
+
+        let direction = self.forward * (latitude.cos() * longitude.cos())
+                      + self.left    * (latitude.cos() * longitude.sin())
+                      + self.up      *  latitude.sin();
+
+        return Ray::new(self.origin, direction, time);
+    }
+
+    fn recalculate_basis(&mut self)
+    {
+        let mut left = self.world_up.cross(self.forward);
+
+        // Looking along the up vector: keep the previous horizontal axis so the frame doesn't flip,
+        // or fall back to any axis perpendicular to the view if that one is unusable too
+        if left.norm2() < DEGENERATE_EPSILON
+        {
+            left = self.horizontal - self.forward * self.horizontal.dot(self.forward);
+        }
+        if left.norm2() < DEGENERATE_EPSILON
+        {
+            left = Self::any_perpendicular(self.forward);
+        }
+
+        let left = left.normalized();
+        let up   = self.forward.cross(left);
+        self.horizontal = left;
+
+        let roll = utils::degrees_to_radians(self.roll);
+        let (sin, cos) = roll.sin_cos();
+
+        self.left = left * cos + up * sin;
+        self.up   = up * cos - left * sin;
+
+        self.recalculate_lower_left_corner();
+    }
+
+    fn any_perpendicular(v: Vec3) -> Vec3
+    {
+        let axis = if v.x().abs() < 0.9 { Vec3::new(1.0, 0.0, 0.0) }
+                   else                  { Vec3::new(0.0, 1.0, 0.0) };
+        return v.cross(axis);
+    }
+
+    fn recalculate_viewport(&mut self)
+    {
+        let h      = (utils::degrees_to_radians(self.vfov) * 0.5).tan();
+        let height = 2.0 * h;
+        let width  = self.aspect_ratio * height;
+
+        self.viewport = Rect{width, height};
+        self.recalculate_lower_left_corner();
     }
 
     fn recalculate_lower_left_corner(&mut self)
@@ -104,11 +468,12 @@ impl Camera
 mod tests
 {
     use super::*;
+    use float_cmp::approx_eq;
 
     fn new_test_camera() -> Camera
     {
         let cam_aspect_ratio = 1.0;
-        let cam_fov          = utils::radians_to_degrees( 2.0 * (0.5 as f32).atan() );
+        let cam_fov          = utils::radians_to_degrees( 2.0 * (0.5 as Float).atan() );
         let cam_aperture     = 0.0;
         let cam_focus_dist   = 1.0;
         let cam_shutter_t0   = 0.0;
@@ -144,9 +509,158 @@ mod tests
         assert_eq!( camera.lower_left_corner, llc1 + t );
         camera.move_to( Vec3::zero() );
 
-        // TODO: Rotation
-        //camera.look_at( t );
-        //assert_eq!( camera.lower_left_corner, );
+        // Rotation
+        camera.look_at( Vec3::new(1.0, 0.0, 0.0) );
+        assert_eq!( camera.lower_left_corner, Vec3::new(1.0, -0.5, 0.5) );
+
+        // Both
+        camera.move_to( t );
+        assert_eq!( camera.lower_left_corner, Vec3::new(1.0, -0.5, 0.5) + t );
+    }
+
+    fn assert_orthonormal_basis(camera: &Camera)
+    {
+        for v in &[camera.forward, camera.up, camera.left]
+        {
+            assert!( approx_eq!(Float, v.norm(), 1.0, epsilon = 0.0001) );
+        }
+        assert!( camera.forward.dot(camera.up).abs()   < 0.0001 );
+        assert!( camera.forward.dot(camera.left).abs() < 0.0001 );
+        assert!( camera.up.dot(camera.left).abs()      < 0.0001 );
+    }
+
+    #[test]
+    fn look_at_along_up_vector()
+    {
+        let mut camera = new_test_camera();
+
+        camera.look_at( Vec3::new(0.0, 10.0, 0.0) );
+        assert_eq!( camera.forward, Vec3::new(0.0, 1.0, 0.0) );
+        assert_orthonormal_basis(&camera);
+
+        camera.look_at( Vec3::new(0.0, -10.0, 0.0) );
+        assert_eq!( camera.forward, Vec3::new(0.0, -1.0, 0.0) );
+        assert_orthonormal_basis(&camera);
+
+        // Looking at itself doesn't change anything
+        camera.look_at( Vec3::zero() );
+        assert_eq!( camera.forward, Vec3::new(0.0, -1.0, 0.0) );
+    }
+
+    #[test]
+    fn explicit_up_vector()
+    {
+        let mut camera = new_test_camera();
+        camera.look_at_with_up( Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 0.0, 1.0) );
+
+        assert_eq!( camera.up, Vec3::new(0.0, 0.0, 1.0) );
+        assert_orthonormal_basis(&camera);
+    }
+
+    #[test]
+    fn roll()
+    {
+        let mut camera = new_test_camera();
+        camera.look_at( Vec3::new(0.0, 0.0, 1.0) );
+        let left = camera.left;
+        let up   = camera.up;
+
+        camera.set_roll( 90.0 );
+        assert_eq!( camera.left, up );
+        assert_eq!( camera.up,   -left );
+        assert_orthonormal_basis(&camera);
+
+        camera.set_roll( 0.0 );
+        assert_eq!( camera.left, left );
+    }
+
+    #[test]
+    fn roll_along_up_vector()
+    {
+        // Only the unrolled axis carries over, the roll is applied once
+        let mut plain  = new_test_camera();
+        let mut rolled = new_test_camera();
+        rolled.set_roll( 90.0 );
+
+        let look_up = |camera: &mut Camera|
+        {
+            camera.look_at( Vec3::new(0.0, 0.0, 1.0) );
+            camera.look_at( Vec3::new(0.0, 10.0, 0.0) );
+        };
+        look_up(&mut plain);
+        look_up(&mut rolled);
+
+        assert!( (rolled.left - plain.up).norm()   < 0.0001 );
+        assert!( (rolled.up   + plain.left).norm() < 0.0001 );
+        assert_orthonormal_basis(&rolled);
+    }
+
+    #[test]
+    fn zero_up_vector_is_ignored()
+    {
+        let mut camera = new_test_camera();
+        let up         = camera.up;
+
+        camera.set_up( Vec3::zero() );
+        assert_eq!( camera.up, up );
+
+        camera.look_at_with_up( Vec3::new(1.0, 0.0, 0.0), Vec3::zero() );
+        assert_eq!( camera.forward, Vec3::new(1.0, 0.0, 0.0) );
+        assert_eq!( camera.up,      up );
+        assert_orthonormal_basis(&camera);
+    }
+
+    #[test]
+    fn setters_keep_projection_consistent()
+    {
+        let mut camera = new_test_camera();
+        camera.move_to( Vec3::new(1.0, 2.0, 3.0) );
+        camera.look_at( Vec3::new(1.0, 2.0, 10.0) );
+
+        camera.set_vfov( 90.0 );
+        camera.resize( 200, 100 );
+        camera.set_focus_dist( 2.0 );
+
+        assert!( approx_eq!(Float, camera.viewport.width,  4.0, epsilon = 0.0001) );
+        assert!( approx_eq!(Float, camera.viewport.height, 2.0, epsilon = 0.0001) );
+
+        // The frame's center is still straight ahead
+        let ray = camera.get_ray(0.5, 0.5).unwrap();
+        assert_eq!( ray.direction, camera.forward );
+
+        // Top right corner of a 2:1 frame at 90 degrees vertical FOV
+        let ray = camera.get_ray(1.0, 1.0).unwrap();
+        assert_eq!( ray.direction, Vec3::new(2.0, 1.0, 1.0).normalized() );
+    }
+
+    #[test]
+    fn set_up_rebuilds_basis()
+    {
+        let mut camera = new_test_camera();
+        camera.set_up( Vec3::new(1.0, 0.0, 0.0) );
+
+        assert_eq!( camera.up,      Vec3::new(1.0, 0.0, 0.0) );
+        assert_eq!( camera.left,    Vec3::new(0.0, -1.0, 0.0) );
+        assert_eq!( camera.forward, Vec3::new(0.0, 0.0, 1.0) );
+    }
+
+    #[test]
+    fn auto_focus()
+    {
+        use super::super::materials::lambertian::LambertianMat;
+        use super::super::hittables::sphere::Sphere;
+
+        let material = std::rc::Rc::new( LambertianMat{ albedo: Vec3::one() } );
+        let scene    = vec![ Intersectionable::Sphere( Sphere::new(1.0, Vec3::new(0.0, 0.0, 5.0), material) ) ];
+
+        let mut camera = new_test_camera();
+        camera.auto_focus(&scene);
+        assert!( approx_eq!(Float, camera.focus_dist(), 4.0, epsilon = 0.0001) );
+
+        // Nothing to focus on: keeps the previous distance
+        camera.look_at( Vec3::new(0.0, 0.0, -1.0) );
+        camera.auto_focus(&scene);
+        assert!( approx_eq!(Float, camera.focus_dist(), 4.0, epsilon = 0.0001) );
     }
 
     #[test]
@@ -155,11 +669,11 @@ mod tests
         let camera = new_test_camera();
 
         // To the plane of projection's center
-        let ray = camera.get_ray(0.5, 0.5);
+        let ray = camera.get_ray(0.5, 0.5).unwrap();
         assert_eq!( ray.direction, camera.forward );
 
         // To the PoP's lower left corner
-        let ray = camera.get_ray(0.0, 0.0);
+        let ray = camera.get_ray(0.0, 0.0).unwrap();
         assert_eq!( ray.direction, camera.lower_left_corner.normalized() );
 
         // To the PoP's top right corner
@@ -167,7 +681,232 @@ mod tests
         let h   = camera.viewport.height;
         let trc = camera.lower_left_corner + Vec3::new( -w, h, 0.0 );
 
-        let ray = camera.get_ray(1.0, 1.0);
+        let ray = camera.get_ray(1.0, 1.0).unwrap();
         assert_eq!( ray.direction, trc.normalized() );
     }
+
+    #[test]
+    fn orthographic_rays_are_parallel()
+    {
+        let mut camera = new_test_camera();
+        camera.set_projection( Projection::Orthographic{ height: Some(4.0) } );
+
+        let center = camera.get_ray(0.5, 0.5).unwrap();
+        let corner = camera.get_ray(1.0, 1.0).unwrap();
+
+        assert_eq!( center.direction, camera.forward );
+        assert_eq!( corner.direction, camera.forward );
+        assert_eq!( center.origin, camera.origin );
+        assert_eq!( corner.origin, camera.origin + camera.left * 2.0 + camera.up * 2.0 );
+    }
+
+    #[test]
+    fn orthographic_height_follows_focus_distance()
+    {
+        let mut camera = new_test_camera();
+        camera.set_projection( Projection::from_name("orthographic").unwrap() );
+
+        // The perspective view is as tall as it is far away
+        camera.set_focus_dist( 6.0 );
+        let corner = camera.get_ray(1.0, 1.0).unwrap();
+        assert_eq!( corner.origin, camera.origin + camera.left * 3.0 + camera.up * 3.0 );
+    }
+
+    #[test]
+    fn fisheye()
+    {
+        let mut camera = new_test_camera();
+        camera.set_vfov( 180.0 );
+
+        for mapping in &[FisheyeMapping::Equidistant, FisheyeMapping::Equisolid]
+        {
+            camera.set_projection( Projection::Fisheye(*mapping) );
+
+            let center = camera.get_ray(0.5, 0.5).unwrap();
+            assert_eq!( center.direction, camera.forward );
+
+            // The edge of the image circle is at half the FOV
+            let edge = camera.get_ray(0.5, 1.0).unwrap();
+            assert!( approx_eq!(Float, edge.direction.dot(camera.forward), 0.0, epsilon = 0.0001) );
+            assert!( (edge.direction - camera.up).norm() < 0.0001 );
+
+            // Corners fall outside the circle
+            assert!( camera.get_ray(1.0, 1.0).is_none() );
+        }
+    }
+
+    #[test]
+    fn fisheye_mappings_differ_off_center()
+    {
+        let mut camera = new_test_camera();
+        camera.set_vfov( 180.0 );
+
+        camera.set_projection( Projection::Fisheye(FisheyeMapping::Equidistant) );
+        let equidistant = camera.get_ray(0.5, 0.75).unwrap();
+        camera.set_projection( Projection::Fisheye(FisheyeMapping::Equisolid) );
+        let equisolid   = camera.get_ray(0.5, 0.75).unwrap();
+
+        // Halfway to the edge: 45 degrees vs 2*asin(0.5*sin(45)) ~ 41.4 degrees
+        let angle = |r: &Ray| utils::radians_to_degrees( r.direction.dot(camera.forward).acos() );
+        assert!( approx_eq!(Float, angle(&equidistant), 45.0,  epsilon = 0.01) );
+        assert!( approx_eq!(Float, angle(&equisolid),   41.41, epsilon = 0.01) );
+    }
+
+    #[test]
+    fn equirectangular()
+    {
+        let mut camera = new_test_camera();
+        camera.set_projection( Projection::Equirectangular );
+
+        assert_eq!( camera.get_ray(0.5,  0.5).unwrap().direction, camera.forward );
+        assert_eq!( camera.get_ray(0.75, 0.5).unwrap().direction, camera.left );
+        assert_eq!( camera.get_ray(0.0,  0.5).unwrap().direction, -camera.forward );
+        assert_eq!( camera.get_ray(0.5,  1.0).unwrap().direction, camera.up );
+    }
+
+    fn new_test_lens_camera(lens: Lens) -> Camera
+    {
+        let mut camera = new_test_camera();
+        camera.set_aperture( 2.0 );
+        camera.set_lens( lens );
+        return camera;
+    }
+
+    #[test]
+    fn moves_along_path_during_shutter()
+    {
+        use super::super::animation::Interpolation;
+
+        let mut path = CameraPath::new(Interpolation::Linear);
+        path.add_keyframe( CameraKeyframe{ time: 0.0, position: Vec3::zero(),               target: Vec3::new(0.0, 0.0, 1.0),  fov: 60.0 } );
+        path.add_keyframe( CameraKeyframe{ time: 1.0, position: Vec3::new(10.0, 0.0, 0.0), target: Vec3::new(10.0, 0.0, 1.0), fov: 60.0 } );
+
+        let mut camera = new_test_camera();
+        camera.set_path( Rc::new(path) );
+
+        // Where the path is at each ray's own time, not where it was when the shutter opened
+        let rays: Vec<Ray> = (0..100).map(|_| camera.get_ray(0.5, 0.5).unwrap()).collect();
+        for ray in &rays
+        {
+            assert!( (ray.origin - Vec3::new(10.0 * ray.time, 0.0, 0.0)).norm() < 0.0001 );
+            assert!( (ray.direction - Vec3::new(0.0, 0.0, 1.0)).norm() < 0.0001 );
+        }
+        assert!( rays.iter().any(|ray| (ray.origin - rays[0].origin).norm() > 1.0) );
+
+        // Between frames it stays at the start of the shutter
+        assert_eq!( camera.origin, Vec3::zero() );
+    }
+
+    #[test]
+    fn sample_towards_inverts_get_ray()
+    {
+        let mut camera = new_test_camera();
+        camera.move_to( Vec3::new(1.0, 2.0, 3.0) );
+        camera.look_at( Vec3::new(4.0, 2.0, 10.0) );
+        camera.set_focus_dist( 3.0 );
+
+        // A pinhole sees whatever get_ray aims at, at any distance
+        for &(u, v) in &[(0.5, 0.5), (0.1, 0.8), (0.9, 0.2)]
+        {
+            let ray = camera.get_ray(u, v).unwrap();
+            let (origin, found_u, found_v) = camera.sample_towards( ray.at(7.0) ).unwrap();
+
+            assert_eq!( origin, camera.origin );
+            assert!( approx_eq!(Float, found_u, u, epsilon = 0.0001) );
+            assert!( approx_eq!(Float, found_v, v, epsilon = 0.0001) );
+        }
+
+        // Through a lens, only what's in focus
+        camera.set_aperture( 0.5 );
+        let ray    = camera.get_ray(0.3, 0.6).unwrap();
+        let target = ray.at( 3.0 / ray.direction.dot(camera.forward) );
+        for _ in 0..10
+        {
+            let (origin, u, v) = camera.sample_towards(target).unwrap();
+            assert!( (origin - camera.origin).norm() <= 0.25 + 1e-4 );
+            assert!( approx_eq!(Float, u, 0.3, epsilon = 0.0001) );
+            assert!( approx_eq!(Float, v, 0.6, epsilon = 0.0001) );
+        }
+
+        assert!( camera.sample_towards( camera.origin - camera.forward ).is_none() );
+    }
+
+    #[test]
+    fn importance_integrates_to_one()
+    {
+        // Over the directions of the whole image, with a 90 by 90 degrees FOV
+        let mut camera = new_test_camera();
+        camera.set_vfov( 90.0 );
+
+        let steps   = 200;
+        let mut sum = 0.0;
+        for i in 0..steps
+        {
+            for j in 0..steps
+            {
+                // Solid angle of a small patch of the image plane, one unit ahead
+                let x   = -1.0 + 2.0 * (i as Float + 0.5) / steps as Float;
+                let y   = -1.0 + 2.0 * (j as Float + 0.5) / steps as Float;
+                let dir = Vec3::new(x, y, 1.0);
+                let area = (2.0 / steps as Float) * (2.0 / steps as Float);
+                sum += camera.importance(dir) * area / dir.norm().powi(3);
+            }
+        }
+        assert!( approx_eq!(Float, sum, 1.0, epsilon = 0.001) );
+    }
+
+    #[test]
+    fn polygonal_aperture()
+    {
+        let camera = new_test_lens_camera( Lens{ blades: 4, blade_rotation: 45.0, ..Lens::default() } );
+
+        // A square aperture rotated 45 degrees is axis aligned, with half side sqrt(0.5)
+        let half_side = (0.5 as Float).sqrt() + 1e-5;
+        for _ in 0..1000
+        {
+            let ray = camera.get_ray(0.5, 0.5).unwrap();
+            assert!( ray.origin.x().abs() <= half_side );
+            assert!( ray.origin.y().abs() <= half_side );
+        }
+    }
+
+    #[test]
+    fn cat_eye_vignetting()
+    {
+        let camera = new_test_lens_camera( Lens{ vignetting: 1.0, ..Lens::default() } );
+
+        let blocked_at = |u: Float, v: Float| (0..1000).filter(|_| camera.sample_ray(u, v).is_none()).count();
+
+        assert_eq!( blocked_at(0.5, 0.5), 0 );
+        assert!( blocked_at(1.0, 1.0) > 0 );
+    }
+
+    #[test]
+    fn chromatic_aberration()
+    {
+        let mut camera = new_test_lens_camera( Lens{ chromatic_aberration: 0.1, ..Lens::default() } );
+        camera.set_aperture( 0.0 );
+
+        let mut directions = [None, None, None];
+        for _ in 0..1000
+        {
+            let (ray, weight) = camera.sample_ray(1.0, 1.0).unwrap();
+            assert!( approx_eq!(Float, weight.r() + weight.g() + weight.b(), 3.0) );
+
+            let channel = if weight.r() > 0.0 { 0 } else if weight.g() > 0.0 { 1 } else { 2 };
+            directions[channel] = Some(ray.direction);
+        }
+
+        let red   = directions[0].unwrap();
+        let green = directions[1].unwrap();
+        let blue  = directions[2].unwrap();
+
+        // Blue lands further from the center than red
+        assert!( blue.dot(camera.forward) < green.dot(camera.forward) );
+        assert!( green.dot(camera.forward) < red.dot(camera.forward) );
+
+        // The center of the image doesn't disperse
+        let (center, _) = camera.sample_ray(0.5, 0.5).unwrap();
+        assert_eq!( center.direction, camera.forward );
+    }
 }
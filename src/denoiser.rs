@@ -0,0 +1,211 @@
+use super::film::Film;
+use super::utils::{ vec3::Vec3,
+                    ppm::ImagePPM,
+                    Float };
+
+// 5x5 B3 spline kernel, separable: outer product of these weights
+const KERNEL: [Float; 5] = [1.0/16.0, 1.0/4.0, 3.0/8.0, 1.0/4.0, 1.0/16.0];
+const ALBEDO_EPSILON: Float = 0.001;
+
+// Edge-avoiding À-Trous wavelet filter (Dammertz et al. 2010).
+// The lighting is filtered with the albedo divided out, so surface detail survives the blur,
+// and neighbours are rejected when their normal, depth or color differ too much.
+pub struct Denoiser
+{
+    pub iterations:   u32,
+    pub sigma_color:  Float,
+    pub sigma_normal: Float,
+    pub sigma_depth:  Float
+}
+
+impl Default for Denoiser
+{
+    fn default() -> Self
+    {
+        Self
+        {
+            iterations:   5,
+            sigma_color:  0.6,
+            sigma_normal: 64.0,
+            sigma_depth:  0.1
+        }
+    }
+}
+
+impl Denoiser
+{
+    // Returns the filtered linear radiance of the film
+    pub fn denoise(&self, i_film: &Film) -> ImagePPM
+    {
+        let w = i_film.width;
+        let h = i_film.height;
+
+        let mut irradiance = ImagePPM::new_filled(w, h, Vec3::zero());
+        for (i, pixel) in irradiance.pixels.iter_mut().enumerate()
+        {
+            *pixel = Self::demodulate(i_film.radiance.pixels[i], i_film.albedo.pixels[i]);
+        }
+
+        let mut sigma_color = self.sigma_color;
+        for iteration in 0..self.iterations
+        {
+            irradiance = self.filter_pass(i_film, &irradiance, 1 << iteration, sigma_color);
+            // Each pass sees a smoother signal, so it can afford being more strict about colors
+            sigma_color *= 0.5;
+        }
+
+        for (i, pixel) in irradiance.pixels.iter_mut().enumerate()
+        {
+            *pixel = Self::remodulate(*pixel, i_film.albedo.pixels[i]);
+        }
+        return irradiance;
+    }
+
+    // PRIVATE
+    fn demodulate(i_radiance: Vec3, i_albedo: Vec3) -> Vec3
+    {
+        Vec3::new(Self::safe_div(i_radiance.r(), i_albedo.r()),
+                  Self::safe_div(i_radiance.g(), i_albedo.g()),
+                  Self::safe_div(i_radiance.b(), i_albedo.b()))
+    }
+
+    fn remodulate(i_irradiance: Vec3, i_albedo: Vec3) -> Vec3
+    {
+        let albedo = Vec3::new(i_albedo.r().max(ALBEDO_EPSILON),
+                               i_albedo.g().max(ALBEDO_EPSILON),
+                               i_albedo.b().max(ALBEDO_EPSILON));
+        return i_irradiance * albedo;
+    }
+
+    fn safe_div(a: Float, b: Float) -> Float { a / b.max(ALBEDO_EPSILON) }
+
+    fn luminance(i_color: Vec3) -> Float
+    {
+        return 0.2126 * i_color.r() + 0.7152 * i_color.g() + 0.0722 * i_color.b();
+    }
+
+    fn filter_pass(&self, i_film: &Film, i_input: &ImagePPM, i_step: i32, i_sigma_color: Float) -> ImagePPM
+    {
+        let w = i_film.width  as i32;
+        let h = i_film.height as i32;
+
+        let mut result = ImagePPM::new_filled(i_film.width, i_film.height, Vec3::zero());
+
+        for y in 0..h {
+            for x in 0..w
+            {
+                let (xu, yu)   = (x as u32, y as u32);
+                let color_p    = i_input.get_pixel(xu, yu);
+                let normal_p   = i_film.normal.get_pixel(xu, yu);
+                let depth_p    = i_film.depth.get_pixel(xu, yu).x();
+                let object_p   = i_film.object_id.get_pixel(xu, yu).x();
+                let lum_p      = Self::luminance(color_p);
+
+                let mut sum        = Vec3::zero();
+                let mut weight_sum = 0.0;
+
+                for (j, ky) in KERNEL.iter().enumerate() {
+                    for (i, kx) in KERNEL.iter().enumerate()
+                    {
+                        let qx = x + (i as i32 - 2) * i_step;
+                        let qy = y + (j as i32 - 2) * i_step;
+                        if qx < 0 || qy < 0 || qx >= w || qy >= h { continue; }
+
+                        let (qxu, qyu) = (qx as u32, qy as u32);
+
+                        // Never mix the skybox with geometry
+                        let object_q = i_film.object_id.get_pixel(qxu, qyu).x();
+                        if (object_p == 0.0) != (object_q == 0.0) { continue; }
+
+                        let color_q  = i_input.get_pixel(qxu, qyu);
+                        let normal_q = i_film.normal.get_pixel(qxu, qyu);
+                        let depth_q  = i_film.depth.get_pixel(qxu, qyu).x();
+
+                        let w_normal = if object_p == 0.0 { 1.0 }
+                                       else { normal_p.dot(normal_q).max(0.0).powf(self.sigma_normal) };
+
+                        let depth_diff = (depth_p - depth_q).abs();
+                        let w_depth    = (-depth_diff / (self.sigma_depth * i_step as Float * depth_p.max(1.0))).exp();
+
+                        let lum_diff = (lum_p - Self::luminance(color_q)).abs();
+                        let w_color  = (-lum_diff / i_sigma_color.max(ALBEDO_EPSILON)).exp();
+
+                        let weight = kx * ky * w_normal * w_depth * w_color;
+
+                        sum        += color_q * weight;
+                        weight_sum += weight;
+                    }
+                }
+
+                let filtered = if weight_sum > 0.0 { sum / weight_sum } else { color_p };
+                result.set_pixel(xu, yu, filtered);
+            }
+        }
+        return result;
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::film::AovSample;
+    use super::super::options::Options;
+    use super::super::ray_tracer::RayTracer;
+    use super::super::scene_generator::Preset;
+
+    fn mse(a: &ImagePPM, b: &ImagePPM) -> Float
+    {
+        let sum: Float = a.pixels.iter()
+                               .zip(b.pixels.iter())
+                               .map(|(pa, pb)| (*pa - *pb).norm2())
+                               .sum();
+        return sum / a.size() as Float;
+    }
+
+    fn render_simple(sample_count: i32) -> Film
+    {
+        let options = Options{ width: 40,
+                               height: 30,
+                               sample_count,
+                               scene: Preset::Simple,
+                               ..Options::default() };
+
+        return RayTracer::new(&options).render();
+    }
+
+    #[test]
+    fn flat_image_is_unchanged()
+    {
+        let mut film = Film::new(8, 8);
+        let mut aovs = AovSample::background();
+        aovs.object_id = 1;
+        aovs.normal    = Vec3::new(0.0, 1.0, 0.0);
+        aovs.albedo    = Vec3::one() * 0.5;
+        aovs.depth     = 1.0;
+
+        for y in 0..8 {
+            for x in 0..8 { film.set_pixel(x, y, Vec3::one() * 0.25, &aovs); }
+        }
+
+        let denoised = Denoiser::default().denoise(&film);
+        assert_eq!( denoised.get_pixel(3, 3), Vec3::one() * 0.25 );
+    }
+
+    #[test]
+    fn reduces_error_against_reference()
+    {
+        // Over several noisy renders, as a single firefly can make any one of them worse
+        let reference = render_simple(256).radiance;
+        let renders   = 8;
+        let gains: Vec<Float> = (0..renders).map(|_|
+        {
+            let noisy = render_simple(4);
+            mse(&noisy.radiance, &reference) - mse(&Denoiser::default().denoise(&noisy), &reference)
+        }).collect();
+
+        let mean     = gains.iter().sum::<Float>() / renders as Float;
+        let variance = gains.iter().map(|gain| (gain - mean) * (gain - mean)).sum::<Float>() / (renders - 1) as Float;
+        assert!( mean > 5.0 * (variance / renders as Float).sqrt(), "Denoising lowered the MSE by {} on average, {:?}", mean, gains );
+    }
+}
@@ -0,0 +1,179 @@
+use super::utils::{ vec3::Vec3,
+                    ppm::ImagePPM,
+                    Float };
+
+// Arbitrary Output Variables gathered along a single camera path.
+// Everything but the lighting split comes from the first hit.
+// IDs start at 1, 0 means the ray escaped to the skybox.
+#[derive(Copy, Clone, Debug)]
+pub struct AovSample
+{
+    pub normal:      Vec3,
+    pub albedo:      Vec3,
+    pub position:    Vec3,
+    pub depth:       Float,
+    pub object_id:   u32,
+    pub material_id: u32,
+    pub direct:      Vec3,
+    pub indirect:    Vec3
+}
+
+impl AovSample
+{
+    pub fn background() -> Self
+    {
+        Self
+        {
+            normal:      Vec3::zero(),
+            albedo:      Vec3::zero(),
+            position:    Vec3::zero(),
+            depth:       0.0,
+            object_id:   0,
+            material_id: 0,
+            direct:      Vec3::zero(),
+            indirect:    Vec3::zero()
+        }
+    }
+
+    // IDs can't be averaged, so the first sample of the pixel keeps them
+    pub fn accumulate(&mut self, other: &Self)
+    {
+        self.normal   += other.normal;
+        self.albedo   += other.albedo;
+        self.position += other.position;
+        self.depth    += other.depth;
+        self.direct   += other.direct;
+        self.indirect += other.indirect;
+    }
+
+    pub fn average(&mut self, sample_count: i32)
+    {
+        let inv_count = 1.0 / sample_count as Float;
+
+        self.normal   *= inv_count;
+        self.albedo   *= inv_count;
+        self.position *= inv_count;
+        self.depth    *= inv_count;
+        self.direct   *= inv_count;
+        self.indirect *= inv_count;
+
+        if self.normal.norm2() > 0.0 { self.normal = self.normal.normalized(); }
+    }
+}
+
+// Linear render buffers, one image per pass
+pub struct Film
+{
+    pub width:       u32,
+    pub height:      u32,
+    pub radiance:    ImagePPM,
+    pub normal:      ImagePPM,
+    pub albedo:      ImagePPM,
+    pub position:    ImagePPM,
+    pub depth:       ImagePPM,
+    pub object_id:   ImagePPM,
+    pub material_id: ImagePPM,
+    pub direct:      ImagePPM,
+    pub indirect:    ImagePPM
+}
+
+impl Film
+{
+    pub fn new(w: u32, h: u32) -> Self
+    {
+        let blank = || ImagePPM::new_filled(w, h, Vec3::zero());
+        Self
+        {
+            width:       w,
+            height:      h,
+            radiance:    blank(),
+            normal:      blank(),
+            albedo:      blank(),
+            position:    blank(),
+            depth:       blank(),
+            object_id:   blank(),
+            material_id: blank(),
+            direct:      blank(),
+            indirect:    blank()
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, i_radiance: Vec3, i_aovs: &AovSample)
+    {
+        self.radiance   .set_pixel(x, y, i_radiance);
+        self.normal     .set_pixel(x, y, i_aovs.normal);
+        self.albedo     .set_pixel(x, y, i_aovs.albedo);
+        self.position   .set_pixel(x, y, i_aovs.position);
+        self.depth      .set_pixel(x, y, Vec3::one() * i_aovs.depth);
+        self.object_id  .set_pixel(x, y, Vec3::one() * i_aovs.object_id   as Float);
+        self.material_id.set_pixel(x, y, Vec3::one() * i_aovs.material_id as Float);
+        self.direct     .set_pixel(x, y, i_aovs.direct);
+        self.indirect   .set_pixel(x, y, i_aovs.indirect);
+    }
+
+    // Display ready beauty pass
+    pub fn to_image(&self) -> ImagePPM
+    {
+        let mut result = ImagePPM::new_filled(self.width, self.height, Vec3::zero());
+        for (dst, src) in result.pixels.iter_mut().zip(self.radiance.pixels.iter())
+        {
+            // Gamma2 correction
+            *dst = src.sqrt();
+        }
+        return result;
+    }
+
+    // Writes every pass as <prefix>_<pass>.pfm
+    pub fn write_aovs(&self, i_prefix: &str)
+    {
+        let passes = [("normal",      &self.normal),
+                      ("albedo",      &self.albedo),
+                      ("position",    &self.position),
+                      ("depth",       &self.depth),
+                      ("object_id",   &self.object_id),
+                      ("material_id", &self.material_id),
+                      ("direct",      &self.direct),
+                      ("indirect",    &self.indirect)];
+
+        for (name, image) in passes.iter()
+        {
+            image.to_pfm_file( &format!("{}_{}.pfm", i_prefix, name) );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn average_keeps_ids_and_normalizes()
+    {
+        let mut sample = AovSample::background();
+        sample.object_id = 3;
+        sample.normal    = Vec3::new(0.0, 1.0, 0.0);
+        sample.depth     = 2.0;
+
+        let mut other = sample;
+        other.object_id = 7;
+        other.normal    = Vec3::new(1.0, 0.0, 0.0);
+        other.depth     = 4.0;
+
+        sample.accumulate(&other);
+        sample.average(2);
+
+        assert_eq!( sample.object_id, 3 );
+        assert_eq!( sample.depth, 3.0 );
+        assert_eq!( sample.normal, Vec3::new(1.0, 1.0, 0.0).normalized() );
+    }
+
+    #[test]
+    fn to_image_applies_gamma()
+    {
+        let mut film = Film::new(1, 1);
+        film.set_pixel(0, 0, Vec3::one() * 0.25, &AovSample::background());
+
+        assert_eq!( film.to_image().get_pixel(0, 0), Vec3::one() * 0.5 );
+    }
+}
@@ -1,21 +1,118 @@
-use super::super::utils::vec3::Vec3;
+use super::super::utils::{ vec3::Vec3, Float, gamma, next_float_up, next_float_down };
 use super::super::ray::Ray;
 use super::super::materials::Material;
 
 #[derive(Clone, Debug)]
 pub struct HitRecord
 {
-    pub front_face: bool,
-    pub distance:   f32,
-    pub position:   Vec3,
-    pub normal:     Vec3,
-    pub p_material: std::rc::Rc<dyn Material>
+    pub front_face:     bool,
+    pub distance:       Float,
+    pub position:       Vec3,
+    pub error:          Vec3,       // Bound on how far the position may be off the surface, per axis
+    pub normal:         Vec3,       // Geometric, always pointing outwards
+    pub shading_normal: Vec3,       // What materials shade with, may be perturbed by normal/bump maps
+    pub uv:             (Float, Float),
+    pub dpdu:           Vec3,       // Change of the position along u, the tangent
+    pub dpdv:           Vec3,       // Change of the position along v, the bitangent
+    pub part:           usize,      // Which object of a set was hit, 0 for single objects
+    pub p_material:     std::rc::Rc<dyn Material>
 }
 
 impl HitRecord
 {
+    pub fn new(i_ray: &Ray,
+               distance: Float,
+               normal: Vec3,
+               uv: (Float, Float),
+               dpdu: Vec3,
+               dpdv: Vec3,
+               p_material: std::rc::Rc<dyn Material>) -> Self
+    {
+        Self{ front_face:     Self::is_front_face(i_ray, normal),
+              distance,
+              position:       i_ray.at(distance),
+              error:          Self::error_along(i_ray, distance),
+              normal,
+              shading_normal: normal,
+              uv,
+              dpdu,
+              dpdv,
+              part:           0,
+              p_material }
+    }
+
+    // Shapes that can tell where they were hit better than the ray can
+    pub fn with_position(mut self, position: Vec3, error: Vec3) -> Self
+    {
+        self.position = position;
+        self.error    = error;
+        self
+    }
+
+    // For flat shapes: the position moved onto the plane normal·p = i_offset. What's left of the error grows with how
+    // far the point is from the world's origin, not with how far the ray came from.
+    pub fn on_plane(self, i_offset: Float) -> Self
+    {
+        let position = self.position - self.normal * (self.normal.dot(self.position) - i_offset);
+        let error    = Vec3::one() * (position.norm() * gamma(9));
+        return self.with_position(position, error);
+    }
+
+    // Rounding error of O + D·t, for a t within a few ulps of where the ray meets a plane
+    fn error_along(i_ray: &Ray, i_distance: Float) -> Vec3
+    {
+        return (i_ray.origin.abs() + (i_ray.direction * i_distance).abs()) * gamma(7);
+    }
+
+    // Where a ray leaving towards i_direction starts: pushed along the geometric normal just past the error in the
+    // position, to the side the ray leaves through, so it can't hit the surface it comes from (PBRT 3.9.5).
+    // Works the same on tiny and huge scenes, unlike a fixed minimum distance.
+    pub fn spawn_origin(&self, i_direction: Vec3) -> Vec3
+    {
+        let normal = if i_direction.dot(self.normal) < 0.0 { -self.normal } else { self.normal };
+        let origin = self.position + normal * normal.abs().dot(self.error);
+
+        // The sum rounds too, one more ulp away from the surface. Also moves exact positions off it.
+        let away = |p: Float, n: Float| if n > 0.0 { next_float_up(p) } else if n < 0.0 { next_float_down(p) } else { p };
+        return Vec3::new(away(origin.x(), normal.x()), away(origin.y(), normal.y()), away(origin.z(), normal.z()));
+    }
+
+    // Reflected or transmitted ray, depending on the side of the surface i_direction points to
+    pub fn spawn_ray(&self, i_direction: Vec3, i_time: Float) -> Ray
+    {
+        return Ray::new(self.spawn_origin(i_direction), i_direction, i_time);
+    }
+
     pub fn is_front_face(i_ray: &Ray, i_outward_norm: Vec3) -> bool
     {
         return i_ray.direction.dot( i_outward_norm ) < 0.0;
     }
-}
\ No newline at end of file
+
+    // Hit from above on a horizontal surface, for testing materials
+    #[cfg(test)]
+    pub fn upwards(i_position: Vec3, p_material: std::rc::Rc<dyn Material>) -> Self
+    {
+        let up  = Vec3::new(0.0, 1.0, 0.0);
+        let ray = Ray::new(i_position + up, -up, 0.0);
+        return Self::new(&ray, 1.0, up, (0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), p_material);
+    }
+
+    // Hits on the transparent parts of a material's mask don't count
+    pub fn is_cut_out(&self) -> bool
+    {
+        return self.p_material.opacity(self) < 0.5;
+    }
+
+    // Whether the shading and the geometric normal agree on which side of the surface i_direction is. Perturbed
+    // shading normals can send reflections under the actual surface, or transmissions over it, leaking light.
+    pub fn is_consistent(&self, i_direction: Vec3) -> bool
+    {
+        return (i_direction.dot(self.shading_normal) > 0.0) == (i_direction.dot(self.normal) > 0.0);
+    }
+
+    // Shading normal on the side the ray came from
+    pub fn facing_normal(&self) -> Vec3
+    {
+        if self.front_face { self.shading_normal } else { -self.shading_normal }
+    }
+}
@@ -1,30 +1,51 @@
 pub mod hit_record;
 pub mod sphere;
+pub mod sphere_set;
+pub mod prism;
+pub mod quad;
+pub mod volume;
 
 use hit_record::HitRecord;
 
 use super::ray::Ray;
-use super::utils::vec3::Vec3;
+use super::utils::{ vec3::Vec3, Float };
+use super::materials::Material;
+
+use std::rc::Rc;
 
 pub trait Hittable
 {
-    fn hit(&self, i_ray: &Ray, i_min_d: f32, i_max_d: f32) -> Option<HitRecord>;
+    fn hit(&self, i_ray: &Ray, i_min_d: Float, i_max_d: Float) -> Option<HitRecord>;
     fn get_normal_at(&self, i_pos: Vec3) -> Vec3;
+
+    // Whether anything is hit at all in the range, all shadow rays need to know
+    fn occluded(&self, i_ray: &Ray, i_min_d: Float, i_max_d: Float) -> bool
+    {
+        self.hit(i_ray, i_min_d, i_max_d).is_some()
+    }
 }
 
 pub enum Intersectionable
 {
     Sphere( sphere::Sphere ),
+    SphereSet( sphere_set::SphereSet ),
+    Prism( prism::Prism ),
+    Quad( quad::Quad ),
+    Volume( volume::Volume ),
     // TODO: Other surfaces
 }
 
 impl Hittable for Intersectionable
 {
-    fn hit(&self, i_ray: &Ray, i_min_d: f32, i_max_d: f32) -> Option<HitRecord>
+    fn hit(&self, i_ray: &Ray, i_min_d: Float, i_max_d: Float) -> Option<HitRecord>
     {
         match self
         {
-            Self::Sphere(s) => s.hit(i_ray, i_min_d, i_max_d),
+            Self::Sphere(s)    => s.hit(i_ray, i_min_d, i_max_d),
+            Self::SphereSet(s) => s.hit(i_ray, i_min_d, i_max_d),
+            Self::Prism(p)     => p.hit(i_ray, i_min_d, i_max_d),
+            Self::Quad(q)      => q.hit(i_ray, i_min_d, i_max_d),
+            Self::Volume(v)    => v.hit(i_ray, i_min_d, i_max_d),
         }
     }
 
@@ -32,7 +53,173 @@ impl Hittable for Intersectionable
     {
         match self
         {
-            Self::Sphere(s) => s.get_normal_at(i_pos),
+            Self::Sphere(s)    => s.get_normal_at(i_pos),
+            Self::SphereSet(s) => s.get_normal_at(i_pos),
+            Self::Prism(p)     => p.get_normal_at(i_pos),
+            Self::Quad(q)      => q.get_normal_at(i_pos),
+            Self::Volume(v)    => v.get_normal_at(i_pos),
+        }
+    }
+
+    fn occluded(&self, i_ray: &Ray, i_min_d: Float, i_max_d: Float) -> bool
+    {
+        match self
+        {
+            Self::Sphere(s)    => s.occluded(i_ray, i_min_d, i_max_d),
+            Self::SphereSet(s) => s.occluded(i_ray, i_min_d, i_max_d),
+            Self::Prism(p)     => p.occluded(i_ray, i_min_d, i_max_d),
+            Self::Quad(q)      => q.occluded(i_ray, i_min_d, i_max_d),
+            Self::Volume(v)    => v.occluded(i_ray, i_min_d, i_max_d),
+        }
+    }
+}
+
+impl Intersectionable
+{
+    // Sets of objects have one per object
+    pub fn materials(&self) -> Vec<&Rc<dyn Material>>
+    {
+        match self
+        {
+            Self::Sphere(s)    => vec![ &s.p_material ],
+            Self::SphereSet(s) => s.spheres().iter().map(|sphere| &sphere.p_material).collect(),
+            Self::Prism(p)     => vec![ &p.p_material ],
+            Self::Quad(q)      => vec![ &q.p_material ],
+            Self::Volume(v)    => vec![ &v.p_material ],
+        }
+    }
+
+    // How many objects it stands for, sets have one per sphere
+    pub fn parts(&self) -> usize
+    {
+        match self
+        {
+            Self::SphereSet(s) => s.spheres().len(),
+            _                  => 1
+        }
+    }
+
+    // Only the shapes that can be area lights know theirs
+    pub fn area(&self) -> Option<Float>
+    {
+        match self
+        {
+            Self::Sphere(s)    => Some(s.area()),
+            Self::Quad(q)      => Some(q.area()),
+            _                  => None
+        }
+    }
+
+    // Uniformly distributed point on the surface, as if hit head-on from outside
+    pub fn sample_surface(&self) -> Option<HitRecord>
+    {
+        match self
+        {
+            Self::Sphere(s)    => Some(s.sample_surface()),
+            Self::Quad(q)      => Some(q.sample_surface()),
+            _                  => None
+        }
+    }
+}
+
+// Returns the closest intersection along the ray together with the index of the object hit
+pub fn closest_hit(i_scene: &[Intersectionable], i_ray: &Ray, i_min_d: Float, i_max_d: Float) -> Option<(usize, HitRecord)>
+{
+    let mut closest: Option<(usize, HitRecord)> = None;
+    let mut max_d = i_max_d;
+
+    for (idx, obj) in i_scene.iter().enumerate()
+    {
+        if let Some(hit) = obj.hit(i_ray, i_min_d, max_d)
+        {
+            max_d   = hit.distance;
+            closest = Some((idx, hit));
         }
     }
-}
\ No newline at end of file
+    return closest;
+}
+
+// Like closest_hit, but stops at the first object in the way
+pub fn any_hit(i_scene: &[Intersectionable], i_ray: &Ray, i_min_d: Float, i_max_d: Float) -> bool
+{
+    return i_scene.iter().any(|obj| obj.occluded(i_ray, i_min_d, i_max_d));
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::materials::{ lambertian::LambertianMat, masked::MaskedMat };
+    use super::super::textures::{ SolidTex, CheckerTex };
+    use super::super::media::{ Medium, grid::DensityGrid };
+
+    // Every kind of object, opaque and with holes
+    fn scene() -> Vec<Intersectionable>
+    {
+        let white: Rc<dyn Material> = Rc::new( LambertianMat{ albedo: Vec3::one() } );
+        let holes: Rc<dyn Material> = Rc::new( MaskedMat::new(white.clone(), CheckerTex::shared(SolidTex::scalar(1.0), SolidTex::scalar(0.0), 0.125)) );
+        let grid = Rc::new( DensityGrid::new([1, 1, 1], vec![1.0]).unwrap().placed(Vec3::new(-2.0, -2.0, 1.0), Vec3::new(-1.0, -1.0, 2.0)) );
+
+        vec![ Intersectionable::Sphere( sphere::Sphere::new(0.5, Vec3::new(1.0, 0.0, 0.0), white.clone()) ),
+              Intersectionable::Sphere( sphere::Sphere::new(0.7, Vec3::new(-1.0, 1.0, 0.0), holes.clone()) ),
+              Intersectionable::Prism( prism::Prism::new(Vec3::new(0.0, -1.0, -1.0), 1.0, 0.5, white.clone()) ),
+              Intersectionable::Prism( prism::Prism::new(Vec3::new(0.0, 1.0, 1.0), 1.0, 0.5, holes.clone()) ),
+              Intersectionable::Quad( quad::Quad::new(Vec3::new(1.0, 1.0, -1.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), white) ),
+              Intersectionable::Quad( quad::Quad::new(Vec3::new(-2.0, -1.0, -1.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), holes) ),
+              Intersectionable::Volume( volume::Volume::new(grid, Medium::absorbing(Vec3::one())) ) ]
+    }
+
+    #[test]
+    fn occluded_agrees_with_hit()
+    {
+        let scene = scene();
+        for _ in 0..5000
+        {
+            // From all around, and from inside the objects
+            let ray   = Ray::new(Vec3::rand_unit() * 3.0 * rand::random::<Float>(), Vec3::rand_unit(), 0.0);
+            let max_d = rand::random::<Float>() * 5.0;
+
+            for obj in &scene
+            {
+                assert_eq!( obj.occluded(&ray, 0.001, max_d), obj.hit(&ray, 0.001, max_d).is_some(), "{:?}", ray );
+            }
+            assert_eq!( any_hit(&scene, &ray, 0.001, max_d), closest_hit(&scene, &ray, 0.001, max_d).is_some() );
+        }
+    }
+
+    // The rand scene's ground and a few objects on it, seen from far away, at scales where a fixed minimum distance
+    // like 0.001 either lets rays hit the surface they leave from again (acne) or skips whole objects
+    #[test]
+    fn spawned_rays_leave_the_surface()
+    {
+        let white: Rc<dyn Material> = Rc::new( LambertianMat{ albedo: Vec3::one() } );
+
+        for scale in [1e-4, 1.0, 1e4]
+        {
+            let scene = vec![ Intersectionable::Sphere( sphere::Sphere::new(1000.0 * scale, Vec3::new(0.0, -1000.0, 0.0) * scale, white.clone()) ),
+                              Intersectionable::Sphere( sphere::Sphere::new(0.5 * scale, Vec3::new(1.0, 0.5, 0.0) * scale, white.clone()) ),
+                              Intersectionable::Prism( prism::Prism::new(Vec3::new(-1.0, 0.5, 0.0) * scale, 1.0 * scale, 1.0 * scale, white.clone()) ),
+                              Intersectionable::Quad( quad::Quad::new(Vec3::new(0.0, 0.2, 1.0) * scale, Vec3::new(1.0, 0.0, 0.0) * scale, Vec3::new(0.0, 1.0, 0.0) * scale, white.clone()) ) ];
+            let eye   = Vec3::new(7.5, 2.0, -3.0) * scale;
+
+            for _ in 0..5000
+            {
+                let ray        = Ray::new(eye, Vec3::rand(-2.0, 2.0) * scale - eye, 0.0);
+                let (idx, hit) = match closest_hit(&scene, &ray, 0.0, Float::INFINITY) { Some(closest) => closest, None => continue };
+                let outside    = if hit.front_face { hit.normal } else { -hit.normal };
+
+                // Every object is convex, nothing leaving on the side the ray came from can hit it again
+                let reflected = hit.spawn_ray(outside + Vec3::rand_unit(), 0.0);
+                assert!( scene[idx].hit(&reflected, 0.0, Float::INFINITY).is_none(), "{} {:?}", scale, reflected );
+
+                // Going into a sphere, the other side is about a diameter away
+                if let Intersectionable::Sphere(sphere) = &scene[idx]
+                {
+                    let transmitted = hit.spawn_ray(-outside + Vec3::rand_unit() * 0.5, 0.0);
+                    let exit        = sphere.hit(&transmitted, 0.0, Float::INFINITY).map_or(0.0, |exit| exit.distance);
+                    assert!( exit > sphere.radius, "{} {:?}", scale, transmitted );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use super::super::utils::{ vec3::Vec3, Float };
+use super::super::materials::Material;
+use super::super::ray::Ray;
+use super::hit_record::HitRecord;
+use super::Hittable;
+
+use std::rc::Rc;
+
+// Plane as n·p = d, with n pointing outwards
+#[derive(Copy, Clone, Debug)]
+struct Plane
+{
+    normal: Vec3,
+    offset: Float
+}
+
+// Triangular prism: an equilateral triangle on the XY plane, extruded along Z.
+// Intersected as the volume inside all of its faces' planes.
+#[derive(Clone, Debug)]
+pub struct Prism
+{
+    faces: [Plane; 5],
+    pub p_material: Rc<dyn Material>
+}
+
+impl Prism
+{
+    // i_side: Length of the triangle's sides
+    // i_depth: Length of the extrusion
+    // The apex points up (+Y)
+    pub fn new(i_center: Vec3, i_side: Float, i_depth: Float, p_material: Rc<dyn Material>) -> Self
+    {
+        let inradius = i_side / (2.0 * (3.0 as Float).sqrt());
+
+        let mut faces = [Plane{ normal: Vec3::zero(), offset: 0.0 }; 5];
+        // Sides, their normals are 120 degrees apart starting straight down
+        for (i, face) in faces.iter_mut().take(3).enumerate()
+        {
+            let angle  = -super::super::utils::PI * 0.5 + super::super::utils::TAU * i as Float / 3.0;
+            let normal = Vec3::new(angle.cos(), angle.sin(), 0.0);
+            *face = Plane{ normal, offset: normal.dot(i_center) + inradius };
+        }
+        // Caps
+        let front = Vec3::new(0.0, 0.0, 1.0);
+        faces[3]  = Plane{ normal:  front, offset:  front.dot(i_center) + i_depth * 0.5 };
+        faces[4]  = Plane{ normal: -front, offset: -front.dot(i_center) + i_depth * 0.5 };
+
+        Self{ faces, p_material }
+    }
+
+    // Distances along the ray to where it enters and leaves the prism, and the faces there
+    fn entry_exit(&self, i_ray: &Ray) -> Option<[(Float, Plane); 2]>
+    {
+        let mut t_enter = Float::NEG_INFINITY;
+        let mut t_exit  = Float::INFINITY;
+        let mut f_enter = self.faces[0];
+        let mut f_exit  = self.faces[0];
+
+        for face in &self.faces
+        {
+            let denom = face.normal.dot(i_ray.direction);
+            let dist  = face.offset - face.normal.dot(i_ray.origin);
+
+            if denom == 0.0
+            {
+                // Parallel and outside this face
+                if dist < 0.0 { return None; }
+                continue;
+            }
+
+            let t = dist / denom;
+            if denom < 0.0 { if t > t_enter { t_enter = t; f_enter = *face; } }
+            else           { if t < t_exit  { t_exit  = t; f_exit  = *face; } }
+        }
+
+        if t_enter > t_exit { return None; }
+        return Some([(t_enter, f_enter), (t_exit, f_exit)]);
+    }
+
+    fn make_record(&self, i_ray: &Ray, distance: Float, i_face: Plane) -> HitRecord
+    {
+        let (tangent, bitangent) = super::super::utils::orthonormal_basis(i_face.normal);
+        let position             = i_ray.at(distance);
+        // Planar projection onto the face, in world units
+        let uv                   = (position.dot(tangent), position.dot(bitangent));
+
+        return HitRecord::new(i_ray, distance, i_face.normal, uv, tangent, bitangent, self.p_material.clone()).on_plane(i_face.offset);
+    }
+}
+
+impl Hittable for Prism
+{
+    fn get_normal_at(&self, i_pos: Vec3) -> Vec3
+    {
+        // The face the point is closest to
+        let mut closest = self.faces[0];
+        for face in &self.faces[1..]
+        {
+            if (face.normal.dot(i_pos) - face.offset).abs() < (closest.normal.dot(i_pos) - closest.offset).abs()
+            {
+                closest = *face;
+            }
+        }
+        return closest.normal;
+    }
+
+    fn hit(&self, i_ray: &Ray, i_min_d: Float, i_max_d: Float) -> Option<HitRecord>
+    {
+        // Through the cut out parts of the front to the back
+        for (t, face) in self.entry_exit(i_ray)?
+        {
+            if t < i_min_d || t > i_max_d { continue; }
+
+            let record = self.make_record(i_ray, t, face);
+            if !record.is_cut_out() { return Some(record); }
+        }
+
+        return None;
+    }
+
+    fn occluded(&self, i_ray: &Ray, i_min_d: Float, i_max_d: Float) -> bool
+    {
+        let faces = match self.entry_exit(i_ray) { Some(faces) => faces, None => return false };
+
+        return faces.iter().any(|(t, face)| *t >= i_min_d && *t <= i_max_d &&
+                                            (!self.p_material.has_cutouts() || !self.make_record(i_ray, *t, *face).is_cut_out()));
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::super::materials::dielectric::{ DielectricMat, Dispersion };
+    use float_cmp::approx_eq;
+
+    fn glass_prism() -> Prism
+    {
+        let material = Rc::new( DielectricMat::with_dispersion(Dispersion::sf11(), Vec3::one()) );
+        Prism::new(Vec3::zero(), 2.0, 2.0, material)
+    }
+
+    #[test]
+    fn hit_from_outside_and_inside()
+    {
+        let prism = glass_prism();
+        let ray   = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+
+        let hit = prism.hit(&ray, 0.001, 100.0).unwrap();
+        assert!( approx_eq!(Float, hit.distance, 4.0, epsilon = 0.0001) );
+        assert_eq!( hit.normal, Vec3::new(0.0, 0.0, -1.0) );
+        assert!( hit.front_face );
+
+        let inside = Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let hit    = prism.hit(&inside, 0.001, 100.0).unwrap();
+        assert!( approx_eq!(Float, hit.distance, 1.0, epsilon = 0.0001) );
+        assert!( !hit.front_face );
+
+        let miss = Ray::new(Vec3::new(0.0, 5.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        assert!( prism.hit(&miss, 0.001, 100.0).is_none() );
+    }
+
+    // Follows a ray through the prism's own material, refracting in and out. Scatter reflects at random now and then,
+    // those are tried again, up to a point. It enters through the middle of the left face close to the angle of minimum
+    // deviation.
+    fn exit_direction(prism: &Prism, wavelength: Float) -> Vec3
+    {
+        let dir     = Vec3::new(0.819, 0.574, 0.0);
+        let mut ray = Ray::new(Vec3::new(-0.5, 0.289, 0.0) - dir * 3.0, dir, 0.0);
+        ray.wavelength = wavelength;
+
+        for _ in 0..2
+        {
+            let hit = prism.hit(&ray, 0.001, 100.0).unwrap();
+            let scattered = (0..1000).map(|_| hit.p_material.scatter(&ray, &hit, &mut Vec3::zero()))
+                                     .find(|scattered| scattered.direction.dot(hit.normal) * ray.direction.dot(hit.normal) > 0.0)
+                                     .expect("The glass never let the ray through");
+
+            ray = Ray::new(scattered.origin, scattered.direction, 0.0);
+            ray.wavelength = wavelength;
+        }
+        return ray.direction;
+    }
+
+    #[test]
+    fn disperses_light()
+    {
+        let prism = glass_prism();
+        let blue  = exit_direction(&prism, 450.0);
+        let red   = exit_direction(&prism, 650.0);
+
+        // Both are bent down, towards the prism's base, blue more so
+        assert!( red.y() < 0.0 );
+        assert!( blue.y() < red.y() );
+        assert!( blue.dot(red) < 0.9999 );
+    }
+}
@@ -0,0 +1,152 @@
+use super::super::utils::{ vec3::Vec3, Float, gamma };
+use super::super::materials::Material;
+use super::super::ray::Ray;
+use super::hit_record::HitRecord;
+use super::Hittable;
+
+use std::rc::Rc;
+
+// Parallelogram spanned by two edges from a corner, with UVs going from 0 to 1 along each edge
+#[derive(Clone, Debug)]
+pub struct Quad
+{
+    corner: Vec3,
+    u:      Vec3,
+    v:      Vec3,
+    normal: Vec3,
+    offset: Float,  // Plane as normal·p = offset
+    w:      Vec3, // Turns points on the plane into UVs
+    pub p_material: Rc<dyn Material>
+}
+
+impl Quad
+{
+    // The normal follows the right hand rule from u to v
+    pub fn new(corner: Vec3, u: Vec3, v: Vec3, p_material: Rc<dyn Material>) -> Self
+    {
+        let n      = u.cross(v);
+        let normal = n.normalized();
+
+        Self{ corner, u, v, normal, offset: normal.dot(corner), w: n / n.dot(n), p_material }
+    }
+
+    // Distance along the ray and UVs where it goes through the parallelogram
+    fn intersect(&self, i_ray: &Ray, i_min_d: Float, i_max_d: Float) -> Option<(Float, (Float, Float))>
+    {
+        let denom = self.normal.dot(i_ray.direction);
+        if denom.abs() < 1e-8 { return None; } // Parallel
+
+        let distance = (self.offset - self.normal.dot(i_ray.origin)) / denom;
+        if distance < i_min_d || distance > i_max_d { return None; }
+
+        let planar = i_ray.at(distance) - self.corner;
+        let alpha  = self.w.dot(planar.cross(self.v));
+        let beta   = self.w.dot(self.u.cross(planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) { return None; }
+
+        return Some((distance, (alpha, beta)));
+    }
+
+    // Point at the UVs, which unlike the ray's distance doesn't depend on how far away the ray came from
+    fn point_at(&self, i_uv: (Float, Float)) -> (Vec3, Vec3)
+    {
+        let (along_u, along_v) = (self.u * i_uv.0, self.v * i_uv.1);
+        return (self.corner + along_u + along_v, (self.corner.abs() + along_u.abs() + along_v.abs()) * gamma(7));
+    }
+
+    pub fn area(&self) -> Float { self.u.cross(self.v).norm() }
+
+    // Uniformly distributed point, as if hit head-on from the front
+    pub fn sample_surface(&self) -> HitRecord
+    {
+        let uv                = (rand::random::<Float>(), rand::random::<Float>());
+        let (position, error) = self.point_at(uv);
+        let ray               = Ray::new(position + self.normal, -self.normal, 0.0);
+        return HitRecord::new(&ray, 1.0, self.normal, uv, self.u, self.v, self.p_material.clone()).with_position(position, error);
+    }
+}
+
+impl Hittable for Quad
+{
+    fn get_normal_at(&self, _i_pos: Vec3) -> Vec3 { self.normal }
+
+    fn hit(&self, i_ray: &Ray, i_min_d: Float, i_max_d: Float) -> Option<HitRecord>
+    {
+        let (distance, uv)    = self.intersect(i_ray, i_min_d, i_max_d)?;
+        let (position, error) = self.point_at(uv);
+
+        let record = HitRecord::new(i_ray, distance, self.normal, uv, self.u, self.v, self.p_material.clone()).with_position(position, error);
+        if record.is_cut_out() { return None; }
+
+        return Some(record);
+    }
+
+    fn occluded(&self, i_ray: &Ray, i_min_d: Float, i_max_d: Float) -> bool
+    {
+        if !self.p_material.has_cutouts() { return self.intersect(i_ray, i_min_d, i_max_d).is_some(); }
+        return self.hit(i_ray, i_min_d, i_max_d).is_some();
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::{ closest_hit, Intersectionable, sphere::Sphere };
+    use super::super::super::materials::{ lambertian::LambertianMat, masked::MaskedMat };
+    use super::super::super::textures::Texture;
+    use float_cmp::approx_eq;
+
+    // Opaque on the left half of the UV square, a fence with a single plank
+    #[derive(Debug)]
+    struct PlankTex;
+
+    impl Texture for PlankTex
+    {
+        fn value(&self, i_uv: (Float, Float), _i_position: Vec3) -> Vec3
+        {
+            if i_uv.0 < 0.5 { Vec3::one() } else { Vec3::zero() }
+        }
+    }
+
+    fn white() -> Rc<dyn Material> { Rc::new( LambertianMat{ albedo: Vec3::one() } ) }
+
+    // 2x2 on the XY plane, facing +Z
+    fn quad(material: Rc<dyn Material>) -> Quad
+    {
+        Quad::new(Vec3::new(-1.0, -1.0, 0.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0), material)
+    }
+
+    fn ray_at(x: Float, y: Float) -> Ray { Ray::new(Vec3::new(x, y, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0) }
+
+    #[test]
+    fn hit()
+    {
+        let quad = quad(white());
+
+        let hit = quad.hit(&ray_at(0.5, -0.5), 0.001, 100.0).unwrap();
+        assert!( approx_eq!(Float, hit.distance, 5.0) );
+        assert_eq!( hit.normal, Vec3::new(0.0, 0.0, 1.0) );
+        assert!( approx_eq!(Float, hit.uv.0, 0.75) && approx_eq!(Float, hit.uv.1, 0.25) );
+        assert!( hit.front_face );
+
+        assert!( quad.hit(&ray_at(1.5, 0.0), 0.001, 100.0).is_none() );
+        assert!( quad.hit(&Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(1.0, 0.0, 0.0), 0.0), 0.001, 100.0).is_none() );
+    }
+
+    #[test]
+    fn cutout()
+    {
+        let fence = quad( Rc::new( MaskedMat::new(white(), Rc::new(PlankTex)) ) );
+
+        assert!( fence.hit(&ray_at(-0.5, 0.0), 0.001, 100.0).is_some() );
+        assert!( fence.hit(&ray_at( 0.5, 0.0), 0.001, 100.0).is_none() );
+
+        // What's behind shows through the holes
+        let scene = vec![ Intersectionable::Quad(fence),
+                          Intersectionable::Sphere( Sphere::new(1.0, Vec3::new(0.0, 0.0, -3.0), white()) ) ];
+
+        assert_eq!( closest_hit(&scene, &ray_at(-0.5, 0.0), 0.001, 100.0).unwrap().0, 0 );
+        assert_eq!( closest_hit(&scene, &ray_at( 0.5, 0.0), 0.001, 100.0).unwrap().0, 1 );
+    }
+}
@@ -1,4 +1,4 @@
-use super::super::utils::vec3::Vec3;
+use super::super::utils::{ vec3::Vec3, Float, gamma };
 use super::super::materials::Material;
 use super::super::ray::Ray;
 use super::hit_record::HitRecord;
@@ -9,26 +9,58 @@ use std::rc::Rc; // It's like C++'s shared_ptr
 #[derive(Clone, Debug)]
 pub struct Sphere
 {
-    pub radius: f32,
+    pub radius: Float,
     pub center: Vec3,
     pub p_material: Rc<dyn Material>
 }
 
 impl Sphere
 {
-    pub fn new(radius: f32, center: Vec3, p_material: Rc<dyn Material>) -> Self {
+    pub fn new(radius: Float, center: Vec3, p_material: Rc<dyn Material>) -> Self {
         Self { radius, center, p_material }
     }
-}
 
-impl Hittable for Sphere
-{
-    fn get_normal_at(&self, i_pos: Vec3) -> Vec3
+    // u: Longitude, starting at -X and going around +Y
+    // v: Latitude, from the bottom (-Y) to the top (+Y)
+    fn uv_at(i_normal: Vec3) -> (Float, Float)
     {
-        return (i_pos - self.center) / self.radius;
+        let theta = (-i_normal.y()).clamp(-1.0, 1.0).acos();
+        let phi   = (-i_normal.z()).atan2(i_normal.x()) + super::super::utils::PI;
+
+        return (phi / super::super::utils::TAU, theta / super::super::utils::PI);
     }
 
-    fn hit(&self, i_ray: &Ray, i_min_d: f32, i_max_d: f32) -> Option<HitRecord>
+    // Derivatives of the position over the UVs above
+    fn tangents_at(&self, i_normal: Vec3) -> (Vec3, Vec3)
+    {
+        let (x, y, z) = (i_normal.x(), i_normal.y(), i_normal.z());
+        let sin_theta = (x * x + z * z).sqrt();
+
+        // Degenerate at the poles, any frame will do
+        if sin_theta < 1e-6 { return super::super::utils::orthonormal_basis(i_normal); }
+
+        let dpdu = Vec3::new(z, 0.0, -x) * (super::super::utils::TAU * self.radius);
+        let dpdv = Vec3::new(-y * x / sin_theta, sin_theta, -y * z / sin_theta) * (super::super::utils::PI * self.radius);
+        return (dpdu, dpdv);
+    }
+
+    fn make_record(&self, i_ray: &Ray, distance: Float) -> HitRecord
+    {
+        let normal       = self.get_normal_at(i_ray.at(distance));
+        let (dpdu, dpdv) = self.tangents_at(normal);
+
+        // The distance can be off by a lot far away from the ray's origin, the point pulled back onto the sphere isn't
+        let offset   = i_ray.at(distance) - self.center;
+        let offset   = offset * (self.radius / offset.norm());
+        let position = self.center + offset;
+        let error    = offset.abs() * gamma(6) + self.center.abs() * gamma(1);
+
+        return HitRecord::new(i_ray, distance, normal, Self::uv_at(normal), dpdu, dpdv, self.p_material.clone())
+                   .with_position(position, error);
+    }
+
+    // Distances along the ray to both sides of the sphere, nearest first
+    fn roots(&self, i_ray: &Ray) -> Option<(Float, Float)>
     {
         let oc = i_ray.origin - self.center;
 
@@ -37,38 +69,139 @@ impl Hittable for Sphere
         // (O+Dt-c)(O+Dt-C) = r^2
         // D·Dt^2 + 2D·(O-C)t + (O-C)·(O-C) - r^2 = 0
 
-        let a = i_ray.direction.dot( i_ray.direction );
-        let b = i_ray.direction.dot(oc) * 2.0;
-        let c = oc.dot(oc) - self.radius*self.radius;
+        let a      = i_ray.direction.dot( i_ray.direction );
+        let half_b = i_ray.direction.dot(oc);
+        let c      = oc.dot(oc) - self.radius*self.radius;
 
-        let discriminant = b*b - 4.0*a*c;
+        // half_b^2 - ac = a·r^2 - |D×(O-C)|^2, which unlike the former doesn't cancel out for small or far away spheres
+        let discriminant = a * self.radius*self.radius - i_ray.direction.cross(oc).norm2();
 
-        if discriminant < 0.0 { return None; }
+        return super::super::utils::quadratic_roots(a, half_b, c, discriminant);
+    }
 
-        let mut distance   = (-b - discriminant.sqrt()) / (2.0 * a) ;
+    pub fn area(&self) -> Float { 2.0 * super::super::utils::TAU * self.radius * self.radius }
 
-        if distance <= i_max_d && distance >= i_min_d
-        {
-            let position   = i_ray.at(distance);
-            let normal     = self.get_normal_at(position);
-            let front_face = HitRecord::is_front_face(i_ray, normal);
-            let p_material = self.p_material.clone();
+    // Uniformly distributed point, as if hit head-on from outside
+    pub fn sample_surface(&self) -> HitRecord
+    {
+        let normal = Vec3::rand_unit();
+        let ray    = Ray::new(self.center + normal * (self.radius + 1.0), -normal, 0.0);
+        return self.make_record(&ray, 1.0);
+    }
+}
 
-            return Some(HitRecord { distance, position, normal, front_face, p_material });
-        }
+impl Hittable for Sphere
+{
+    fn get_normal_at(&self, i_pos: Vec3) -> Vec3
+    {
+        return (i_pos - self.center) / self.radius;
+    }
 
-        distance = (-b - discriminant.sqrt()) / (2.0 * a) ;
+    fn hit(&self, i_ray: &Ray, i_min_d: Float, i_max_d: Float) -> Option<HitRecord>
+    {
+        let (near, far) = self.roots(i_ray)?;
 
-        if distance <= i_max_d && distance >= i_min_d
+        for distance in [near, far]
         {
-            let position   = i_ray.at(distance);
-            let normal     = self.get_normal_at(position);
-            let front_face = HitRecord::is_front_face(i_ray, normal);
-            let p_material = self.p_material.clone();
-
-            return Some(HitRecord { distance, position, normal, front_face, p_material });
+            if distance <= i_max_d && distance >= i_min_d
+            {
+                let record = self.make_record(i_ray, distance);
+                if !record.is_cut_out() { return Some(record); }
+            }
         }
 
         return None
     }
-}
\ No newline at end of file
+
+    fn occluded(&self, i_ray: &Ray, i_min_d: Float, i_max_d: Float) -> bool
+    {
+        let (near, far) = match self.roots(i_ray) { Some(roots) => roots, None => return false };
+
+        return [near, far].iter().any(|distance| *distance <= i_max_d && *distance >= i_min_d &&
+                                                 (!self.p_material.has_cutouts() || !self.make_record(i_ray, *distance).is_cut_out()));
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::super::materials::lambertian::LambertianMat;
+
+    #[test]
+    fn tangent_frame()
+    {
+        let sphere = Sphere::new(2.0, Vec3::new(1.0, 2.0, 3.0), Rc::new( LambertianMat{ albedo: Vec3::one() } ));
+
+        for direction in &[Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.3, -0.5, 0.8), Vec3::new(0.0, 1.0, 0.0)]
+        {
+            let ray = Ray::new(sphere.center + *direction * 10.0, -*direction, 0.0);
+            let hit = sphere.hit(&ray, 0.001, 100.0).unwrap();
+
+            // Right handed around the outward normal
+            let normal = hit.dpdu.cross(hit.dpdv).normalized();
+            assert!( (normal - hit.normal).norm() < 1e-4 );
+
+            // Matches the UVs of a nearby point
+            let du    = 1e-3;
+            let moved = sphere.center + (hit.position + hit.dpdu * du - sphere.center).normalized() * sphere.radius;
+            let uv    = Sphere::uv_at(sphere.get_normal_at(moved));
+            if hit.normal.y().abs() < 0.99 { assert!( ((uv.0 - hit.uv.0) / du - 1.0).abs() < 0.01 ); }
+        }
+    }
+
+    fn unit_sphere() -> Sphere { Sphere::new(1.0, Vec3::zero(), Rc::new( LambertianMat{ albedo: Vec3::one() } )) }
+
+    #[test]
+    fn hit_from_outside()
+    {
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let hit = unit_sphere().hit(&ray, 0.001, 100.0).unwrap();
+
+        assert!( (hit.distance - 4.0).abs() < 1e-5 );
+        assert!( hit.front_face );
+        // Only the far side is left past the near one
+        assert!( (unit_sphere().hit(&ray, 4.5, 100.0).unwrap().distance - 6.0).abs() < 1e-5 );
+    }
+
+    #[test]
+    fn tangent()
+    {
+        let ray = Ray::new(Vec3::new(0.0, 1.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let hit = unit_sphere().hit(&ray, 0.001, 100.0).unwrap();
+
+        assert!( (hit.distance - 5.0).abs() < 1e-5 );
+        assert!( unit_sphere().hit(&Ray::new(Vec3::new(0.0, 1.001, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0), 0.001, 100.0).is_none() );
+    }
+
+    #[test]
+    fn behind_origin()
+    {
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+
+        assert!( unit_sphere().hit(&ray, 0.001, 100.0).is_none() );
+        assert!( !unit_sphere().occluded(&ray, 0.001, 100.0) );
+    }
+
+    // Far away and small, where the textbook formula loses the hit in rounding
+    #[test]
+    fn far_away()
+    {
+        let sphere = Sphere::new(0.01, Vec3::new(0.0, 0.0, 2000.0), Rc::new( LambertianMat{ albedo: Vec3::one() } ));
+        let ray    = Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let hit    = sphere.hit(&ray, 0.001, 1e4).unwrap();
+
+        assert!( (hit.distance - 1999.99).abs() < 1e-2 );
+    }
+
+    #[test]
+    fn hit_from_inside()
+    {
+        let sphere = Sphere::new(2.0, Vec3::new(1.0, 2.0, 3.0), Rc::new( LambertianMat{ albedo: Vec3::one() } ));
+        let ray    = Ray::new(sphere.center, Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let hit    = sphere.hit(&ray, 0.001, 100.0).unwrap();
+
+        assert!( (hit.distance - 2.0).abs() < 1e-5 );
+        assert!( !hit.front_face );
+    }
+}
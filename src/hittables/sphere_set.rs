@@ -0,0 +1,292 @@
+use super::super::utils::{ vec3::Vec3, Float };
+use super::super::ray::Ray;
+use super::super::utils::quadratic_roots;
+use super::hit_record::HitRecord;
+use super::sphere::Sphere;
+use super::Hittable;
+
+// Spheres intersected at once, one per SIMD lane
+const LANES: usize = 4;
+
+// Structure of arrays holding LANES spheres. Unused lanes have a negative squared radius, which no ray hits.
+#[derive(Copy, Clone, Debug)]
+struct Lanes
+{
+    center_x: [Float; LANES],
+    center_y: [Float; LANES],
+    center_z: [Float; LANES],
+    radius2:  [Float; LANES]
+}
+
+// Many spheres, e.g. the small ones scattered around the rand scene. They're tested against a ray LANES at a time
+// and only the closest one makes a HitRecord, which matters when there are hundreds of them.
+#[derive(Clone, Debug)]
+pub struct SphereSet
+{
+    lanes:   Vec<Lanes>,
+    spheres: Vec<Sphere>, // For the materials and the records, in lane order
+    cutouts: bool         // Some material has holes, the SIMD path can't see those
+}
+
+impl SphereSet
+{
+    pub fn new(spheres: Vec<Sphere>) -> Self
+    {
+        let lanes = spheres.chunks(LANES).map(|chunk|
+        {
+            let mut lanes = Lanes{ center_x: [0.0; LANES], center_y: [0.0; LANES], center_z: [0.0; LANES], radius2: [-1.0; LANES] };
+            for (i, sphere) in chunk.iter().enumerate()
+            {
+                lanes.center_x[i] = sphere.center.x();
+                lanes.center_y[i] = sphere.center.y();
+                lanes.center_z[i] = sphere.center.z();
+                lanes.radius2[i]  = sphere.radius * sphere.radius;
+            }
+            lanes
+        }).collect();
+
+        let cutouts = spheres.iter().any(|sphere| sphere.p_material.has_cutouts());
+        Self{ lanes, spheres, cutouts }
+    }
+
+    pub fn spheres(&self) -> &[Sphere] { &self.spheres }
+
+    // Index of the closest sphere the ray hits within the range, and the distance to it
+    fn nearest(&self, i_ray: &Ray, i_min_d: Float, i_max_d: Float) -> Option<(usize, Float)>
+    {
+        let mut result: Option<(usize, Float)> = None;
+        let mut max_d = i_max_d;
+
+        for (chunk, lanes) in self.lanes.iter().enumerate()
+        {
+            let distances = lane_distances(lanes, i_ray, i_min_d, max_d);
+            for (i, distance) in distances.iter().enumerate()
+            {
+                // Lanes that missed are infinite, ties keep the sphere found first
+                if distance.is_finite() && (result.is_none() || *distance < max_d)
+                {
+                    max_d  = *distance;
+                    result = Some((chunk * LANES + i, *distance));
+                }
+            }
+        }
+        return result;
+    }
+}
+
+impl Hittable for SphereSet
+{
+    // Normal of the sphere whose surface is closest to the point
+    fn get_normal_at(&self, i_pos: Vec3) -> Vec3
+    {
+        let closest = self.spheres.iter().min_by(|a, b|
+        {
+            ((i_pos - a.center).norm() - a.radius).abs().total_cmp(&((i_pos - b.center).norm() - b.radius).abs())
+        });
+        return closest.map_or(Vec3::zero(), |sphere| sphere.get_normal_at(i_pos));
+    }
+
+    fn hit(&self, i_ray: &Ray, i_min_d: Float, i_max_d: Float) -> Option<HitRecord>
+    {
+        if !self.cutouts
+        {
+            // The scalar test can disagree on grazing rays, then it's the one that decides
+            let (idx, _) = self.nearest(i_ray, i_min_d, i_max_d)?;
+            if let Some(record) = self.spheres[idx].hit(i_ray, i_min_d, i_max_d) { return Some(HitRecord{ part: idx, ..record }); }
+        }
+
+        // One by one, skipping the holes
+        let mut closest: Option<HitRecord> = None;
+        let mut max_d = i_max_d;
+        for (idx, sphere) in self.spheres.iter().enumerate()
+        {
+            if let Some(record) = sphere.hit(i_ray, i_min_d, max_d)
+            {
+                max_d   = record.distance;
+                closest = Some(HitRecord{ part: idx, ..record });
+            }
+        }
+        return closest;
+    }
+
+    fn occluded(&self, i_ray: &Ray, i_min_d: Float, i_max_d: Float) -> bool
+    {
+        if self.cutouts { return self.spheres.iter().any(|sphere| sphere.occluded(i_ray, i_min_d, i_max_d)); }
+        return self.lanes.iter().any(|lanes| lane_distances(lanes, i_ray, i_min_d, i_max_d).iter().any(|d| d.is_finite()));
+    }
+}
+
+// Distance to where the ray first meets each sphere within [i_min_d, i_max_d], infinity where it doesn't.
+// Same as Sphere::hit, only for every lane at once. Four doubles don't fit in SSE registers, so only for f32.
+#[cfg(all(target_arch = "x86_64", not(feature = "f64")))]
+fn lane_distances(i_lanes: &Lanes, i_ray: &Ray, i_min_d: Float, i_max_d: Float) -> [Float; LANES]
+{
+    use std::arch::x86_64::*;
+
+    let mut result = [0.0; LANES];
+
+    // SSE2 is part of x86_64, so it's always there. The loads and the store stay within the arrays.
+    unsafe
+    {
+        let select = |mask: __m128, a: __m128, b: __m128| _mm_or_ps(_mm_and_ps(mask, a), _mm_andnot_ps(mask, b));
+
+        let ocx = _mm_sub_ps(_mm_set1_ps(i_ray.origin.x()), _mm_loadu_ps(i_lanes.center_x.as_ptr()));
+        let ocy = _mm_sub_ps(_mm_set1_ps(i_ray.origin.y()), _mm_loadu_ps(i_lanes.center_y.as_ptr()));
+        let ocz = _mm_sub_ps(_mm_set1_ps(i_ray.origin.z()), _mm_loadu_ps(i_lanes.center_z.as_ptr()));
+
+        let (dx, dy, dz) = (_mm_set1_ps(i_ray.direction.x()), _mm_set1_ps(i_ray.direction.y()), _mm_set1_ps(i_ray.direction.z()));
+        let a            = i_ray.direction.dot(i_ray.direction);
+        let radius2      = _mm_loadu_ps(i_lanes.radius2.as_ptr());
+
+        let half_b = _mm_add_ps(_mm_add_ps(_mm_mul_ps(dx, ocx), _mm_mul_ps(dy, ocy)), _mm_mul_ps(dz, ocz));
+        let c      = _mm_sub_ps(_mm_add_ps(_mm_add_ps(_mm_mul_ps(ocx, ocx), _mm_mul_ps(ocy, ocy)), _mm_mul_ps(ocz, ocz)), radius2);
+
+        // a·r^2 - |D×(O-C)|^2, see Sphere::roots
+        let cross_x      = _mm_sub_ps(_mm_mul_ps(dy, ocz), _mm_mul_ps(dz, ocy));
+        let cross_y      = _mm_sub_ps(_mm_mul_ps(dz, ocx), _mm_mul_ps(dx, ocz));
+        let cross_z      = _mm_sub_ps(_mm_mul_ps(dx, ocy), _mm_mul_ps(dy, ocx));
+        let cross2       = _mm_add_ps(_mm_add_ps(_mm_mul_ps(cross_x, cross_x), _mm_mul_ps(cross_y, cross_y)), _mm_mul_ps(cross_z, cross_z));
+        let discriminant = _mm_sub_ps(_mm_mul_ps(_mm_set1_ps(a), radius2), cross2);
+
+        // Roots as in utils::quadratic_roots, q has the sign of -half_b
+        let sign_bit = _mm_set1_ps(-0.0);
+        let root     = _mm_sqrt_ps(_mm_max_ps(discriminant, _mm_setzero_ps()));
+        let q        = _mm_xor_ps(_mm_add_ps(half_b, _mm_or_ps(root, _mm_and_ps(half_b, sign_bit))), sign_bit);
+        let t0       = _mm_div_ps(q, _mm_set1_ps(a));
+        let t1       = _mm_div_ps(c, q);
+        let near     = _mm_min_ps(t0, t1);
+        let far      = _mm_max_ps(t0, t1);
+
+        let min_d   = _mm_set1_ps(i_min_d);
+        let max_d   = _mm_set1_ps(i_max_d);
+        let hit     = _mm_cmpge_ps(discriminant, _mm_setzero_ps());
+        let near_ok = _mm_and_ps(hit, _mm_and_ps(_mm_cmpge_ps(near, min_d), _mm_cmple_ps(near, max_d)));
+        let far_ok  = _mm_and_ps(hit, _mm_and_ps(_mm_cmpge_ps(far, min_d), _mm_cmple_ps(far, max_d)));
+
+        let missed = _mm_set1_ps(f32::INFINITY);
+        _mm_storeu_ps(result.as_mut_ptr(), select(near_ok, near, select(far_ok, far, missed)));
+    }
+    return result;
+}
+
+#[cfg(any(not(target_arch = "x86_64"), feature = "f64"))]
+fn lane_distances(i_lanes: &Lanes, i_ray: &Ray, i_min_d: Float, i_max_d: Float) -> [Float; LANES]
+{
+    lane_distances_scalar(i_lanes, i_ray, i_min_d, i_max_d)
+}
+
+// Fallback for other architectures and f64, and the reference for the SIMD version
+#[cfg_attr(all(target_arch = "x86_64", not(feature = "f64"), not(test)), allow(dead_code))]
+fn lane_distances_scalar(i_lanes: &Lanes, i_ray: &Ray, i_min_d: Float, i_max_d: Float) -> [Float; LANES]
+{
+    let mut result = [Float::INFINITY; LANES];
+
+    for (i, distance) in result.iter_mut().enumerate()
+    {
+        let oc           = i_ray.origin - Vec3::new(i_lanes.center_x[i], i_lanes.center_y[i], i_lanes.center_z[i]);
+        let a            = i_ray.direction.dot(i_ray.direction);
+        let half_b       = oc.dot(i_ray.direction);
+        let c            = oc.dot(oc) - i_lanes.radius2[i];
+        let discriminant = a * i_lanes.radius2[i] - i_ray.direction.cross(oc).norm2();
+
+        if let Some((near, far)) = quadratic_roots(a, half_b, c, discriminant)
+        {
+            if      near >= i_min_d && near <= i_max_d { *distance = near; }
+            else if far  >= i_min_d && far  <= i_max_d { *distance = far;  }
+        }
+    }
+    return result;
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::{ closest_hit, Intersectionable };
+    use super::super::super::materials::{ Material, lambertian::LambertianMat };
+    use super::super::super::scene_generator;
+
+    use std::rc::Rc;
+    use std::time::Instant;
+
+    fn random_spheres(count: usize) -> Vec<Sphere>
+    {
+        let white: Rc<dyn Material> = Rc::new( LambertianMat{ albedo: Vec3::one() } );
+        (0..count).map(|_| Sphere::new(0.1 + rand::random::<Float>() * 0.5, Vec3::rand(-3.0, 3.0), white.clone())).collect()
+    }
+
+    fn random_ray() -> Ray { Ray::new(Vec3::rand(-4.0, 4.0), Vec3::rand_unit(), 0.0) }
+
+    #[test]
+    fn simd_matches_scalar()
+    {
+        let set = SphereSet::new(random_spheres(7));
+        for _ in 0..2000
+        {
+            let ray = random_ray();
+            for lanes in &set.lanes
+            {
+                let simd   = lane_distances(lanes, &ray, 0.001, 5.0);
+                let scalar = lane_distances_scalar(lanes, &ray, 0.001, 5.0);
+                for i in 0..LANES
+                {
+                    assert!( simd[i] == scalar[i] || (simd[i] - scalar[i]).abs() < 1e-4, "{:?} vs {:?}", simd, scalar );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn same_hits_as_separate_spheres()
+    {
+        let spheres  = random_spheres(13);
+        let separate: Vec<Intersectionable> = spheres.iter().cloned().map(Intersectionable::Sphere).collect();
+        let set      = SphereSet::new(spheres);
+
+        for _ in 0..2000
+        {
+            let ray      = random_ray();
+            let expected = closest_hit(&separate, &ray, 0.001, 10.0);
+            let found    = set.hit(&ray, 0.001, 10.0);
+
+            assert_eq!( found.as_ref().map(|hit| hit.position), expected.as_ref().map(|(_, hit)| hit.position) );
+            assert_eq!( set.occluded(&ray, 0.001, 10.0), expected.is_some() );
+        }
+    }
+
+    // Benchmark, run with: cargo test --release -- --ignored --nocapture sphere_set
+    #[test]
+    #[ignore]
+    fn sphere_set_speedup()
+    {
+        let spheres: Vec<Sphere> = scene_generator::rand().into_iter().filter_map(|obj| match obj
+        {
+            Intersectionable::Sphere(sphere)  => Some(vec![sphere]),
+            Intersectionable::SphereSet(set)  => Some(set.spheres().to_vec()),
+            _                                 => None
+        }).flatten().collect();
+
+        let separate: Vec<Intersectionable> = spheres.iter().cloned().map(Intersectionable::Sphere).collect();
+        let batched                         = vec![ Intersectionable::SphereSet( SphereSet::new(spheres.clone()) ) ];
+
+        // Primary rays from the default camera, spread over the scene
+        let rays: Vec<Ray> = (0..200_000).map(|_|
+        {
+            let target = Vec3::new(rand::random::<Float>() * 22.0 - 11.0, rand::random::<Float>() * 2.0, rand::random::<Float>() * 22.0 - 11.0);
+            Ray::new(super::super::super::ray_tracer::CAMERA_POSITION, target - super::super::super::ray_tracer::CAMERA_POSITION, 0.0)
+        }).collect();
+
+        let time = |scene: &[Intersectionable]|
+        {
+            let start = Instant::now();
+            let hits  = rays.iter().filter(|ray| closest_hit(scene, ray, 0.001, 100.0).is_some()).count();
+            (start.elapsed().as_secs_f32(), hits)
+        };
+        let (separate_time, separate_hits) = time(&separate);
+        let (batched_time, batched_hits)   = time(&batched);
+
+        println!("{} spheres, {} rays: {:.3}s one by one, {:.3}s batched, {:.2}x",
+                 spheres.len(), rays.len(), separate_time, batched_time, separate_time / batched_time);
+        assert_eq!( separate_hits, batched_hits );
+    }
+}
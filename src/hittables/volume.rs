@@ -0,0 +1,146 @@
+use super::super::utils::{ vec3::Vec3, Float };
+use super::super::materials::{ Material, volume::VolumeMat };
+use super::super::media::{ Medium, grid::DensityGrid };
+use super::super::ray::Ray;
+use super::hit_record::HitRecord;
+use super::Hittable;
+
+use std::rc::Rc;
+
+// Smoke, clouds and the like: the box a density grid is stretched over, filled with a medium.
+// Only its boundary is intersected, the ray tracer tracks rays through the inside.
+#[derive(Clone, Debug)]
+pub struct Volume
+{
+    min: Vec3,
+    max: Vec3,
+    pub p_material: Rc<dyn Material>
+}
+
+impl Volume
+{
+    // i_medium: Coefficients where the density is 1
+    pub fn new(i_grid: Rc<DensityGrid>, i_medium: Medium) -> Self
+    {
+        let (min, max) = i_grid.bounds();
+        let medium     = Medium{ density: Some(i_grid), ..i_medium };
+
+        Self{ min, max, p_material: Rc::new( VolumeMat::new(medium) ) }
+    }
+
+    fn make_record(&self, i_ray: &Ray, distance: Float, normal: Vec3) -> HitRecord
+    {
+        let (tangent, bitangent) = super::super::utils::orthonormal_basis(normal);
+        let corner               = if normal.x() + normal.y() + normal.z() < 0.0 { self.min } else { self.max };
+
+        return HitRecord::new(i_ray, distance, normal, (0.0, 0.0), tangent, bitangent, self.p_material.clone())
+                   .on_plane(normal.dot(corner));
+    }
+
+    // Slab test, one pair of parallel planes per axis. Distances along the ray to where it enters and leaves the
+    // box, and the normals of the faces there.
+    fn entry_exit(&self, i_ray: &Ray) -> Option<[(Float, Vec3); 2]>
+    {
+        let mut t_enter = Float::NEG_INFINITY;
+        let mut t_exit  = Float::INFINITY;
+        let mut n_enter = Vec3::zero();
+        let mut n_exit  = Vec3::zero();
+
+        let origin    = [i_ray.origin.x(), i_ray.origin.y(), i_ray.origin.z()];
+        let direction = [i_ray.direction.x(), i_ray.direction.y(), i_ray.direction.z()];
+        let min       = [self.min.x(), self.min.y(), self.min.z()];
+        let max       = [self.max.x(), self.max.y(), self.max.z()];
+
+        for axis in 0..3
+        {
+            if direction[axis] == 0.0
+            {
+                // Parallel and outside this slab
+                if origin[axis] < min[axis] || origin[axis] > max[axis] { return None; }
+                continue;
+            }
+
+            let mut normal = [0.0; 3];
+            normal[axis]   = -(1.0 as Float).copysign(direction[axis]);
+            let normal     = Vec3::new(normal[0], normal[1], normal[2]);
+
+            let t_min = (min[axis] - origin[axis]) / direction[axis];
+            let t_max = (max[axis] - origin[axis]) / direction[axis];
+            let (near, far) = if t_min < t_max { (t_min, t_max) } else { (t_max, t_min) };
+
+            if near > t_enter { t_enter = near; n_enter = normal;  }
+            if far  < t_exit  { t_exit  = far;  n_exit  = -normal; }
+        }
+
+        if t_enter > t_exit { return None; }
+        return Some([(t_enter, n_enter), (t_exit, n_exit)]);
+    }
+}
+
+impl Hittable for Volume
+{
+    fn get_normal_at(&self, i_pos: Vec3) -> Vec3
+    {
+        // Along the axis the point sticks out the most, relative to the size of the box
+        let center = (self.min + self.max) * 0.5;
+        let extent = (self.max - self.min) * 0.5;
+        let local  = i_pos - center;
+        let ratios = [local.x() / extent.x(), local.y() / extent.y(), local.z() / extent.z()];
+
+        let mut axis = 0;
+        for i in 1..3 { if ratios[i].abs() > ratios[axis].abs() { axis = i; } }
+
+        let mut normal = [0.0; 3];
+        normal[axis]   = (1.0 as Float).copysign(ratios[axis]);
+        return Vec3::new(normal[0], normal[1], normal[2]);
+    }
+
+    fn hit(&self, i_ray: &Ray, i_min_d: Float, i_max_d: Float) -> Option<HitRecord>
+    {
+        for (t, normal) in self.entry_exit(i_ray)?
+        {
+            if t >= i_min_d && t <= i_max_d { return Some( self.make_record(i_ray, t, normal) ); }
+        }
+        return None;
+    }
+
+    fn occluded(&self, i_ray: &Ray, i_min_d: Float, i_max_d: Float) -> bool
+    {
+        let faces = match self.entry_exit(i_ray) { Some(faces) => faces, None => return false };
+        return faces.iter().any(|(t, _)| *t >= i_min_d && *t <= i_max_d);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use float_cmp::approx_eq;
+
+    fn smoke_box() -> Volume
+    {
+        let grid = DensityGrid::new([1, 1, 1], vec![1.0]).unwrap().placed(Vec3::new(-1.0, 0.0, -2.0), Vec3::new(1.0, 1.0, 2.0));
+        Volume::new(Rc::new(grid), Medium::absorbing(Vec3::one()))
+    }
+
+    #[test]
+    fn hit_from_outside_and_inside()
+    {
+        let volume = smoke_box();
+        let ray    = Ray::new(Vec3::new(0.0, 0.5, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+
+        let hit = volume.hit(&ray, 0.001, 100.0).unwrap();
+        assert!( approx_eq!(Float, hit.distance, 3.0, epsilon = 0.0001) );
+        assert_eq!( hit.normal, Vec3::new(0.0, 0.0, -1.0) );
+        assert!( hit.front_face );
+
+        let inside = Ray::new(hit.position, ray.direction, 0.0);
+        let exit   = volume.hit(&inside, 0.001, 100.0).unwrap();
+        assert!( approx_eq!(Float, exit.distance, 4.0, epsilon = 0.0001) );
+        assert_eq!( exit.normal, Vec3::new(0.0, 0.0, 1.0) );
+        assert!( !exit.front_face );
+
+        assert_eq!( volume.get_normal_at(Vec3::new(0.9, 0.5, 1.0)), Vec3::new(1.0, 0.0, 0.0) );
+        assert!( volume.hit(&Ray::new(Vec3::new(0.0, 2.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0), 0.001, 100.0).is_none() );
+    }
+}
@@ -0,0 +1,460 @@
+use super::{ Integrator, to_ray_space, to_film_space, sample_light, is_visible, is_point_visible };
+use super::super::ray::Ray;
+use super::super::film::AovSample;
+use super::super::hittables::{ closest_hit, hit_record::HitRecord };
+use super::super::ray_tracer::RayTracer;
+use super::super::utils::{ vec3::Vec3, PI, Float };
+
+// Longest subpaths traced from the camera and from a light, counting the camera or the point on the light
+const MAX_VERTICES: usize = 10;
+
+#[derive(Clone, Debug)]
+enum VertexKind
+{
+    Camera,
+    Light(HitRecord),
+    Surface(HitRecord)
+}
+
+#[derive(Clone, Debug)]
+struct Vertex
+{
+    kind:     VertexKind,
+    position: Vec3,
+    normal:   Vec3,  // Geometric, pointing outwards. Zero for the camera.
+    object:   usize, // What the vertex lies on
+    beta:     Vec3,  // Throughput of the subpath up to and including this vertex
+    delta:    bool,  // Scattered by a perfectly specular surface, or a camera light can't reach: nothing can connect to it
+    pdf_fwd:  Float,   // Density per unit area this vertex was sampled with, coming from the previous one
+    pdf_rev:  Float    // Same, if it had been sampled the other way around, coming from the next one
+}
+
+impl Vertex
+{
+    fn is_connectible(&self) -> bool
+    {
+        match self.kind
+        {
+            VertexKind::Camera     => !self.delta,
+            VertexKind::Light(_)   => true,
+            VertexKind::Surface(_) => !self.delta
+        }
+    }
+
+    // Surface the vertex lies on, the camera has none
+    fn record(&self) -> Option<&HitRecord>
+    {
+        match &self.kind
+        {
+            VertexKind::Camera                                      => None,
+            VertexKind::Light(record) | VertexKind::Surface(record) => Some(record)
+        }
+    }
+
+    // Turns a solid angle density of going from this vertex towards i_next into one per unit area around it
+    fn convert_density(&self, i_pdf: Float, i_next: &Vertex) -> Float
+    {
+        let offset   = i_next.position - self.position;
+        let distance2 = offset.norm2();
+        if distance2 == 0.0 { return 0.0; }
+
+        let mut result = i_pdf / distance2;
+        if let VertexKind::Camera = i_next.kind {}
+        else { result *= (i_next.normal.dot(offset) / distance2.sqrt()).abs(); }
+        return result;
+    }
+
+    // Density per unit area of picking i_next, having arrived from i_prev
+    fn pdf(&self, i_prev: Option<&Vertex>, i_next: &Vertex) -> Float
+    {
+        match (&self.kind, i_prev)
+        {
+            (VertexKind::Light(_), _)             => self.pdf_light(i_next),
+            (VertexKind::Surface(record), Some(prev)) =>
+            {
+                let wo = (prev.position - self.position).normalized();
+                let wi = (i_next.position - self.position).normalized();
+                self.convert_density(record.p_material.pdf(record, wo, wi), i_next)
+            },
+            _ => 0.0
+        }
+    }
+
+    // Density per unit area of a vertex on a light emitting towards i_next, lights are cosine weighted
+    fn pdf_light(&self, i_next: &Vertex) -> Float
+    {
+        let cos = self.normal.dot((i_next.position - self.position).normalized());
+        if cos <= 0.0 { return 0.0; }
+        return self.convert_density(cos / PI, i_next);
+    }
+
+    // Density per unit area of a light subpath starting at this vertex, 0 if it isn't on a light that gets sampled
+    fn pdf_light_origin(&self, i_tracer: &RayTracer) -> Float
+    {
+        let lights = i_tracer.lights();
+        if !lights.contains(&self.object) { return 0.0; }
+
+        let area = i_tracer.scene()[self.object].area().unwrap_or(0.0);
+        return if area > 0.0 { 1.0 / (lights.len() as Float * area) } else { 0.0 };
+    }
+
+    // BSDF times the cosine towards i_next, for light coming from i_prev
+    fn f(&self, i_prev: &Vertex, i_next: &Vertex, i_wavelength: Float) -> Vec3
+    {
+        match &self.kind
+        {
+            VertexKind::Surface(record) =>
+            {
+                let wo = (i_prev.position - self.position).normalized();
+                let wi = (i_next.position - self.position).normalized();
+                to_ray_space(record.p_material.eval(record, wo, wi), i_wavelength)
+            },
+            _ => Vec3::zero()
+        }
+    }
+}
+
+// Bidirectional path tracer, Veach 1997. Traces one subpath from the camera and one from a random light,
+// then connects every vertex of one to every vertex of the other, weighting each of these strategies
+// with the balance heuristic so none of them counts twice. Finds caustics and lights hidden behind
+// geometry that a path tracer only reaches by chance.
+// Light subpaths connected to the camera land on other pixels, and are splatted onto the film.
+// Participating media are ignored.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct BidirectionalIntegrator;
+
+impl BidirectionalIntegrator
+{
+    // Follows i_ray from the last vertex of io_path, adding a vertex per surface hit.
+    // i_pdf is the solid angle density i_ray was sampled with. Returns the throughput and ray that escaped to the sky.
+    fn random_walk(&self, i_tracer: &RayTracer, mut i_ray: Ray, mut i_beta: Vec3, mut i_pdf: Float, io_path: &mut Vec<Vertex>) -> Option<(Vec3, Ray)>
+    {
+        let wavelength = i_ray.wavelength;
+        while io_path.len() < MAX_VERTICES
+        {
+            let (idx, hit) = match closest_hit(i_tracer.scene(), &i_ray, 0.0, Float::INFINITY)
+            {
+                Some(closest) => closest,
+                None          => return Some((i_beta, i_ray))
+            };
+
+            let mut vertex = Vertex{ kind:     VertexKind::Surface(hit.clone()),
+                                     position: hit.position,
+                                     normal:   hit.normal,
+                                     object:   idx,
+                                     beta:     i_beta,
+                                     delta:    false,
+                                     pdf_fwd:  0.0,
+                                     pdf_rev:  0.0 };
+            vertex.pdf_fwd = io_path[io_path.len() - 1].convert_density(i_pdf, &vertex);
+            io_path.push(vertex);
+
+            let mut attenuation = Vec3::one();
+            let mut scattered   = hit.p_material.scatter(&i_ray, &hit, &mut attenuation);
+            scattered.wavelength = wavelength;
+
+            // Absorbed, e.g. by a light. Its densities get filled in by whichever strategy ends here.
+            i_beta *= to_ray_space(attenuation, wavelength);
+            if i_beta.norm2() == 0.0 { break; }
+
+            let wo = -i_ray.direction;
+            let wi = scattered.direction;
+            i_pdf  = hit.p_material.pdf(&hit, wo, wi);
+            let mut pdf_rev = hit.p_material.pdf(&hit, wi, wo);
+
+            // Specular surfaces can't be evaluated, so their densities don't mean anything either
+            let last = io_path.len() - 1;
+            if i_pdf == 0.0
+            {
+                io_path[last].delta = true;
+                pdf_rev = 0.0;
+            }
+            io_path[last - 1].pdf_rev = io_path[last].convert_density(pdf_rev, &io_path[last - 1]);
+
+            i_ray = scattered;
+        }
+        return None;
+    }
+
+    // Starts at a random point of a random light and follows the light it gives off
+    fn light_subpath(&self, i_tracer: &RayTracer, i_time: Float, i_wavelength: Float) -> Vec<Vertex>
+    {
+        let (idx, record, pdf_position) = match sample_light(i_tracer) { Some(sample) => sample, None => return Vec::new() };
+        let emission = to_ray_space(record.p_material.emitted(&record), i_wavelength);
+
+        let origin = Vertex{ kind:     VertexKind::Light(record.clone()),
+                             position: record.position,
+                             normal:   record.normal,
+                             object:   idx,
+                             beta:     emission * (1.0 / pdf_position),
+                             delta:    false,
+                             pdf_fwd:  pdf_position,
+                             pdf_rev:  0.0 };
+
+        // Cosine weighted, so the cosine and the density cancel out but for π
+        let mut ray = record.spawn_ray(record.normal + Vec3::rand_unit(), i_time);
+        ray.wavelength = i_wavelength;
+        let pdf_direction = ray.direction.dot(record.normal).max(0.0) / PI;
+
+        let mut result = vec![origin];
+        self.random_walk(i_tracer, ray, emission * (PI / pdf_position), pdf_direction, &mut result);
+        return result;
+    }
+
+    // Light carried by the path made of the first i_s light vertices and the first i_t camera vertices, unweighted.
+    // i_ray is the camera ray, for its time and wavelength.
+    fn connect(&self, i_tracer: &RayTracer, i_camera: &[Vertex], i_light: &[Vertex], i_s: usize, i_t: usize, i_ray: &Ray) -> Vec3
+    {
+        let wavelength = i_ray.wavelength;
+        let pt = &i_camera[i_t - 1];
+
+        // The camera subpath found a light by itself
+        if i_s == 0
+        {
+            return match &pt.kind
+            {
+                VertexKind::Surface(record) => pt.beta * to_ray_space(record.p_material.emitted(record), wavelength),
+                _                           => Vec3::zero()
+            };
+        }
+
+        let qs = &i_light[i_s - 1];
+        if !pt.is_connectible() || !qs.is_connectible() { return Vec3::zero(); }
+
+        let offset    = qs.position - pt.position;
+        let distance2 = offset.norm2();
+        if distance2 == 0.0 { return Vec3::zero(); }
+
+        let result = if i_s == 1
+        {
+            // Lights only emit from their front
+            let cos_light = -qs.normal.dot(offset) / distance2.sqrt();
+            if cos_light <= 0.0 { return Vec3::zero(); }
+
+            pt.beta * pt.f(&i_camera[i_t - 2], qs, wavelength) * qs.beta * (cos_light / distance2)
+        }
+        else
+        {
+            qs.beta * qs.f(&i_light[i_s - 2], pt, wavelength) * pt.f(&i_camera[i_t - 2], qs, wavelength) * pt.beta * (1.0 / distance2)
+        };
+
+        if result.norm2() == 0.0 { return Vec3::zero(); }
+        return match (pt.record(), qs.record())
+        {
+            (Some(from), Some(to)) if is_visible(i_tracer, from, to, i_ray.time) => result,
+            _                                                                    => Vec3::zero()
+        };
+    }
+
+    // Light carried by the first i_s light vertices straight to a random point of the camera's lens, unweighted,
+    // with the camera vertex it made and the pixel it lands in
+    fn connect_to_camera(&self, i_tracer: &RayTracer, i_light: &[Vertex], i_s: usize, i_ray: &Ray) -> Option<(Vec3, Vertex, usize)>
+    {
+        let qs = &i_light[i_s - 1];
+        if !qs.is_connectible() { return None; }
+
+        let (origin, pixel) = i_tracer.sample_camera(qs.position)?;
+        let camera          = Self::camera_vertex(i_tracer, origin);
+
+        let offset    = qs.position - origin;
+        let distance2 = offset.norm2();
+        if distance2 == 0.0 { return None; }
+
+        let result = qs.beta * qs.f(&i_light[i_s - 2], &camera, i_ray.wavelength) * (i_tracer.camera_pdf(offset) / distance2);
+        if result.norm2() == 0.0 { return None; }
+
+        let visible = match qs.record() { Some(record) => is_point_visible(i_tracer, record, origin, i_ray.time), None => false };
+        return if visible { Some((result, camera, pixel)) } else { None };
+    }
+
+    fn camera_vertex(i_tracer: &RayTracer, i_position: Vec3) -> Vertex
+    {
+        Vertex{ kind:     VertexKind::Camera,
+                position: i_position,
+                normal:   Vec3::zero(),
+                object:   0,
+                beta:     Vec3::one(),
+                delta:    !i_tracer.camera().is_connectible(),
+                pdf_fwd:  1.0,
+                pdf_rev:  0.0 }
+    }
+
+    // Balance heuristic: the density of this strategy over the sum of those of every strategy that could have
+    // made the same path. Only ratios of neighboring strategies are needed, which differ by a single vertex.
+    fn mis_weight(&self, i_tracer: &RayTracer, i_camera: &[Vertex], i_light: &[Vertex], i_s: usize, i_t: usize) -> Float
+    {
+        if i_s + i_t == 2 { return 1.0; }
+
+        // Copies of the subpaths, with the reverse densities around the connection this strategy makes
+        let mut camera = i_camera[..i_t].to_vec();
+        let mut light  = i_light[..i_s].to_vec();
+        let pt         = camera[i_t - 1].clone();
+
+        if i_t == 1
+        {
+            // The lens is sampled the same way by both subpaths, only the direction from it differs
+            let qs = light[i_s - 1].clone();
+
+            light[i_s - 1].pdf_rev = pt.convert_density(i_tracer.camera_pdf(qs.position - pt.position), &qs);
+            light[i_s - 2].pdf_rev = qs.pdf(Some(&pt), &light[i_s - 2]);
+        }
+        else if i_s == 0
+        {
+            let pdf_origin = pt.pdf_light_origin(i_tracer);
+            // No light subpath could ever start here
+            if pdf_origin == 0.0 { return 1.0; }
+
+            camera[i_t - 1].pdf_rev = pdf_origin;
+            camera[i_t - 2].pdf_rev = pt.pdf_light(&camera[i_t - 2]);
+        }
+        else
+        {
+            let qs = light[i_s - 1].clone();
+
+            camera[i_t - 1].pdf_rev = qs.pdf(if i_s > 1 { Some(&light[i_s - 2]) } else { None }, &pt);
+            camera[i_t - 2].pdf_rev = pt.pdf(Some(&qs), &camera[i_t - 2]);
+            light[i_s - 1].pdf_rev  = pt.pdf(Some(&camera[i_t - 2]), &qs);
+            if i_s > 1 { light[i_s - 2].pdf_rev = qs.pdf(Some(&pt), &light[i_s - 2]); }
+        }
+
+        let remap   = |pdf: Float| if pdf != 0.0 { pdf } else { 1.0 };
+        let mut sum = 0.0;
+
+        // Handing camera vertices over to the light subpath, down to the one the light subpath reaches the camera from
+        let mut ratio = 1.0;
+        for i in (1..i_t).rev()
+        {
+            ratio *= remap(camera[i].pdf_rev) / remap(camera[i].pdf_fwd);
+            if !camera[i].delta && !camera[i - 1].delta { sum += ratio; }
+        }
+
+        // And light vertices over to the camera subpath, area lights are never specular
+        let mut ratio = 1.0;
+        for i in (0..i_s).rev()
+        {
+            ratio *= remap(light[i].pdf_rev) / remap(light[i].pdf_fwd);
+            let delta_before = i > 0 && light[i - 1].delta;
+            if !light[i].delta && !delta_before { sum += ratio; }
+        }
+
+        return 1.0 / (1.0 + sum);
+    }
+}
+
+impl Integrator for BidirectionalIntegrator
+{
+    fn compute_ray(&self, i_tracer: &RayTracer, i_ray: Ray) -> (Vec3, AovSample)
+    {
+        let wavelength = i_ray.wavelength;
+        let time       = i_ray.time;
+
+        // The lens point's own density is the same for every strategy, so any will do
+        let mut camera  = vec![Self::camera_vertex(i_tracer, i_ray.origin)];
+        let escaped     = self.random_walk(i_tracer, i_ray, Vec3::one(), i_tracer.camera_pdf(i_ray.direction), &mut camera);
+        let light       = self.light_subpath(i_tracer, time, wavelength);
+
+        let mut aovs = AovSample::background();
+        if let Some(Vertex{ kind: VertexKind::Surface(record), object, .. }) = camera.get(1)
+        {
+            aovs = i_tracer.first_hit_aovs(&i_ray, *object, record);
+        }
+
+        // Light reaching the first hit straight from the sky or a light counts as direct
+        let mut direct   = Vec3::zero();
+        let mut indirect = Vec3::zero();
+
+        // Only camera subpaths can find the sky
+        if let Some((beta, ray)) = escaped
+        {
+            let sky = beta * to_ray_space(i_tracer.sample_skybox(&ray), wavelength);
+            if camera.len() <= 2 { direct   += sky; }
+            else                 { indirect += sky; }
+        }
+
+        for t in 2..=camera.len()
+        {
+            for s in 0..=light.len()
+            {
+                let contribution = self.connect(i_tracer, &camera, &light, s, t, &i_ray);
+                if contribution.norm2() == 0.0 { continue; }
+
+                let weighted = contribution * self.mis_weight(i_tracer, &camera, &light, s, t);
+                let bounces  = s + t - 2;
+                if bounces <= 1 { direct   += weighted; }
+                else            { indirect += weighted; }
+            }
+        }
+
+        // A light seen straight through the camera is left to the camera subpath
+        for s in 2..=light.len()
+        {
+            if let Some((contribution, camera_vertex, pixel)) = self.connect_to_camera(i_tracer, &light, s, &i_ray)
+            {
+                let weighted = contribution * self.mis_weight(i_tracer, &[camera_vertex], &light, s, 1);
+                i_tracer.splat(pixel, to_film_space(weighted, wavelength));
+            }
+        }
+
+        aovs.direct   = to_film_space(direct, wavelength);
+        aovs.indirect = to_film_space(indirect, wavelength);
+        return (to_film_space(direct + indirect, wavelength), aovs);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::path::PathIntegrator;
+    use super::super::mean_and_variance;
+    use super::super::super::hittables::{ Intersectionable, sphere::Sphere, quad::Quad };
+    use super::super::super::materials::{ lambertian::LambertianMat, metallic::MetallicMat, light::DiffuseLightMat };
+
+    use std::rc::Rc;
+
+    // A floor under a square light, with a mirror ball on the side, and no sky
+    fn lit_floor() -> RayTracer
+    {
+        let floor  = Rc::new( LambertianMat{ albedo: Vec3::new(0.8, 0.5, 0.2) } );
+        let light  = Rc::new( DiffuseLightMat::new(Vec3::one() * 4.0) );
+        let mirror = Rc::new( MetallicMat::new(0.0, Vec3::one()) );
+
+        let scene = vec![ Intersectionable::Quad( Quad::new(Vec3::new(-3.0, 0.0, -3.0), Vec3::new(0.0, 0.0, 6.0), Vec3::new(6.0, 0.0, 0.0), floor) ),
+                          Intersectionable::Quad( Quad::new(Vec3::new(-1.0, 1.5, -1.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 2.0), light) ),
+                          Intersectionable::Sphere( Sphere::new(0.5, Vec3::new(1.2, 0.5, 0.0), mirror) ) ];
+        return RayTracer::for_scene(scene, 0.0);
+    }
+
+    #[test]
+    fn renders_the_same_as_path_tracing()
+    {
+        // Mean of the whole image, as light tracing lands anywhere on it. Independent renders give its variance.
+        let mean = |integrator: Box<dyn Integrator>|
+        {
+            let mut tracer = lit_floor().with_integrator(integrator, 256);
+            tracer.camera_mut().move_to( Vec3::new(0.0, 1.0, -3.0) );
+            tracer.camera_mut().look_at( Vec3::new(0.0, 0.3, 0.0) );
+
+            mean_and_variance(16, ||
+            {
+                let film = tracer.render();
+                film.radiance.pixels.iter().fold(Vec3::zero(), |sum, pixel| sum + *pixel) / film.radiance.pixels.len() as Float
+            })
+        };
+
+        let (bdpt, bdpt_variance) = mean( Box::new(BidirectionalIntegrator) );
+        let (path, path_variance) = mean( Box::<PathIntegrator>::default() );
+
+        assert!( path > 0.1 );
+        assert!( (bdpt - path).abs() < 5.0 * (bdpt_variance + path_variance).sqrt(), "{} vs {}", bdpt, path );
+    }
+
+    #[test]
+    fn sees_lights_directly()
+    {
+        let tracer = lit_floor();
+        let ray    = Ray::new(Vec3::new(0.0, 0.5, 0.0), Vec3::new(0.0, 1.0, 0.0), 0.0);
+
+        assert_eq!( BidirectionalIntegrator.compute_ray(&tracer, ray).0, Vec3::one() * 4.0 );
+    }
+}
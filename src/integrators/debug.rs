@@ -0,0 +1,173 @@
+use super::Integrator;
+use super::super::ray::Ray;
+use super::super::film::AovSample;
+use super::super::hittables::{ closest_hit, any_hit, hit_record::HitRecord };
+use super::super::ray_tracer::RayTracer;
+use super::super::utils::{ vec3::Vec3, Float };
+
+// Integrators that show something about the scene rather than light it. They all work in RGB.
+
+// Runs i_shade on what the camera ray hits first, black where it escapes
+fn shade_first_hit<F>(i_tracer: &RayTracer, i_ray: &Ray, i_shade: F) -> (Vec3, AovSample)
+    where F: Fn(&HitRecord, &AovSample) -> Vec3
+{
+    match closest_hit(i_tracer.scene(), i_ray, 0.0, Float::INFINITY)
+    {
+        Some((idx, hit)) =>
+        {
+            let aovs = i_tracer.first_hit_aovs(i_ray, idx, &hit);
+            (i_shade(&hit, &aovs), aovs)
+        },
+        None => (Vec3::zero(), AovSample::background())
+    }
+}
+
+// Normal facing the side i_ray came from
+fn facing_normal(i_ray: &Ray, i_hit: &HitRecord) -> Vec3
+{
+    let normal = i_hit.p_material.shading_normal(i_hit);
+    return if normal.dot(i_ray.direction) > 0.0 { -normal } else { normal };
+}
+
+// Shading normals, from [-1, 1] to [0, 1] per channel
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NormalsIntegrator;
+
+impl Integrator for NormalsIntegrator
+{
+    fn compute_ray(&self, i_tracer: &RayTracer, i_ray: Ray) -> (Vec3, AovSample)
+    {
+        shade_first_hit(i_tracer, &i_ray, |_, aovs| (aovs.normal + Vec3::one()) * 0.5)
+    }
+}
+
+// Distance along the camera's forward axis, white up close fading to black at twice the distance in focus,
+// so it follows the size of the scene
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DepthIntegrator;
+
+impl Integrator for DepthIntegrator
+{
+    fn compute_ray(&self, i_tracer: &RayTracer, i_ray: Ray) -> (Vec3, AovSample)
+    {
+        let range = 2.0 * i_tracer.camera().focus_dist();
+        shade_first_hit(i_tracer, &i_ray, |_, aovs| Vec3::one() * (1.0 - aovs.depth / range).clamp(0.0, 1.0))
+    }
+}
+
+// Fraction of the hemisphere around the first hit that's open within some distance, cosine weighted
+#[derive(Copy, Clone, Debug)]
+pub struct AmbientOcclusionIntegrator
+{
+    pub samples:  u32, // Rays cast per camera ray
+    pub distance: Float  // How far away things still occlude
+}
+
+impl Integrator for AmbientOcclusionIntegrator
+{
+    fn compute_ray(&self, i_tracer: &RayTracer, i_ray: Ray) -> (Vec3, AovSample)
+    {
+        shade_first_hit(i_tracer, &i_ray, |hit, _|
+        {
+            let normal = facing_normal(&i_ray, hit);
+            let open   = (0..self.samples).filter(|_|
+            {
+                let ray = hit.spawn_ray(normal + Vec3::rand_unit(), i_ray.time);
+                !any_hit(i_tracer.scene(), &ray, 0.0, self.distance)
+            }).count();
+
+            Vec3::one() * (open as Float / self.samples.max(1) as Float)
+        })
+    }
+}
+
+// Surface bounces a path takes before it escapes or gets absorbed, from blue for none to red for MAX_BOUNCES or more
+#[derive(Copy, Clone, Debug, Default)]
+pub struct BounceHeatmapIntegrator;
+
+impl BounceHeatmapIntegrator
+{
+    const MAX_BOUNCES: u32 = 16;
+
+    fn heat(i_bounces: u32) -> Vec3
+    {
+        let t = (i_bounces as Float / Self::MAX_BOUNCES as Float).min(1.0);
+        if t < 0.5 { Vec3::lerp(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 1.0, 0.0), t * 2.0) }
+        else       { Vec3::lerp(Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0), t * 2.0 - 1.0) }
+    }
+}
+
+impl Integrator for BounceHeatmapIntegrator
+{
+    fn compute_ray(&self, i_tracer: &RayTracer, mut i_ray: Ray) -> (Vec3, AovSample)
+    {
+        let mut aovs    = AovSample::background();
+        let mut bounces = 0;
+
+        while bounces < Self::MAX_BOUNCES
+        {
+            let (idx, hit) = match closest_hit(i_tracer.scene(), &i_ray, 0.0, Float::INFINITY) { Some(closest) => closest, None => break };
+            if bounces == 0 { aovs = i_tracer.first_hit_aovs(&i_ray, idx, &hit); }
+            bounces += 1;
+
+            let mut attenuation = Vec3::one();
+            i_ray = hit.p_material.scatter(&i_ray, &hit, &mut attenuation);
+            if attenuation.norm2() == 0.0 { break; }
+        }
+
+        return (Self::heat(bounces), aovs);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::super::hittables::{ Intersectionable, sphere::Sphere, quad::Quad };
+    use super::super::super::materials::lambertian::LambertianMat;
+
+    use std::rc::Rc;
+
+    #[test]
+    fn ambient_occlusion()
+    {
+        let white  = Rc::new( LambertianMat{ albedo: Vec3::one() } );
+        let tracer = RayTracer::for_scene(vec![ Intersectionable::Sphere( Sphere::new(1.0, Vec3::zero(), white) ) ], 1.0);
+        let ray    = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+
+        // Nothing around a lone sphere, and nothing open inside of it
+        let ao = AmbientOcclusionIntegrator{ samples: 16, distance: 3.0 };
+        assert_eq!( ao.compute_ray(&tracer, ray).0, Vec3::one() );
+        assert_eq!( ao.compute_ray(&tracer, Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, 1.0), 0.0)).0, Vec3::zero() );
+
+        // Escaped rays are black
+        let missed = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 1.0, 0.0), 0.0);
+        assert_eq!( ao.compute_ray(&tracer, missed).0, Vec3::zero() );
+    }
+
+    #[test]
+    fn ambient_occlusion_distance()
+    {
+        // A floor under a ceiling one unit above it, much wider than high
+        let white  = Rc::new( LambertianMat{ albedo: Vec3::one() } );
+        let size   = 1e5;
+        let scene  = vec![ Intersectionable::Quad( Quad::new(Vec3::new(-0.5 * size, 0.0, -0.5 * size), Vec3::new(0.0, 0.0, size), Vec3::new(size, 0.0, 0.0), white.clone()) ),
+                           Intersectionable::Quad( Quad::new(Vec3::new(-0.5 * size, 1.0, -0.5 * size), Vec3::new(size, 0.0, 0.0), Vec3::new(0.0, 0.0, size), white) ) ];
+        let tracer = RayTracer::for_scene(scene, 1.0);
+        let ray    = Ray::new(Vec3::new(0.0, 0.5, 0.0), Vec3::new(0.3, -1.0, 0.2), 0.0);
+
+        // The ceiling only occludes what's within reach. Far enough, all but the most grazing rays reach it.
+        let near = AmbientOcclusionIntegrator{ samples: 64, distance: 0.9 };
+        let far  = AmbientOcclusionIntegrator{ samples: 64, distance: size / 10.0 };
+        assert_eq!( near.compute_ray(&tracer, ray).0, Vec3::one() );
+        assert_eq!( far.compute_ray(&tracer, ray).0, Vec3::zero() );
+    }
+
+    #[test]
+    fn heat()
+    {
+        assert_eq!( BounceHeatmapIntegrator::heat(0), Vec3::new(0.0, 0.0, 1.0) );
+        assert_eq!( BounceHeatmapIntegrator::heat(BounceHeatmapIntegrator::MAX_BOUNCES / 2), Vec3::new(0.0, 1.0, 0.0) );
+        assert_eq!( BounceHeatmapIntegrator::heat(100), Vec3::new(1.0, 0.0, 0.0) );
+    }
+}
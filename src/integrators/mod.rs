@@ -0,0 +1,146 @@
+pub mod path;
+pub mod bdpt;
+pub mod photon_map;
+pub mod debug;
+pub mod whitted;
+
+use super::ray::Ray;
+use super::film::AovSample;
+use super::media::Medium;
+use super::ray_tracer::RayTracer;
+use super::hittables::{ any_hit, hit_record::HitRecord };
+use super::options::Options;
+use super::utils::{ vec3::Vec3, spectrum, Float };
+
+// Part of a shadow ray left out at its end, so it doesn't hit what it's aimed at. Relative, so it scales with the scene.
+pub const SHADOW_EPSILON: Float = 0.0001;
+
+// Turns a camera ray into the light arriving along it, plus the auxiliary values of what it hit first
+pub trait Integrator
+{
+    fn compute_ray(&self, i_tracer: &RayTracer, i_ray: Ray) -> (Vec3, AovSample);
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum IntegratorKind
+{
+    Path,
+    Bidirectional,
+    PhotonMapping, // Path tracing, with caustics from a photon map
+    Whitted,
+    // Debugging
+    Normals,
+    Depth,
+    AmbientOcclusion,
+    BounceHeatmap
+}
+
+impl IntegratorKind
+{
+    pub fn from_name(i_name: &str) -> Option<Self>
+    {
+        match i_name
+        {
+            "path"    => Some(Self::Path),
+            "bdpt"    => Some(Self::Bidirectional),
+            "photon"  => Some(Self::PhotonMapping),
+            "whitted" => Some(Self::Whitted),
+            "normals" => Some(Self::Normals),
+            "depth"   => Some(Self::Depth),
+            "ao"      => Some(Self::AmbientOcclusion),
+            "bounces" => Some(Self::BounceHeatmap),
+            _         => None
+        }
+    }
+
+    pub fn build(&self, i_tracer: &RayTracer, i_options: &Options) -> Box<dyn Integrator>
+    {
+        match self
+        {
+            Self::Path             => Box::new( path::PathIntegrator::default() ),
+            Self::Bidirectional    => Box::new( bdpt::BidirectionalIntegrator ),
+            Self::PhotonMapping    =>
+            {
+                let caustics = photon_map::PhotonMap::trace_caustics(i_tracer, i_options.photon_count);
+                Box::new( path::PathIntegrator::default().with_caustics(caustics) )
+            },
+            Self::Whitted          => Box::new( whitted::WhittedIntegrator ),
+            Self::Normals          => Box::new( debug::NormalsIntegrator ),
+            Self::Depth            => Box::new( debug::DepthIntegrator ),
+            Self::AmbientOcclusion => Box::new( debug::AmbientOcclusionIntegrator{ samples:  i_options.ao_samples,
+                                                                           distance: i_options.ao_distance } ),
+            Self::BounceHeatmap    => Box::new( debug::BounceHeatmapIntegrator )
+        }
+    }
+}
+
+// Uniformly distributed point on a uniformly picked light, with the index of the light and the density per unit area
+pub fn sample_light(i_tracer: &RayTracer) -> Option<(usize, HitRecord, Float)>
+{
+    let lights = i_tracer.lights();
+    if lights.is_empty() { return None; }
+
+    let idx    = lights[ ((rand::random::<Float>() * lights.len() as Float) as usize).min(lights.len() - 1) ];
+    let light  = &i_tracer.scene()[idx];
+    let record = light.sample_surface()?;
+    let area   = light.area().unwrap_or(0.0);
+    if area <= 0.0 { return None; }
+
+    return Some((idx, record, 1.0 / (lights.len() as Float * area)));
+}
+
+// Nothing in between the two surface points, both ends are kept off their surfaces like scattered rays
+pub fn is_visible(i_tracer: &RayTracer, i_from: &HitRecord, i_to: &HitRecord, i_time: Float) -> bool
+{
+    let from = i_from.spawn_origin(i_to.position - i_from.position);
+    let to   = i_to.spawn_origin(from - i_to.position);
+    let ray  = Ray::new(from, to - from, i_time);
+    return !any_hit(i_tracer.scene(), &ray, 0.0, (to - from).norm() * (1.0 - SHADOW_EPSILON));
+}
+
+// Same as is_visible, towards a point that isn't on a surface, like one on the camera's lens
+pub fn is_point_visible(i_tracer: &RayTracer, i_from: &HitRecord, i_to: Vec3, i_time: Float) -> bool
+{
+    let from = i_from.spawn_origin(i_to - i_from.position);
+    let ray  = Ray::new(from, i_to - from, i_time);
+    return !any_hit(i_tracer.scene(), &ray, 0.0, (i_to - from).norm() * (1.0 - SHADOW_EPSILON));
+}
+
+// Mean of i_count independent estimates, summed over the channels, and the variance of that mean.
+// In f64 so the sums don't drift over many samples.
+#[cfg(test)]
+pub fn mean_and_variance(i_count: u32, mut i_estimate: impl FnMut() -> Vec3) -> (f64, f64)
+{
+    let mut sum  = 0.0f64;
+    let mut sum2 = 0.0f64;
+    for _ in 0..i_count
+    {
+        let value = i_estimate();
+        let value = (value.x() + value.y() + value.z()) as f64;
+        sum  += value;
+        sum2 += value * value;
+    }
+
+    let mean = sum / i_count as f64;
+    return (mean, (sum2 / i_count as f64 - mean * mean) / (i_count - 1) as f64);
+}
+
+// Spectral rays carry the value for their wavelength on every channel
+pub fn to_ray_space(i_color: Vec3, i_wavelength: Float) -> Vec3
+{
+    if i_wavelength <= 0.0 { return i_color; }
+    return Vec3::one() * spectrum::rgb_to_spectral(i_color, i_wavelength);
+}
+
+pub fn to_film_space(i_color: Vec3, i_wavelength: Float) -> Vec3
+{
+    if i_wavelength <= 0.0 { return i_color; }
+    return spectrum::spectral_to_rgb(i_color.x(), i_wavelength);
+}
+
+pub fn medium_to_ray_space(i_medium: &Medium, i_wavelength: Float) -> Medium
+{
+    Medium{ absorption: to_ray_space(i_medium.absorption, i_wavelength),
+            scattering: to_ray_space(i_medium.scattering, i_wavelength),
+            ..i_medium.clone() }
+}
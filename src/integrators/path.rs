@@ -0,0 +1,225 @@
+use super::{ Integrator, to_ray_space, to_film_space, medium_to_ray_space };
+use super::photon_map::PhotonMap;
+use super::super::ray::Ray;
+use super::super::film::AovSample;
+use super::super::hittables::closest_hit;
+use super::super::media::Medium;
+use super::super::ray_tracer::RayTracer;
+use super::super::utils::{ vec3::Vec3, Float };
+
+const MAX_DEPTH: i32 = 50;
+// Scattering events inside volumes per path after which Russian roulette ends walks as their throughput drops.
+// Random walks in dense media take many more steps than surface bounces, cutting them off would darken them.
+const ROULETTE_MEDIUM_EVENTS: i32 = 32;
+// Walks through media that don't absorb never lose throughput. Only there so one can't go on forever.
+const MAX_MEDIUM_EVENTS: i32 = 1 << 16;
+
+// Unidirectional path tracer: follows the camera ray as it scatters around until it reaches the sky or a light
+#[derive(Clone, Debug, Default)]
+pub struct PathIntegrator
+{
+    // Caustics looked up wherever the path lands on something diffuse, instead of waiting for it to find a light
+    caustics: Option<PhotonMap>
+}
+
+impl PathIntegrator
+{
+    pub fn with_caustics(mut self, i_caustics: PhotonMap) -> Self
+    {
+        self.caustics = Some(i_caustics);
+        return self;
+    }
+}
+
+impl Integrator for PathIntegrator
+{
+    fn compute_ray(&self, i_tracer: &RayTracer, mut i_ray: Ray) -> (Vec3, AovSample)
+    {
+        let wavelength     = i_ray.wavelength;
+        let mut aovs       = AovSample::background();
+        let mut depth      = MAX_DEPTH;
+        let mut throughput = Vec3::one();
+        // Light reaching the first hit straight from the sky or a light counts as direct
+        let mut direct     = Vec3::zero();
+        let mut indirect   = Vec3::zero();
+        // Media the ray is currently inside of, innermost last
+        let mut media: Vec<Medium> = Vec::new();
+        let mut medium_events = 0;
+        // Lights found through mirrors or glass right after a diffuse bounce were already counted by the caustics
+        let mut after_diffuse  = false;
+        let mut after_specular = false;
+
+        let finish = |direct: Vec3, indirect: Vec3, mut aovs: AovSample| -> (Vec3, AovSample)
+        {
+            aovs.direct   = to_film_space(direct, wavelength);
+            aovs.indirect = to_film_space(indirect, wavelength);
+            return (to_film_space(direct + indirect, wavelength), aovs);
+        };
+
+        while depth > 0
+        {
+            let closest = closest_hit(i_tracer.scene(), &i_ray, 0.0, Float::INFINITY);
+
+            // A ray inside a volume always meets its boundary, unless it slipped through it and is out in the open
+            if let (Some(medium), Some((_, boundary))) = (media.last(), closest.as_ref())
+            {
+                // Ray::new normalizes directions, so distances along rays are world space distances
+                let max_d = boundary.distance;
+
+                if !medium.scatters()
+                {
+                    throughput *= medium.transmittance(&i_ray, max_d);
+                }
+                else
+                {
+                    let (weight, scattered_at) = medium.sample_distance(&i_ray, max_d);
+                    throughput *= weight;
+
+                    // Random walk inside the volume, it doesn't count towards the surface bounces
+                    if let Some(distance) = scattered_at
+                    {
+                        medium_events += 1;
+                        if medium_events > MAX_MEDIUM_EVENTS || throughput.norm2() == 0.0 { return finish(direct, indirect, aovs); }
+
+                        // Survivors make up for the ones that were ended, so on average nothing is lost
+                        if medium_events > ROULETTE_MEDIUM_EVENTS
+                        {
+                            let survival = throughput.x().max(throughput.y()).max(throughput.z()).min(1.0);
+                            if rand::random::<Float>() >= survival { return finish(direct, indirect, aovs); }
+                            throughput *= 1.0 / survival;
+                        }
+
+                        i_ray = Ray::new(i_ray.at(distance), medium.sample_phase(i_ray.direction), i_ray.time);
+                        i_ray.wavelength = wavelength;
+                        after_diffuse    = false;
+                        continue;
+                    }
+                }
+            }
+
+            let bounces = MAX_DEPTH - depth;
+            if let Some((idx, hit)) = closest
+            {
+                if depth == MAX_DEPTH { aovs = i_tracer.first_hit_aovs(&i_ray, idx, &hit); }
+
+                if self.caustics.is_none() || !(after_diffuse && after_specular)
+                {
+                    let emitted = throughput * to_ray_space(hit.p_material.emitted(&hit), wavelength);
+                    if bounces <= 1 { direct   += emitted; }
+                    else            { indirect += emitted; }
+                }
+
+                let mut attenuation = Vec3::one();
+                let incoming        = i_ray.direction;
+
+                i_ray = hit.p_material.scatter(&i_ray, &hit, &mut attenuation);
+                i_ray.wavelength = wavelength;
+
+                if let Some(caustics) = &self.caustics
+                {
+                    // Same test the photons were stored with
+                    if hit.p_material.pdf(&hit, -incoming, i_ray.direction) > 0.0
+                    {
+                        indirect      += throughput * to_ray_space(caustics.estimate(&hit, -incoming), wavelength);
+                        after_diffuse  = true;
+                        after_specular = false;
+                    }
+                    else { after_specular = true; }
+                }
+
+                // Crossing the boundary of a volume
+                let transmitted = incoming.dot(hit.normal) * i_ray.direction.dot(hit.normal) > 0.0;
+                if let (true, Some(medium)) = (transmitted, hit.p_material.medium())
+                {
+                    if hit.front_face { media.push(medium_to_ray_space(&medium, wavelength)); }
+                    else              { media.pop(); }
+                    after_diffuse = false;
+                }
+
+                throughput *= to_ray_space(attenuation, wavelength);
+                depth      -= 1;
+
+                // Absorbed, e.g. a microfacet bounced the ray into the surface
+                if throughput.norm2() == 0.0 { return finish(direct, indirect, aovs); }
+            }
+            else
+            {
+                let sky = throughput * to_ray_space(i_tracer.sample_skybox(&i_ray), wavelength);
+                if bounces <= 1 { direct   += sky; }
+                else            { indirect += sky; }
+
+                return finish(direct, indirect, aovs);
+            }
+        }
+
+        return finish(direct, indirect, aovs);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::mean_and_variance;
+    use super::super::super::hittables::{ Intersectionable, sphere::Sphere, quad::Quad, prism::Prism };
+    use super::super::super::materials::{ lambertian::LambertianMat, subsurface::SubsurfaceMat, light::DiffuseLightMat };
+    use super::super::super::ray_tracer::{ CAMERA_POSITION, CAMERA_TARGET };
+
+    use std::rc::Rc;
+
+    // The simple scene with a wall behind it, i_scale times as big and seen from as far, under the sky
+    fn render_scaled(i_scale: Float) -> (f64, f64)
+    {
+        let ground = Rc::new( LambertianMat{ albedo: Vec3::new(0.5, 0.75, 0.0) } );
+        let grey   = Rc::new( LambertianMat{ albedo: Vec3::new(0.5, 0.5, 0.5) } );
+        let scene  = vec![ Intersectionable::Sphere( Sphere::new(1000.0 * i_scale, Vec3::new(0.0, -1000.0, 0.0) * i_scale, ground) ),
+                           Intersectionable::Sphere( Sphere::new(2.0 * i_scale, Vec3::new(0.0, 2.0, 0.0) * i_scale, grey.clone()) ),
+                           Intersectionable::Quad( Quad::new(Vec3::new(-20.0, 0.0, 12.0) * i_scale, Vec3::new(40.0, 0.0, 0.0) * i_scale, Vec3::new(0.0, 20.0, 0.0) * i_scale, grey) ) ];
+
+        let mut tracer = RayTracer::for_scene(scene, 1.0).with_integrator(Box::<PathIntegrator>::default(), 16);
+        tracer.camera_mut().set_aperture( 0.0 );
+        tracer.camera_mut().move_to( CAMERA_POSITION * i_scale );
+        tracer.camera_mut().look_at( CAMERA_TARGET * i_scale );
+
+        return mean_and_variance(16, ||
+        {
+            let film = tracer.render();
+            film.radiance.pixels.iter().fold(Vec3::zero(), |sum, pixel| sum + *pixel) / film.radiance.pixels.len() as Float
+        });
+    }
+
+    #[test]
+    fn renders_the_same_at_any_scale()
+    {
+        // A fixed limit on how far rays go would cut off the ground and the wall at some scale
+        let (unit, unit_variance) = render_scaled(1.0);
+
+        for scale in [1e-3, 1e3]
+        {
+            let (scaled, scaled_variance) = render_scaled(scale);
+            assert!( (scaled - unit).abs() < 5.0 * (unit_variance + scaled_variance).sqrt(), "{} at scale {} vs {}", scaled, scale, unit );
+        }
+    }
+
+    #[test]
+    fn long_random_walks_keep_their_light()
+    {
+        // White and so dense that light scatters thousands of times before it finds its way out, all of it eventually.
+        // Inside a box glowing the same everywhere, it can only look as bright as the box.
+        let material = Rc::new( SubsurfaceMat::new(Vec3::one(), Vec3::one() * 0.02, 1.0) );
+        let light    = Rc::new( DiffuseLightMat::new(Vec3::one()) );
+        let face     = |corner: Vec3, u: Vec3, v: Vec3| Intersectionable::Quad( Quad::new(corner * 10.0, u * 20.0, v * 20.0, light.clone()) );
+        let (x, y, z) = (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+
+        // Facing inwards
+        let scene = vec![ Intersectionable::Prism( Prism::new(Vec3::zero(), 2.0, 2.0, material) ),
+                          face(-Vec3::one(), z, x), face(Vec3::new(-1.0, 1.0, -1.0), x, z),
+                          face(-Vec3::one(), y, z), face(Vec3::new(1.0, -1.0, -1.0), z, y),
+                          face(-Vec3::one(), x, y), face(Vec3::new(-1.0, -1.0, 1.0), y, x) ];
+        let tracer = RayTracer::for_scene(scene, 0.0);
+        let ray    = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+
+        let (walked, walked_variance) = mean_and_variance(1000, || PathIntegrator::default().compute_ray(&tracer, ray).0);
+        assert!( (walked - 3.0).abs() < 0.01 + 5.0 * walked_variance.sqrt(), "{}", walked );
+    }
+}
@@ -0,0 +1,290 @@
+use super::sample_light;
+use super::super::hittables::{ closest_hit, hit_record::HitRecord };
+use super::super::ray_tracer::RayTracer;
+use super::super::utils::{ vec3::Vec3, PI, Float };
+
+use std::collections::BinaryHeap;
+
+// Photons gathered around a point to estimate the light there, and how far to look for them at most. The radius is
+// relative to the diagonal of the box the photons landed in, so it scales with the scene.
+const GATHER_COUNT:      usize = 100;
+const MAX_GATHER_RADIUS: Float = 0.025;
+// Specular bounces a photon may take before landing, glass spheres need two per pass through them
+const MAX_BOUNCES: i32 = 16;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Photon
+{
+    pub position:  Vec3,
+    pub direction: Vec3, // Where the photon came from, pointing away from the surface
+    pub power:     Vec3
+}
+
+// Photons stored as an implicit, balanced kd-tree: the median of every range splits it along the axis kept next to it
+#[derive(Clone, Debug, Default)]
+pub struct PhotonMap
+{
+    photons:    Vec<Photon>,
+    axes:       Vec<u8>,
+    max_radius: Float
+}
+
+fn coordinate(i_position: Vec3, i_axis: u8) -> Float
+{
+    match i_axis
+    {
+        0 => i_position.x(),
+        1 => i_position.y(),
+        _ => i_position.z()
+    }
+}
+
+impl PhotonMap
+{
+    pub fn new(mut photons: Vec<Photon>) -> Self
+    {
+        let (min, max) = Self::bounds(&photons);
+        let max_radius = (max - min).norm() * MAX_GATHER_RADIUS;
+
+        let mut axes = vec![0; photons.len()];
+        Self::build(&mut photons, &mut axes);
+        Self{ photons, axes, max_radius }
+    }
+
+    // Light from the lights that reached a diffuse surface only through mirrors and glass, what path tracing
+    // struggles with. Photons are traced in RGB, so they don't disperse.
+    pub fn trace_caustics(i_tracer: &RayTracer, i_count: usize) -> Self
+    {
+        let mut photons = Vec::new();
+        for _ in 0..i_count
+        {
+            let (_, record, pdf_position) = match sample_light(i_tracer) { Some(sample) => sample, None => break };
+
+            // Emitted cosine weighted, the cosine and the density cancel out but for π
+            let mut power    = record.p_material.emitted(&record) * (PI / (pdf_position * i_count as Float));
+            let mut ray      = record.spawn_ray(record.normal + Vec3::rand_unit(), 0.0);
+            let mut specular = false;
+
+            for _ in 0..MAX_BOUNCES
+            {
+                let hit = match closest_hit(i_tracer.scene(), &ray, 0.0, Float::INFINITY) { Some((_, hit)) => hit, None => break };
+
+                let mut attenuation = Vec3::one();
+                let scattered       = hit.p_material.scatter(&ray, &hit, &mut attenuation);
+
+                // Landed on something diffuse, only stored when it got there in a way path tracing can't follow
+                if hit.p_material.pdf(&hit, -ray.direction, scattered.direction) > 0.0
+                {
+                    if specular { photons.push( Photon{ position: hit.position, direction: -ray.direction, power } ); }
+                    break;
+                }
+
+                // Media are left to the path tracer
+                let transmitted = ray.direction.dot(hit.normal) * scattered.direction.dot(hit.normal) > 0.0;
+                if transmitted && hit.p_material.medium().is_some() { break; }
+
+                power *= attenuation;
+                if power.norm2() == 0.0 { break; }
+
+                specular = true;
+                ray      = scattered;
+            }
+        }
+        return Self::new(photons);
+    }
+
+    // Reflected radiance towards i_wo, from the photons around the hit spread over the disk they were found in
+    pub fn estimate(&self, i_record: &HitRecord, i_wo: Vec3) -> Vec3
+    {
+        let (nearest, radius2) = self.nearest(i_record.position, GATHER_COUNT, self.max_radius);
+        if nearest.is_empty() { return Vec3::zero(); }
+
+        let normal     = i_record.p_material.shading_normal(i_record);
+        let mut result = Vec3::zero();
+        for idx in nearest
+        {
+            let photon = &self.photons[idx];
+
+            // eval() includes the cosine, but the photon's power already is per unit area of the surface
+            let cos = normal.dot(photon.direction).abs();
+            if cos < 1e-4 { continue; }
+            result += i_record.p_material.eval(i_record, i_wo, photon.direction) * photon.power * (1.0 / cos);
+        }
+        return result * (1.0 / (PI * radius2));
+    }
+
+    // Indices of the i_count photons closest to i_position within i_max_radius, and the squared radius they fit in.
+    // That's i_max_radius if there aren't that many.
+    pub fn nearest(&self, i_position: Vec3, i_count: usize, i_max_radius: Float) -> (Vec<usize>, Float)
+    {
+        // Non negative floats sort the same as their bits, which unlike them are Ord. As f64 whatever Float is.
+        let mut heap: BinaryHeap<(u64, usize)> = BinaryHeap::with_capacity(i_count + 1);
+        let mut radius2 = i_max_radius * i_max_radius;
+
+        self.gather(0, self.photons.len(), i_position, i_count, &mut heap, &mut radius2);
+        return (heap.into_iter().map(|(_, idx)| idx).collect(), radius2);
+    }
+
+    fn gather(&self, i_begin: usize, i_end: usize, i_position: Vec3, i_count: usize, io_heap: &mut BinaryHeap<(u64, usize)>, io_radius2: &mut Float)
+    {
+        if i_begin >= i_end { return; }
+
+        let middle = (i_begin + i_end) / 2;
+        let photon = &self.photons[middle];
+        let offset = coordinate(i_position, self.axes[middle]) - coordinate(photon.position, self.axes[middle]);
+
+        // The side of the splitting plane the position is on first, the other only if the plane is close enough
+        let (near, far) = if offset < 0.0 { ((i_begin, middle), (middle + 1, i_end)) } else { ((middle + 1, i_end), (i_begin, middle)) };
+        self.gather(near.0, near.1, i_position, i_count, io_heap, io_radius2);
+
+        let distance2 = (photon.position - i_position).norm2();
+        if distance2 < *io_radius2
+        {
+            io_heap.push(((distance2 as f64).to_bits(), middle));
+            if io_heap.len() > i_count { io_heap.pop(); }
+            if io_heap.len() == i_count { *io_radius2 = f64::from_bits(io_heap.peek().unwrap().0) as Float; }
+        }
+
+        if offset * offset < *io_radius2 { self.gather(far.0, far.1, i_position, i_count, io_heap, io_radius2); }
+    }
+
+    // Smallest box around all the photons, empty at the origin if there are none
+    fn bounds(i_photons: &[Photon]) -> (Vec3, Vec3)
+    {
+        let first   = i_photons.first().map_or(Vec3::zero(), |photon| photon.position);
+        let mut min = first;
+        let mut max = first;
+        for photon in i_photons
+        {
+            min = Vec3::new(min.x().min(photon.position.x()), min.y().min(photon.position.y()), min.z().min(photon.position.z()));
+            max = Vec3::new(max.x().max(photon.position.x()), max.y().max(photon.position.y()), max.z().max(photon.position.z()));
+        }
+        return (min, max);
+    }
+
+    // Puts the median along the longest side of the bounds in the middle, then does the same for either half
+    fn build(io_photons: &mut [Photon], io_axes: &mut [u8])
+    {
+        if io_photons.is_empty() { return; }
+
+        let (min, max) = Self::bounds(io_photons);
+        let size       = max - min;
+        let axis = if size.x() >= size.y() && size.x() >= size.z() { 0 } else if size.y() >= size.z() { 1 } else { 2 };
+
+        let middle = io_photons.len() / 2;
+        io_photons.select_nth_unstable_by(middle, |a, b| coordinate(a.position, axis).total_cmp(&coordinate(b.position, axis)));
+        io_axes[middle] = axis;
+
+        let (photons_before, photons_after) = io_photons.split_at_mut(middle);
+        let (axes_before, axes_after)       = io_axes.split_at_mut(middle);
+        Self::build(photons_before, axes_before);
+        Self::build(&mut photons_after[1..], &mut axes_after[1..]);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::{ Integrator, mean_and_variance };
+    use super::super::super::ray::Ray;
+    use super::super::path::PathIntegrator;
+    use super::super::super::hittables::{ Intersectionable, quad::Quad };
+    use super::super::super::materials::{ lambertian::LambertianMat, metallic::MetallicMat, light::DiffuseLightMat };
+
+    use std::rc::Rc;
+
+    // A light facing up at a mirror, the floor only sees it in there, and no sky. i_scale times as big.
+    fn light_under_mirror(i_scale: Float) -> RayTracer
+    {
+        let floor  = Rc::new( LambertianMat{ albedo: Vec3::new(0.8, 0.8, 0.8) } );
+        let light  = Rc::new( DiffuseLightMat::new(Vec3::one() * 4.0) );
+        let mirror = Rc::new( MetallicMat::new(0.0, Vec3::one()) );
+        let quad   = |corner: Vec3, u: Vec3, v: Vec3, material| Intersectionable::Quad( Quad::new(corner * i_scale, u * i_scale, v * i_scale, material) );
+
+        let scene = vec![ quad(Vec3::new(-3.0, 0.0, -3.0), Vec3::new(0.0, 0.0, 6.0), Vec3::new(6.0, 0.0, 0.0), floor),
+                          quad(Vec3::new(-0.5, 1.0, -0.5), Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0), light),
+                          quad(Vec3::new(-1.5, 2.0, -1.5), Vec3::new(3.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 3.0), mirror) ];
+        return RayTracer::for_scene(scene, 0.0);
+    }
+
+    // Onto the floor, clear of the shadow of the light's back
+    fn floor_ray(i_scale: Float) -> Ray
+    {
+        return Ray::new(Vec3::new(-3.5, 0.5, 0.0) * i_scale, Vec3::new(2.0, -0.5, 0.0), 0.0);
+    }
+
+    // Path traced with caustics from i_maps photon maps. Each map is off by its own bit of noise, the ray keeps
+    // landing on the same photons.
+    fn render_caustics(i_tracer: &RayTracer, i_ray: Ray, i_maps: u32, i_photons: usize) -> (f64, f64)
+    {
+        return mean_and_variance(i_maps, ||
+        {
+            let integrator = PathIntegrator::default().with_caustics(PhotonMap::trace_caustics(i_tracer, i_photons));
+            (0..200).fold(Vec3::zero(), |sum, _| sum + integrator.compute_ray(i_tracer, i_ray).0) / 200.0
+        });
+    }
+
+    #[test]
+    fn caustics_match_path_tracing()
+    {
+        let tracer = light_under_mirror(1.0);
+
+        // Only what bounced off the mirror, the floor is the only diffuse surface
+        let caustics = PhotonMap::trace_caustics(&tracer, 200000);
+        assert!( !caustics.photons.is_empty() );
+        assert!( caustics.photons.iter().all(|photon| photon.position.y().abs() < 1e-3) );
+
+        let ray = floor_ray(1.0);
+        let (photon, photon_variance) = render_caustics(&tracer, ray, 10, 200000);
+        let (path, path_variance)     = mean_and_variance(200000, || PathIntegrator::default().compute_ray(&tracer, ray).0);
+
+        // Density estimation blurs the caustic a bit, on top of the noise
+        assert!( path > 0.1 );
+        assert!( (photon - path).abs() < 0.05 * path + 5.0 * (photon_variance + path_variance).sqrt(), "{} vs {}", photon, path );
+    }
+
+    #[test]
+    fn caustics_are_the_same_at_any_scale()
+    {
+        // A fixed gather radius finds next to no photons at some scale and blurs everything at another
+        let (unit, unit_variance) = render_caustics(&light_under_mirror(1.0), floor_ray(1.0), 10, 50000);
+
+        for scale in [1e-3, 1e3]
+        {
+            let (scaled, scaled_variance) = render_caustics(&light_under_mirror(scale), floor_ray(scale), 10, 50000);
+            assert!( (scaled - unit).abs() < 5.0 * (unit_variance + scaled_variance).sqrt(), "{} at scale {} vs {}", scaled, scale, unit );
+        }
+    }
+
+    #[test]
+    fn nearest_matches_brute_force()
+    {
+        let photons: Vec<Photon> = (0..500).map(|_| Photon{ position:  Vec3::rand_unit() * 2.0,
+                                                             direction: Vec3::new(0.0, 1.0, 0.0),
+                                                             power:     Vec3::one() }).collect();
+        let map = PhotonMap::new(photons);
+
+        for _ in 0..20
+        {
+            let position = Vec3::rand_unit();
+            let (mut found, radius2) = map.nearest(position, 10, 10.0);
+
+            let mut expected: Vec<usize> = (0..map.photons.len()).collect();
+            expected.sort_by(|a, b| (map.photons[*a].position - position).norm2().total_cmp(&(map.photons[*b].position - position).norm2()));
+            expected.truncate(10);
+
+            found.sort_unstable();
+            let farthest = (map.photons[expected[9]].position - position).norm2();
+            expected.sort_unstable();
+
+            assert_eq!( found, expected );
+            assert_eq!( radius2, farthest );
+        }
+
+        // Nothing that close
+        let (found, radius2) = map.nearest(Vec3::new(100.0, 0.0, 0.0), 10, 0.5);
+        assert!( found.is_empty() );
+        assert_eq!( radius2, 0.25 );
+    }
+}
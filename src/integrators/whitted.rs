@@ -0,0 +1,106 @@
+use super::{ Integrator, to_ray_space, to_film_space, sample_light, is_visible };
+use super::super::ray::Ray;
+use super::super::film::AovSample;
+use super::super::hittables::closest_hit;
+use super::super::ray_tracer::RayTracer;
+use super::super::utils::{ vec3::Vec3, Float };
+
+const MAX_DEPTH: i32 = 8;
+
+// Whitted style recursive ray tracer: mirrors and glass are followed, everything else is lit straight from the lights
+// with a shadow ray, plus the sky above it as an unshadowed ambient term. No indirect light, but fast, and only the
+// soft shadows of area lights are noisy.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct WhittedIntegrator;
+
+impl WhittedIntegrator
+{
+    fn trace(&self, i_tracer: &RayTracer, i_ray: &Ray, i_depth: i32) -> Vec3
+    {
+        if i_depth <= 0 { return Vec3::zero(); }
+
+        let wavelength = i_ray.wavelength;
+        let hit        = match closest_hit(i_tracer.scene(), i_ray, 0.0, Float::INFINITY)
+        {
+            Some((_, hit)) => hit,
+            None           => return to_ray_space(i_tracer.sample_skybox(i_ray), wavelength)
+        };
+
+        let mut result      = to_ray_space(hit.p_material.emitted(&hit), wavelength);
+        let mut attenuation = Vec3::one();
+        let mut scattered   = hit.p_material.scatter(i_ray, &hit, &mut attenuation);
+        scattered.wavelength = wavelength;
+
+        let wo = -i_ray.direction;
+        if hit.p_material.pdf(&hit, wo, scattered.direction) == 0.0
+        {
+            // Specular, there's only the one direction to go
+            if attenuation.norm2() > 0.0 { result += to_ray_space(attenuation, wavelength) * self.trace(i_tracer, &scattered, i_depth - 1); }
+            return result;
+        }
+
+        // A point on a random light, as good as one on each of them
+        if let Some((_, light, pdf_position)) = sample_light(i_tracer)
+        {
+            let offset    = light.position - hit.position;
+            let distance2 = offset.norm2();
+            let wi        = offset.normalized();
+            let cos_light = -light.normal.dot(wi);
+
+            if cos_light > 0.0 && is_visible(i_tracer, &hit, &light, i_ray.time)
+            {
+                let emitted = hit.p_material.eval(&hit, wo, wi) * light.p_material.emitted(&light);
+                result += to_ray_space(emitted, wavelength) * (cos_light / (distance2 * pdf_position));
+            }
+        }
+
+        let normal  = hit.p_material.shading_normal(&hit);
+        let normal  = if normal.dot(wo) < 0.0 { -normal } else { normal };
+        let ambient = hit.p_material.albedo(&hit) * i_tracer.sample_skybox(&Ray::new(hit.position, normal, i_ray.time));
+        return result + to_ray_space(ambient, wavelength);
+    }
+}
+
+impl Integrator for WhittedIntegrator
+{
+    fn compute_ray(&self, i_tracer: &RayTracer, i_ray: Ray) -> (Vec3, AovSample)
+    {
+        let aovs = match closest_hit(i_tracer.scene(), &i_ray, 0.0, Float::INFINITY)
+        {
+            Some((idx, hit)) => i_tracer.first_hit_aovs(&i_ray, idx, &hit),
+            None             => AovSample::background()
+        };
+
+        return (to_film_space(self.trace(i_tracer, &i_ray, MAX_DEPTH), i_ray.wavelength), aovs);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::path::PathIntegrator;
+    use super::super::mean_and_variance;
+    use super::super::super::hittables::{ Intersectionable, quad::Quad };
+    use super::super::super::materials::{ lambertian::LambertianMat, light::DiffuseLightMat };
+
+    use std::rc::Rc;
+
+    #[test]
+    fn direct_light_matches_path_tracing()
+    {
+        // Light can't bounce more than once between a floor and the light above it
+        let floor  = Rc::new( LambertianMat{ albedo: Vec3::new(0.8, 0.5, 0.2) } );
+        let light  = Rc::new( DiffuseLightMat::new(Vec3::one() * 4.0) );
+        let scene  = vec![ Intersectionable::Quad( Quad::new(Vec3::new(-3.0, 0.0, -3.0), Vec3::new(0.0, 0.0, 6.0), Vec3::new(6.0, 0.0, 0.0), floor) ),
+                           Intersectionable::Quad( Quad::new(Vec3::new(-1.0, 1.5, -1.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 2.0), light) ) ];
+        let tracer = RayTracer::for_scene(scene, 0.0);
+
+        let ray = Ray::new(Vec3::new(-2.0, 2.0, 0.0), Vec3::new(2.5, -2.0, 0.0), 0.0);
+        let (whitted, whitted_variance) = mean_and_variance(20000,  || WhittedIntegrator.compute_ray(&tracer, ray).0);
+        let (path, path_variance)       = mean_and_variance(100000, || PathIntegrator::default().compute_ray(&tracer, ray).0);
+
+        assert!( whitted > 0.1 );
+        assert!( (whitted - path).abs() < 5.0 * (whitted_variance + path_variance).sqrt(), "{} vs {}", whitted, path );
+    }
+}
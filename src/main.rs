@@ -1,22 +1,83 @@
+#![allow(clippy::needless_return)]
+#![allow(clippy::unnecessary_cast)] // utils::Float is f32 or f64 depending on the build
+
 mod utils;
 mod ray;
 mod camera;
 mod hittables;
 mod materials;
+mod textures;
+mod media;
+mod integrators;
 mod scene_generator;
+mod film;
+mod denoiser;
+mod animation;
+mod options;
 mod ray_tracer;
 
-use ray_tracer::RayTracer;
+use animation::{ CameraPath, FrameRange };
+use options::Options;
+use ray_tracer::{ RayTracer, CAMERA_POSITION, CAMERA_TARGET };
+use utils::Float;
+use std::rc::Rc;
 use std::time::Instant;
 
 fn main()
 {
-    let raytracer = RayTracer::new();
-    let start     = Instant::now();
+    let options       = Options::from_args( std::env::args().skip(1) );
+    let mut raytracer = RayTracer::new(&options);
+
+    match &options.camera_path
+    {
+        None       => render_frame(&raytracer, &options.output, options.write_aovs),
+        Some(path) =>
+        {
+            let frames = options.frames;
+            let path   = if path == "turntable"
+            {
+                let duration = (frames.last + 1) as Float / frames.fps;
+                CameraPath::turntable(CAMERA_TARGET, CAMERA_POSITION, options.fov, duration, options.interpolation)
+            }
+            else
+            {
+                match CameraPath::from_file(path, options.interpolation)
+                {
+                    Ok(path) => path,
+                    Err(e)   => { println!("ERROR: Invalid camera path. {}", e); return; }
+                }
+            };
+
+            if frames.frame_time(frames.last) > path.duration()
+            {
+                println!("WARNING: The last frames go past the end of the camera path");
+            }
+            let path = Rc::new(path);
+
+            for frame in frames.first..=frames.last
+            {
+                let (t0, t1) = frames.shutter_interval(frame);
+                raytracer.set_camera_path( path.clone(), t0, t1 );
 
-    let image     = raytracer.render();
+                println!("Frame {} / {}", frame, frames.last);
+                render_frame(&raytracer, &FrameRange::file_name(&options.output, frame), options.write_aovs);
+            }
+        }
+    }
+}
+
+fn render_frame(i_raytracer: &RayTracer, i_output: &str, i_write_aovs: bool)
+{
+    let start = Instant::now();
+    let film  = i_raytracer.render();
 
     println!("RENDER TIME: {} ms", start.elapsed().as_millis());
 
-    image.to_file("out.ppm");
+    film.to_image().to_file(i_output);
+
+    if i_write_aovs
+    {
+        let prefix = i_output.trim_end_matches(".ppm");
+        film.write_aovs(prefix);
+    }
 }
@@ -0,0 +1,239 @@
+use super::Material;
+use super::microfacet::{ self, Microfacet, Distribution };
+use super::super::ray::Ray;
+use super::super::utils::{ vec3::Vec3, Float };
+use super::super::hittables::hit_record::HitRecord;
+
+use std::rc::Rc;
+
+// A thin dielectric layer, like varnish or a clear coat, over any other material.
+// The coat reflects part of the light depending on the angle, what gets through lights the base
+// and has to cross the coat again on its way out. Its thickness is ignored.
+#[derive(Clone, Debug)]
+pub struct CoatedMat
+{
+    pub base:       Rc<dyn Material>,
+    refraction_idx: Float,
+    microfacet:     Microfacet,
+    tint:           Vec3 // Left after crossing the coat down and back up head-on
+}
+
+impl CoatedMat
+{
+    pub fn new(base: Rc<dyn Material>, refraction_idx: Float) -> Self
+    {
+        Self{ base,
+              refraction_idx,
+              microfacet: Microfacet::from_roughness(Distribution::Ggx, 0.0),
+              tint:       Vec3::one() }
+    }
+
+    pub fn with_roughness(mut self, roughness: Float) -> Self
+    {
+        self.microfacet = Microfacet::from_roughness(Distribution::Ggx, roughness);
+        return self;
+    }
+
+    pub fn with_tint(mut self, tint: Vec3) -> Self
+    {
+        self.tint = tint;
+        return self;
+    }
+
+    fn fresnel(&self, cos: Float) -> Float
+    {
+        return microfacet::fresnel_dielectric(cos, 1.0 / self.refraction_idx);
+    }
+
+    // Through the coat and back, at the angles the light refracts to inside it
+    fn transmittance(&self, cos_o: Float, cos_i: Float) -> Vec3
+    {
+        if self.tint == Vec3::one() { return Vec3::one(); }
+
+        let inside = |cos: Float| (1.0 - (1.0 - cos * cos) / (self.refraction_idx * self.refraction_idx)).sqrt();
+        let path   = 0.5 / inside(cos_o) + 0.5 / inside(cos_i.abs());
+
+        return Vec3::new(self.tint.r().powf(path), self.tint.g().powf(path), self.tint.b().powf(path));
+    }
+}
+
+impl Material for CoatedMat
+{
+    fn scatter(&self,
+               i_ray: &Ray,
+               i_record: &HitRecord,
+               o_attenuation: &mut Vec3) -> Ray
+    {
+        // Only coated on the outside
+        if !i_record.front_face { return self.base.scatter(i_ray, i_record, o_attenuation); }
+
+        let normal = i_record.shading_normal;
+        let wo     = -i_ray.direction.normalized();
+        let cos_o  = wo.dot(normal).max(0.0);
+
+        // Reflected by the coat as often as its Fresnel says, so that term cancels out
+        let coat_p = self.fresnel(cos_o);
+        if rand::random::<Float>() < coat_p
+        {
+            if self.microfacet.is_smooth()
+            {
+                *o_attenuation = Vec3::one();
+                return i_record.spawn_ray(i_ray.direction.reflect(normal), i_ray.time);
+            }
+
+            let h         = self.microfacet.sample_h(normal);
+            let reflected = i_ray.direction.normalized().reflect(h);
+            let cos_i     = reflected.dot(normal);
+
+            *o_attenuation = if cos_i <= 0.0 || wo.dot(h) <= 0.0 { Vec3::zero() }
+                             else
+                             {
+                                 Vec3::one() * (self.fresnel(wo.dot(h)) / coat_p
+                                                * self.microfacet.g(cos_o, cos_i) * wo.dot(h) / (cos_o * h.dot(normal)))
+                             };
+            return i_record.spawn_ray(reflected, i_ray.time);
+        }
+
+        let mut attenuation = Vec3::zero();
+        let scattered = self.base.scatter(i_ray, i_record, &mut attenuation);
+        let cos_i     = scattered.direction.normalized().dot(normal);
+
+        // What made it in also has to leave through the coat
+        let exit = if cos_i > 0.0 { 1.0 - self.fresnel(cos_i) } else { 1.0 };
+        *o_attenuation = attenuation * self.transmittance(cos_o, cos_i) * exit;
+
+        return scattered;
+    }
+
+    fn albedo(&self, i_record: &HitRecord) -> Vec3 { self.base.albedo(i_record) * self.tint }
+
+    fn opacity(&self, i_record: &HitRecord) -> Float { self.base.opacity(i_record) }
+
+    fn has_cutouts(&self) -> bool { self.base.has_cutouts() }
+
+    fn eval(&self, i_record: &HitRecord, i_wo: Vec3, i_wi: Vec3) -> Vec3
+    {
+        let base = self.base.eval(i_record, i_wo, i_wi);
+        if !i_record.front_face { return base; }
+
+        let normal = i_record.shading_normal;
+        let cos_o  = i_wo.dot(normal);
+        let cos_i  = i_wi.dot(normal);
+        if cos_o <= 0.0 { return Vec3::zero(); }
+
+        let exit       = if cos_i > 0.0 { 1.0 - self.fresnel(cos_i) } else { 1.0 };
+        let mut result = base * self.transmittance(cos_o, cos_i) * ((1.0 - self.fresnel(cos_o)) * exit);
+
+        if !self.microfacet.is_smooth() && cos_i > 0.0
+        {
+            let h  = (i_wo + i_wi).normalized();
+            result += Vec3::one() * (self.fresnel(i_wo.dot(h)) * self.microfacet.d(h.dot(normal))
+                                     * self.microfacet.g(cos_o, cos_i) / (4.0 * cos_o));
+        }
+        return result;
+    }
+
+    fn pdf(&self, i_record: &HitRecord, i_wo: Vec3, i_wi: Vec3) -> Float
+    {
+        let base = self.base.pdf(i_record, i_wo, i_wi);
+        if !i_record.front_face { return base; }
+
+        let normal = i_record.shading_normal;
+        let cos_o  = i_wo.dot(normal);
+        if cos_o <= 0.0 { return 0.0; }
+
+        let coat_p = self.fresnel(cos_o);
+        let mut result = base * (1.0 - coat_p);
+
+        if !self.microfacet.is_smooth() && i_wi.dot(normal) > 0.0
+        {
+            let h  = (i_wo + i_wi).normalized();
+            result += coat_p * self.microfacet.pdf_h(h.dot(normal)) / (4.0 * i_wo.dot(h));
+        }
+        return result;
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::lambertian::LambertianMat;
+    use super::super::super::utils::{ rand_in_range, TAU };
+    use float_cmp::approx_eq;
+
+    fn new_record(material: &CoatedMat) -> HitRecord
+    {
+        HitRecord::upwards(Vec3::zero(), Rc::new(material.clone()))
+    }
+
+    fn direction(cos_theta: Float, phi: Float) -> Vec3
+    {
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        return Vec3::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+    }
+
+    fn average_attenuation(material: &CoatedMat, wo: Vec3, samples: u32) -> Vec3
+    {
+        let record  = new_record(material);
+        let ray     = Ray::new(wo, -wo, 0.0);
+        let mut sum = Vec3::zero();
+        for _ in 0..samples
+        {
+            let mut attenuation = Vec3::zero();
+            material.scatter(&ray, &record, &mut attenuation);
+            sum += attenuation;
+        }
+        return sum / samples as Float;
+    }
+
+    #[test]
+    fn coat_over_black_reflects_fresnel()
+    {
+        let black  = Rc::new( LambertianMat{ albedo: Vec3::zero() } );
+        let coated = CoatedMat::new(black, 1.5);
+
+        for cos_o in &[1.0, 0.5, 0.1]
+        {
+            let reflected = average_attenuation(&coated, direction(*cos_o, 0.0), 20000).x();
+            let expected  = microfacet::fresnel_dielectric(*cos_o, 1.0 / 1.5);
+            assert!( approx_eq!(Float, reflected, expected, epsilon = 0.02), "{} vs {}", reflected, expected );
+        }
+    }
+
+    #[test]
+    fn energy_is_bounded()
+    {
+        let white = Rc::new( LambertianMat{ albedo: Vec3::one() } );
+        for coated in &[CoatedMat::new(white.clone(), 1.5), CoatedMat::new(white.clone(), 1.5).with_roughness(0.4)]
+        {
+            for cos_o in &[1.0, 0.5, 0.1]
+            {
+                let albedo = average_attenuation(coated, direction(*cos_o, 0.0), 20000).x();
+                assert!( albedo <= 1.01, "{}", albedo );
+            }
+        }
+    }
+
+    #[test]
+    fn eval_matches_sampling()
+    {
+        let base   = Rc::new( LambertianMat{ albedo: Vec3::new(0.8, 0.4, 0.2) } );
+        let coated = CoatedMat::new(base, 1.5).with_roughness(0.5).with_tint(Vec3::new(0.9, 0.8, 0.5));
+        let record = new_record(&coated);
+        let wo     = direction(0.6, 0.0);
+
+        let samples = 100000;
+        let mut integrated = 0.0;
+        for _ in 0..samples
+        {
+            // Uniformly over the hemisphere
+            let wi      = direction(rand::random::<Float>(), rand_in_range(0.0, TAU));
+            integrated += coated.eval(&record, wo, wi).x() * TAU;
+        }
+        let integrated = integrated / samples as Float;
+        let sampled    = average_attenuation(&coated, wo, samples).x();
+
+        assert!( approx_eq!(Float, sampled, integrated, epsilon = 0.02), "{} vs {}", sampled, integrated );
+    }
+}
@@ -3,6 +3,7 @@ use super::super::ray::Ray;
 use super::super::utils::{ vec3::Vec3, rand_point_in_unit_hemisphere };
 use super::super::hittables::hit_record::HitRecord;
 
+#[allow(dead_code)]
 #[derive(Copy, Clone, Debug)]
 pub struct DebugMat;
 
@@ -13,8 +14,10 @@ impl Material for DebugMat
                i_record: &HitRecord,
                o_attenuation: &mut Vec3) -> Ray
     {
-        let scatter_dir = rand_point_in_unit_hemisphere(i_record.normal);
+        let scatter_dir = rand_point_in_unit_hemisphere(i_record.shading_normal);
         *o_attenuation  = Vec3::debug_color();
-        return Ray::new(i_record.position, scatter_dir, i_ray.time);
+        return i_record.spawn_ray(scatter_dir, i_ray.time);
     }
+
+    fn albedo(&self, _i_record: &HitRecord) -> Vec3 { Vec3::debug_color() }
 }
\ No newline at end of file
@@ -1,25 +1,174 @@
 use super::Material;
+use super::microfacet::{ self, Microfacet, Distribution };
 use super::super::ray::Ray;
-use super::super::utils::vec3::Vec3;
+use super::super::utils::{ vec3::Vec3, Float };
 use super::super::hittables::hit_record::HitRecord;
+use super::super::media::Medium;
+
+// Wavelength dependent index of refraction, only used by rays that carry a wavelength
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug)]
+pub enum Dispersion
+{
+    None,
+    Cauchy{ a: Float, b: Float },             // n = A + B/λ², λ in micrometers
+    Sellmeier{ b: [Float; 3], c: [Float; 3] } // n² = 1 + Σ Bi·λ²/(λ² - Ci), λ in micrometers
+}
+
+impl Dispersion
+{
+    // Borosilicate crown glass
+    #[allow(dead_code)]
+    pub fn bk7() -> Self
+    {
+        Self::Sellmeier{ b: [1.039_612, 0.231_792_34, 1.010_469_5],
+                         c: [0.006_000_699, 0.020_017_914, 103.560_65] }
+    }
+
+    // Dense flint glass, disperses much more than crown glass
+    pub fn sf11() -> Self
+    {
+        Self::Sellmeier{ b: [1.737_596_9, 0.313_747_35, 1.898_781],
+                         c: [0.013_188_707, 0.062_306_814, 155.2363] }
+    }
+}
 
 #[derive(Copy, Clone, Debug)]
 pub struct DielectricMat
 {
-    refraction_idx: f32,
+    refraction_idx: Float,
+    dispersion: Dispersion,
+    absorption: Option<Vec3>, // Per unit of distance travelled inside
+    microfacet: Microfacet,   // Frosted glass when rough
     albedo: Vec3
 }
 
 impl DielectricMat
 {
-    pub fn new(refraction_idx: f32, albedo: Vec3) -> Self { Self{refraction_idx, albedo} }
+    pub fn new(refraction_idx: Float, albedo: Vec3) -> Self
+    {
+        Self{refraction_idx, dispersion: Dispersion::None, absorption: None, microfacet: Self::smooth(), albedo}
+    }
+
+    // Colored glass: light that travels i_distance inside ends up multiplied by i_color
+    pub fn tinted(refraction_idx: Float, i_color: Vec3, i_distance: Float) -> Self
+    {
+        let absorption = Vec3::new(-i_color.r().max(Float::MIN_POSITIVE).ln(),
+                                   -i_color.g().max(Float::MIN_POSITIVE).ln(),
+                                   -i_color.b().max(Float::MIN_POSITIVE).ln()) / i_distance;
+
+        Self::new(refraction_idx, Vec3::one()).with_absorption(absorption)
+    }
+
+    pub fn with_absorption(mut self, absorption: Vec3) -> Self
+    {
+        self.absorption = Some(absorption);
+        return self;
+    }
+
+    pub fn with_dispersion(dispersion: Dispersion, albedo: Vec3) -> Self
+    {
+        // Sodium D line, what a single refraction index usually refers to
+        let refraction_idx = Self::dispersed_idx(&dispersion, 589.3).unwrap_or(1.5);
+        Self{refraction_idx, dispersion, absorption: None, microfacet: Self::smooth(), albedo}
+    }
+
+    pub fn with_roughness(mut self, roughness: Float) -> Self
+    {
+        self.microfacet = Microfacet::from_roughness(Distribution::Ggx, roughness);
+        return self;
+    }
 
-    fn schlick_approx(cosine: f32, refraction_idx: f32) -> f32
+    fn smooth() -> Microfacet { Microfacet::from_roughness(Distribution::Ggx, 0.0) }
+
+    pub fn refraction_idx_at(&self, wavelength: Float) -> Float
+    {
+        if wavelength <= 0.0 { return self.refraction_idx; }
+        return Self::dispersed_idx(&self.dispersion, wavelength).unwrap_or(self.refraction_idx);
+    }
+
+    fn dispersed_idx(dispersion: &Dispersion, wavelength: Float) -> Option<Float>
+    {
+        let l  = wavelength * 0.001; // nm to μm
+        let l2 = l * l;
+
+        match dispersion
+        {
+            Dispersion::None             => None,
+            Dispersion::Cauchy{ a, b }   => Some(a + b / l2),
+            Dispersion::Sellmeier{ b, c } =>
+            {
+                let n2 = 1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<Float>();
+                Some(n2.sqrt())
+            }
+        }
+    }
+
+    fn schlick_approx(cosine: Float, refraction_idx: Float) -> Float
     {
         let mut r0 = (1.0 - refraction_idx) / (1.0 + refraction_idx);
         r0 = r0*r0;
         return r0 + (1.0 - r0) * (1.0 - cosine).powi(5);
     }
+
+    // Relative index of refraction and normal, both as seen from the side i_wo is on
+    fn interface(&self, i_record: &HitRecord, wavelength: Float) -> (Float, Vec3)
+    {
+        let refraction_idx = self.refraction_idx_at(wavelength);
+        let eta = if i_record.front_face { 1.0 / refraction_idx } else { refraction_idx };
+        return (eta, i_record.facing_normal());
+    }
+
+    // Rough interface from Walter et al. 2007, "Microfacet Models for Refraction through Rough Surfaces".
+    // Picks reflection or refraction through a sampled microfacet proportionally to its Fresnel term.
+    fn scatter_rough(&self, i_ray: &Ray, i_record: &HitRecord, o_attenuation: &mut Vec3) -> Ray
+    {
+        let (eta, normal) = self.interface(i_record, i_ray.wavelength);
+
+        let input_ray_dir = i_ray.direction.normalized();
+        let wo       = -input_ray_dir;
+        let cos_o    = wo.dot(normal);
+        let h        = self.microfacet.sample_h(normal);
+        let wo_dot_h = wo.dot(h);
+
+        if wo_dot_h <= 0.0 || cos_o <= 0.0
+        {
+            *o_attenuation = Vec3::zero();
+            return i_record.spawn_ray(input_ray_dir.reflect(normal), i_ray.time);
+        }
+
+        let reflect = rand::random::<Float>() < microfacet::fresnel_dielectric(wo_dot_h, eta);
+        let resulting_ray_dir = if reflect { input_ray_dir.reflect(h) }
+                                else       { input_ray_dir.refract(h, eta) };
+
+        // Reflections have to stay on this side, refractions have to cross it
+        let cos_i = resulting_ray_dir.dot(normal);
+        if (reflect && cos_i <= 0.0) || (!reflect && cos_i >= 0.0)
+        {
+            *o_attenuation = Vec3::zero();
+            return i_record.spawn_ray(resulting_ray_dir, i_ray.time);
+        }
+
+        // The Fresnel terms cancel out with the probability of picking each lobe
+        let weight = self.microfacet.g(cos_o, cos_i) * wo_dot_h / (cos_o * h.dot(normal));
+        *o_attenuation = self.albedo * weight;
+
+        return i_record.spawn_ray(resulting_ray_dir, i_ray.time);
+    }
+
+    // Microfacet normal that turns i_wo into i_wi, on the same side as the normal.
+    // None when no microfacet can do it.
+    fn half_vector(i_wo: Vec3, i_wi: Vec3, eta: Float, normal: Vec3) -> Option<Vec3>
+    {
+        let reflection = i_wi.dot(normal) > 0.0;
+        let h = if reflection { i_wo + i_wi } else { -(i_wo * eta + i_wi) };
+        if h.norm2() == 0.0 { return None; }
+
+        let h = if h.dot(normal) < 0.0 { -h.normalized() } else { h.normalized() };
+        if i_wo.dot(h) <= 0.0 || (!reflection && i_wi.dot(h) >= 0.0) { return None; }
+
+        return Some(h);
+    }
 }
 
 impl Material for DielectricMat
@@ -29,26 +178,166 @@ impl Material for DielectricMat
                i_record: &HitRecord,
                o_attenuation: &mut Vec3) -> Ray
     {
-        let eta = if i_record.front_face { 1.0 / self.refraction_idx }
-                  else { self.refraction_idx };
+        if !self.microfacet.is_smooth() { return self.scatter_rough(i_ray, i_record, o_attenuation); }
+
+        let refraction_idx = self.refraction_idx_at(i_ray.wavelength);
+        let eta = if i_record.front_face { 1.0 / refraction_idx }
+                  else { refraction_idx };
+
+        // Refraction expects the normal on the same side as the incoming ray
+        let normal = i_record.facing_normal();
 
         let input_ray_dir = i_ray.direction.normalized();
 
-        let candidate = -input_ray_dir.dot(i_record.normal);
+        let candidate = -input_ray_dir.dot(normal);
         let cos_theta = if candidate < 1.0 { candidate } else { 1.0 }; // std::cmp::min doesn't work with floats
         let sin_theta = (1.0 - cos_theta*cos_theta).sqrt();
 
         let reflect_prob = Self::schlick_approx(cos_theta, eta);
 
-        let resulting_ray_dir = if eta * sin_theta > 1.0 || rand::random::<f32>() < reflect_prob {
-                                    input_ray_dir.reflect(i_record.normal)
+        let resulting_ray_dir = if eta * sin_theta > 1.0 || rand::random::<Float>() < reflect_prob {
+                                    input_ray_dir.reflect(normal)
                                 } else {
-                                    input_ray_dir.refract(i_record.normal, eta)
+                                    input_ray_dir.refract(normal, eta)
                                 };
 
         *o_attenuation = self.albedo;
-        return Ray::new(i_record.position, resulting_ray_dir, i_ray.time);
+        return i_record.spawn_ray(resulting_ray_dir, i_ray.time);
+    }
+
+    fn albedo(&self, _i_record: &HitRecord) -> Vec3 { self.albedo }
+
+    // Transmission leaves out the eta² radiance scaling, the same way scatter does
+    fn eval(&self, i_record: &HitRecord, i_wo: Vec3, i_wi: Vec3) -> Vec3
+    {
+        if self.microfacet.is_smooth() { return Vec3::zero(); }
+
+        let (eta, normal) = self.interface(i_record, 0.0);
+        let cos_o = i_wo.dot(normal);
+        let cos_i = i_wi.dot(normal);
+        if cos_o <= 0.0 { return Vec3::zero(); }
+
+        let h = match Self::half_vector(i_wo, i_wi, eta, normal) { Some(h) => h, None => return Vec3::zero() };
+        let d = self.microfacet.d(h.dot(normal));
+        let g = self.microfacet.g(cos_o, cos_i);
+        let f = microfacet::fresnel_dielectric(i_wo.dot(h), eta);
+
+        if cos_i > 0.0 { return self.albedo * (f * d * g / (4.0 * cos_o)); }
+
+        let denom = eta * i_wo.dot(h) + i_wi.dot(h);
+        return self.albedo * ((1.0 - f) * d * g * i_wo.dot(h) * i_wi.dot(h).abs() / (cos_o * denom * denom));
     }
+
+    fn pdf(&self, i_record: &HitRecord, i_wo: Vec3, i_wi: Vec3) -> Float
+    {
+        if self.microfacet.is_smooth() { return 0.0; }
+
+        let (eta, normal) = self.interface(i_record, 0.0);
+        if i_wo.dot(normal) <= 0.0 { return 0.0; }
+
+        let h = match Self::half_vector(i_wo, i_wi, eta, normal) { Some(h) => h, None => return 0.0 };
+        let pdf_h = self.microfacet.pdf_h(h.dot(normal));
+        let f     = microfacet::fresnel_dielectric(i_wo.dot(h), eta);
+
+        if i_wi.dot(normal) > 0.0 { return f * pdf_h / (4.0 * i_wo.dot(h)); }
+
+        let denom = eta * i_wo.dot(h) + i_wi.dot(h);
+        return (1.0 - f) * pdf_h * i_wi.dot(h).abs() / (denom * denom);
+    }
+
+    fn medium(&self) -> Option<Medium> { self.absorption.map(Medium::absorbing) }
 }
 
-// TODO: tests
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use float_cmp::approx_eq;
+
+    #[test]
+    fn sellmeier_matches_catalog()
+    {
+        let bk7 = DielectricMat::with_dispersion(Dispersion::bk7(), Vec3::one());
+
+        // Schott catalog values
+        assert!( approx_eq!(Float, bk7.refraction_idx_at(587.6), 1.5168, epsilon = 0.0005) );
+        assert!( approx_eq!(Float, bk7.refraction_idx_at(486.1), 1.5224, epsilon = 0.0005) );
+        assert!( approx_eq!(Float, bk7.refraction_idx_at(656.3), 1.5143, epsilon = 0.0005) );
+    }
+
+    #[test]
+    fn cauchy()
+    {
+        let glass = DielectricMat::with_dispersion(Dispersion::Cauchy{ a: 1.5, b: 0.004 }, Vec3::one());
+
+        assert!( approx_eq!(Float, glass.refraction_idx_at(500.0), 1.516, epsilon = 0.0001) );
+        assert!( glass.refraction_idx_at(400.0) > glass.refraction_idx_at(700.0) );
+    }
+
+    #[test]
+    fn tinted()
+    {
+        let glass        = DielectricMat::tinted(1.5, Vec3::new(0.5, 0.25, 1.0), 2.0);
+        let transmitted  = (-glass.medium().unwrap().absorption * 2.0).exp();
+
+        assert_eq!( transmitted, Vec3::new(0.5, 0.25, 1.0) );
+        assert!( DielectricMat::new(1.5, Vec3::one()).medium().is_none() );
+    }
+
+    #[test]
+    fn rgb_rays_ignore_dispersion()
+    {
+        let glass = DielectricMat::with_dispersion(Dispersion::sf11(), Vec3::one());
+        let plain = DielectricMat::new(1.5, Vec3::one());
+
+        assert_eq!( glass.refraction_idx_at(0.0), glass.refraction_idx );
+        assert_eq!( plain.refraction_idx_at(450.0), 1.5 );
+    }
+
+    #[test]
+    fn rough_glass()
+    {
+        use super::super::super::utils::{ rand_in_range, TAU };
+        use std::rc::Rc;
+
+        let glass  = DielectricMat::new(1.5, Vec3::one()).with_roughness(0.8);
+        let record = HitRecord::upwards(Vec3::zero(), Rc::new(glass));
+
+        let wo  = Vec3::new(0.6, 0.8, 0.0);
+        let ray = Ray::new(wo, -wo, 0.0);
+
+        let samples = 1000000;
+        let mut sampled     = 0.0f64;
+        let mut sampled2    = 0.0f64;
+        let mut transmitted = 0;
+        let mut integrated  = 0.0f64;
+        let mut integrated2 = 0.0f64;
+        for _ in 0..samples
+        {
+            let mut attenuation = Vec3::zero();
+            let scattered = glass.scatter(&ray, &record, &mut attenuation);
+            sampled  += attenuation.x() as f64;
+            sampled2 += (attenuation.x() * attenuation.x()) as f64;
+            if attenuation.x() > 0.0 && scattered.direction.y() < 0.0 { transmitted += 1; }
+
+            // Uniformly over the sphere
+            let cos_theta = rand_in_range(-1.0, 1.0);
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+            let phi       = rand_in_range(0.0, TAU);
+            let wi        = Vec3::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+            let value     = (glass.eval(&record, wo, wi).x() * 2.0 * TAU) as f64;
+            integrated   += value;
+            integrated2  += value * value;
+        }
+
+        let sampled    = sampled    / samples as f64;
+        let integrated = integrated / samples as f64;
+
+        // Both are means of independent samples, five standard errors apart almost never happens by chance
+        let variance = (sampled2 / samples as f64 - sampled * sampled + integrated2 / samples as f64 - integrated * integrated) / samples as f64;
+
+        assert!( sampled <= 1.01 );
+        assert!( transmitted > samples / 2 );
+        assert!( (sampled - integrated).abs() < 5.0 * variance.sqrt(), "{} vs {}", sampled, integrated );
+    }
+}
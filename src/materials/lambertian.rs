@@ -1,6 +1,6 @@
 use super::Material;
 use super::super::ray::Ray;
-use super::super::utils::vec3::Vec3;
+use super::super::utils::{ vec3::Vec3, PI, Float };
 use super::super::hittables::hit_record::HitRecord;
 
 #[derive(Copy, Clone, Debug)]
@@ -16,8 +16,23 @@ impl Material for LambertianMat
                i_record: &HitRecord,
                o_attenuation: &mut Vec3) -> Ray
     {
-        let scatter_dir = i_record.normal + Vec3::rand_unit();
-        *o_attenuation = self.albedo;
-        return Ray::new(i_record.position, scatter_dir, i_ray.time);
+        let scatter_dir = i_record.shading_normal + Vec3::rand_unit();
+        // Under the actual surface it would come out the other side
+        *o_attenuation = if i_record.is_consistent(scatter_dir) { self.albedo } else { Vec3::zero() };
+        return i_record.spawn_ray(scatter_dir, i_ray.time);
+    }
+
+    fn albedo(&self, _i_record: &HitRecord) -> Vec3 { self.albedo }
+
+    fn eval(&self, i_record: &HitRecord, i_wo: Vec3, i_wi: Vec3) -> Vec3
+    {
+        if !i_record.is_consistent(i_wi) { return Vec3::zero(); }
+        return self.albedo * self.pdf(i_record, i_wo, i_wi);
+    }
+
+    // scatter() picks directions proportionally to the cosine
+    fn pdf(&self, i_record: &HitRecord, _i_wo: Vec3, i_wi: Vec3) -> Float
+    {
+        return i_wi.dot(i_record.shading_normal).max(0.0) / PI;
     }
 }
\ No newline at end of file
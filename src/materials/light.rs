@@ -0,0 +1,41 @@
+use super::Material;
+use super::super::ray::Ray;
+use super::super::utils::vec3::Vec3;
+use super::super::hittables::hit_record::HitRecord;
+
+// Area light giving off the same radiance in every direction, from its front side only.
+// It reflects nothing, light arriving at it ends there.
+#[derive(Copy, Clone, Debug)]
+pub struct DiffuseLightMat
+{
+    pub emission: Vec3
+}
+
+impl DiffuseLightMat
+{
+    pub fn new(emission: Vec3) -> Self
+    {
+        Self{ emission }
+    }
+}
+
+impl Material for DiffuseLightMat
+{
+    fn scatter(&self,
+               i_ray: &Ray,
+               i_record: &HitRecord,
+               o_attenuation: &mut Vec3) -> Ray
+    {
+        *o_attenuation = Vec3::zero();
+        return i_record.spawn_ray(i_ray.direction, i_ray.time);
+    }
+
+    fn albedo(&self, _i_record: &HitRecord) -> Vec3 { Vec3::zero() }
+
+    fn emitted(&self, i_record: &HitRecord) -> Vec3
+    {
+        if i_record.front_face { self.emission } else { Vec3::zero() }
+    }
+
+    fn is_emissive(&self) -> bool { true }
+}
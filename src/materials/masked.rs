@@ -0,0 +1,57 @@
+use super::Material;
+use super::super::ray::Ray;
+use super::super::utils::{ vec3::Vec3, Float };
+use super::super::hittables::hit_record::HitRecord;
+use super::super::media::Medium;
+use super::super::textures::Texture;
+
+use std::rc::Rc;
+
+// Cuts holes into another material, for leaves, fences and the like.
+// The holes are skipped while intersecting, so rays never scatter off them.
+#[derive(Clone, Debug)]
+pub struct MaskedMat
+{
+    pub base: Rc<dyn Material>,
+    pub mask: Rc<dyn Texture> // Opacity, only the red channel is used
+}
+
+impl MaskedMat
+{
+    pub fn new(base: Rc<dyn Material>, mask: Rc<dyn Texture>) -> Self
+    {
+        Self{ base, mask }
+    }
+}
+
+impl Material for MaskedMat
+{
+    fn scatter(&self,
+               i_ray: &Ray,
+               i_record: &HitRecord,
+               o_attenuation: &mut Vec3) -> Ray
+    {
+        return self.base.scatter(i_ray, i_record, o_attenuation);
+    }
+
+    fn albedo(&self, i_record: &HitRecord) -> Vec3 { self.base.albedo(i_record) }
+
+    fn eval(&self, i_record: &HitRecord, i_wo: Vec3, i_wi: Vec3) -> Vec3 { self.base.eval(i_record, i_wo, i_wi) }
+
+    fn pdf(&self, i_record: &HitRecord, i_wo: Vec3, i_wi: Vec3) -> Float { self.base.pdf(i_record, i_wo, i_wi) }
+
+    fn shading_normal(&self, i_record: &HitRecord) -> Vec3 { self.base.shading_normal(i_record) }
+
+    fn medium(&self) -> Option<Medium> { self.base.medium() }
+
+    fn emitted(&self, i_record: &HitRecord) -> Vec3 { self.base.emitted(i_record) }
+
+    fn is_emissive(&self) -> bool { self.base.is_emissive() }
+
+    fn opacity(&self, i_record: &HitRecord) -> Float
+    {
+        return self.mask.value(i_record.uv, i_record.position).x() * self.base.opacity(i_record);
+    }
+
+    fn has_cutouts(&self) -> bool { true }
+}
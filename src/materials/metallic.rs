@@ -1,21 +1,72 @@
 use super::Material;
+use super::microfacet::{ self, Microfacet, Distribution };
 use super::super::ray::Ray;
-use super::super::{utils, utils::vec3::Vec3};
+use super::super::utils::{ vec3::Vec3, Float };
 use super::super::hittables::hit_record::HitRecord;
 
+#[derive(Copy, Clone, Debug)]
+pub enum Fresnel
+{
+    Schlick(Vec3),                  // Color at normal incidence
+    Conductor{ eta: Vec3, k: Vec3 } // Complex index of refraction per color channel
+}
+
+// Cook-Torrance microfacet BRDF
 #[derive(Copy, Clone, Debug)]
 pub struct MetallicMat
 {
-    roughness: f32,
-    albedo: Vec3
+    microfacet: Microfacet,
+    fresnel: Fresnel
 }
 
 impl MetallicMat
 {
-    pub fn new(roughness: f32, albedo: Vec3) -> Self
+    pub fn new(roughness: Float, albedo: Vec3) -> Self
+    {
+        Self { microfacet: Microfacet::from_roughness(Distribution::Ggx, roughness),
+               fresnel: Fresnel::Schlick(albedo) }
+    }
+
+    pub fn conductor(roughness: Float, eta: Vec3, k: Vec3) -> Self
+    {
+        Self { microfacet: Microfacet::from_roughness(Distribution::Ggx, roughness),
+               fresnel: Fresnel::Conductor{ eta, k } }
+    }
+
+    // Spectral data sampled at 650, 550 and 450nm
+    pub fn gold(roughness: Float) -> Self
     {
-        let roughness = if roughness > 1.0 { 1.0 } else if roughness < 0.0 { 0.0 } else { roughness };
-        Self { roughness, albedo }
+        Self::conductor(roughness, Vec3::new(0.143, 0.374, 1.442), Vec3::new(3.983, 2.385, 1.603))
+    }
+
+    pub fn copper(roughness: Float) -> Self
+    {
+        Self::conductor(roughness, Vec3::new(0.200, 0.924, 1.102), Vec3::new(3.912, 2.452, 2.142))
+    }
+
+    pub fn silver(roughness: Float) -> Self
+    {
+        Self::conductor(roughness, Vec3::new(0.155, 0.117, 0.138), Vec3::new(4.828, 3.122, 2.147))
+    }
+
+    pub fn aluminium(roughness: Float) -> Self
+    {
+        Self::conductor(roughness, Vec3::new(1.657, 0.880, 0.521), Vec3::new(9.224, 6.270, 4.837))
+    }
+
+    pub fn with_distribution(mut self, distribution: Distribution) -> Self
+    {
+        self.microfacet.distribution = distribution;
+        return self;
+    }
+
+    fn fresnel(&self, cos_i: Float) -> Vec3
+    {
+        match self.fresnel
+        {
+            Fresnel::Schlick(f0)           => microfacet::fresnel_schlick(cos_i, f0),
+            Fresnel::Conductor{ eta, k }   => microfacet::fresnel_conductor(cos_i, eta, k)
+        }
     }
 }
 
@@ -26,13 +77,191 @@ impl Material for MetallicMat
                i_record: &HitRecord,
                o_attenuation: &mut Vec3) -> Ray
     {
-        let reflected  = i_ray.direction
-                             .normalized()
-                             .reflect( i_record.normal );
+        let normal = i_record.facing_normal();
+        let wo     = -i_ray.direction.normalized();
+        let cos_o  = wo.dot(normal);
+
+        if self.microfacet.is_smooth()
+        {
+            *o_attenuation = self.fresnel(cos_o);
+            return i_record.spawn_ray(i_ray.direction.reflect(normal), i_ray.time);
+        }
+
+        let h         = self.microfacet.sample_h(normal);
+        let wo_dot_h  = wo.dot(h);
+        let reflected = i_ray.direction.normalized().reflect(h);
+        let cos_i     = reflected.dot(normal);
+
+        // Bounced into the surface: the energy is lost instead of leaking through
+        if wo_dot_h <= 0.0 || cos_i <= 0.0 || cos_o <= 0.0
+        {
+            *o_attenuation = Vec3::zero();
+            return i_record.spawn_ray(reflected, i_ray.time);
+        }
+
+        // eval / pdf
+        *o_attenuation = self.fresnel(wo_dot_h)
+                       * (self.microfacet.g(cos_o, cos_i) * wo_dot_h / (cos_o * h.dot(normal)));
+
+        return i_record.spawn_ray(reflected, i_ray.time);
+    }
+
+    fn albedo(&self, _i_record: &HitRecord) -> Vec3 { self.fresnel(1.0) }
+
+    fn eval(&self, i_record: &HitRecord, i_wo: Vec3, i_wi: Vec3) -> Vec3
+    {
+        let normal = i_record.facing_normal();
+        let cos_o  = i_wo.dot(normal);
+        let cos_i  = i_wi.dot(normal);
+
+        if self.microfacet.is_smooth() || cos_o <= 0.0 || cos_i <= 0.0 { return Vec3::zero(); }
+
+        let h = (i_wo + i_wi).normalized();
+        let d = self.microfacet.d(h.dot(normal));
+        let g = self.microfacet.g(cos_o, cos_i);
+
+        // D·G·F / (4·cos_o·cos_i), times cos_i
+        return self.fresnel(i_wo.dot(h)) * (d * g / (4.0 * cos_o));
+    }
+
+    fn pdf(&self, i_record: &HitRecord, i_wo: Vec3, i_wi: Vec3) -> Float
+    {
+        let normal = i_record.facing_normal();
+        if self.microfacet.is_smooth() || i_wo.dot(normal) <= 0.0 || i_wi.dot(normal) <= 0.0 { return 0.0; }
+
+        let h = (i_wo + i_wi).normalized();
+        return self.microfacet.pdf_h(h.dot(normal)) / (4.0 * i_wo.dot(h));
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::super::utils::{ self, PI };
+    use float_cmp::approx_eq;
+    use std::rc::Rc;
+
+    fn new_record(material: MetallicMat) -> HitRecord
+    {
+        HitRecord::upwards(Vec3::zero(), Rc::new(material))
+    }
+
+    fn direction(cos_theta: Float, phi: Float) -> Vec3
+    {
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        return Vec3::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+    }
+
+    fn rough_metals() -> Vec<MetallicMat>
+    {
+        vec![ MetallicMat::new(0.3, Vec3::one()),
+              MetallicMat::new(0.9, Vec3::one()),
+              MetallicMat::new(0.5, Vec3::one()).with_distribution(Distribution::Beckmann),
+              MetallicMat::gold(0.4) ]
+    }
+
+    #[test]
+    fn reciprocity()
+    {
+        for material in rough_metals()
+        {
+            let record = new_record(material);
+            for _ in 0..100
+            {
+                let wo = direction(utils::rand_in_range(0.05, 1.0), utils::rand_in_range(0.0, 2.0 * PI));
+                let wi = direction(utils::rand_in_range(0.05, 1.0), utils::rand_in_range(0.0, 2.0 * PI));
 
-        let offset     = utils::rand_point_in_unit_sphere() * self.roughness;
-        *o_attenuation = self.albedo;
+                // Without the cosine the BRDF is symmetric
+                let f_oi = material.eval(&record, wo, wi) / wi.y();
+                let f_io = material.eval(&record, wi, wo) / wo.y();
 
-        return Ray::new(i_record.position, reflected + offset, i_ray.time);
+                assert!( (f_oi - f_io).norm() <= 1e-4 * f_oi.norm().max(1.0), "{:?} vs {:?}", f_oi, f_io );
+            }
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn energy_is_conserved()
+    {
+        for material in rough_metals()
+        {
+            let record = new_record(material);
+            for cos_o in &[1.0, 0.7, 0.3, 0.05]
+            {
+                let wo  = direction(*cos_o, 0.0);
+                let ray = Ray::new(wo, -wo, 0.0);
+
+                let samples = 20000;
+                let mut sum = Vec3::zero();
+                for _ in 0..samples
+                {
+                    let mut attenuation = Vec3::zero();
+                    let scattered = material.scatter(&ray, &record, &mut attenuation);
+
+                    // Never below the surface
+                    assert!( attenuation.norm2() == 0.0 || scattered.direction.y() > 0.0 );
+                    assert!( attenuation.x() >= 0.0 && attenuation.y() >= 0.0 && attenuation.z() >= 0.0 );
+                    sum += attenuation;
+                }
+
+                let albedo = sum / samples as Float;
+                assert!( albedo.x() <= 1.01 && albedo.y() <= 1.01 && albedo.z() <= 1.01, "{:?} at {}", albedo, cos_o );
+            }
+        }
+    }
+
+    #[test]
+    fn eval_matches_sampling()
+    {
+        // ∫ eval dω by uniformly sampling the hemisphere should match the average importance sampled weight
+        let material = MetallicMat::new(0.6, Vec3::one());
+        let record   = new_record(material);
+        let wo       = direction(0.8, 0.0);
+        let ray      = Ray::new(wo, -wo, 0.0);
+
+        let samples = 100000;
+        let mut uniform    = 0.0;
+        let mut importance = 0.0;
+        for _ in 0..samples
+        {
+            let wi   = direction(rand::random::<Float>(), utils::rand_in_range(0.0, 2.0 * PI));
+            uniform += material.eval(&record, wo, wi).x() * 2.0 * PI;
+
+            let mut attenuation = Vec3::zero();
+            material.scatter(&ray, &record, &mut attenuation);
+            importance += attenuation.x();
+        }
+
+        let uniform    = uniform    / samples as Float;
+        let importance = importance / samples as Float;
+        assert!( approx_eq!(Float, uniform, importance, epsilon = 0.02), "{} vs {}", uniform, importance );
+    }
+
+    #[test]
+    fn mirror()
+    {
+        let material = MetallicMat::new(0.0, Vec3::new(0.9, 0.5, 0.1));
+        let record   = new_record(material);
+        let ray      = Ray::new(Vec3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0), 0.0);
+
+        let mut attenuation = Vec3::zero();
+        let scattered = material.scatter(&ray, &record, &mut attenuation);
+
+        assert_eq!( scattered.direction, Vec3::new(1.0, 1.0, 0.0).normalized() );
+        assert_eq!( material.pdf(&record, Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0)), 0.0 );
+    }
+
+    #[test]
+    fn conductor_presets()
+    {
+        let gold   = MetallicMat::gold(0.0).fresnel(1.0);
+        let copper = MetallicMat::copper(0.0).fresnel(1.0);
+
+        // Both are reddish/yellowish: more red than blue
+        assert!( gold.r()   > gold.b() );
+        assert!( copper.r() > copper.b() );
+        // Everything reflects fully at grazing angles
+        assert!( approx_eq!(Float, MetallicMat::gold(0.0).fresnel(0.0).b(), 1.0, epsilon = 0.001) );
+    }
+}
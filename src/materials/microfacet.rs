@@ -0,0 +1,215 @@
+use super::super::utils::{ self, vec3::Vec3, PI, TAU, Float };
+
+// Below this alpha a surface is treated as a perfect mirror
+const SMOOTH_ALPHA: Float = 0.001;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Distribution
+{
+    Ggx,
+    Beckmann
+}
+
+// Microfacet normal distribution together with its Smith shadowing-masking term.
+// All cosines are measured against the macro surface normal.
+#[derive(Copy, Clone, Debug)]
+pub struct Microfacet
+{
+    pub distribution: Distribution,
+    pub alpha:        Float
+}
+
+impl Microfacet
+{
+    // Perceptual roughness in [0,1], squared so it feels linear
+    pub fn from_roughness(distribution: Distribution, roughness: Float) -> Self
+    {
+        let roughness = roughness.clamp(0.0, 1.0);
+        Self{ distribution, alpha: roughness * roughness }
+    }
+
+    pub fn is_smooth(&self) -> bool { self.alpha < SMOOTH_ALPHA }
+
+    fn tan2(cos: Float) -> Float
+    {
+        let cos2 = cos * cos;
+        return (1.0 - cos2).max(0.0) / cos2;
+    }
+
+    // Normal distribution function
+    pub fn d(&self, cos_h: Float) -> Float
+    {
+        if cos_h <= 0.0 { return 0.0; }
+
+        let a2   = self.alpha * self.alpha;
+        let cos4 = cos_h * cos_h * cos_h * cos_h;
+        let tan2 = Self::tan2(cos_h);
+
+        match self.distribution
+        {
+            Distribution::Ggx      => a2 / (PI * cos4 * (a2 + tan2) * (a2 + tan2)),
+            Distribution::Beckmann => (-tan2 / a2).exp() / (PI * a2 * cos4)
+        }
+    }
+
+    // Smith's auxiliary function
+    pub fn lambda(&self, cos: Float) -> Float
+    {
+        let tan2 = Self::tan2(cos);
+        if tan2 == 0.0 { return 0.0; }
+
+        match self.distribution
+        {
+            Distribution::Ggx => 0.5 * (-1.0 + (1.0 + self.alpha * self.alpha * tan2).sqrt()),
+            Distribution::Beckmann =>
+            {
+                let a = 1.0 / (self.alpha * tan2.sqrt());
+                if a >= 1.6 { 0.0 }
+                else        { (1.0 - 1.259 * a + 0.396 * a * a) / (3.535 * a + 2.181 * a * a) }
+            }
+        }
+    }
+
+    // Height correlated shadowing-masking
+    pub fn g(&self, cos_o: Float, cos_i: Float) -> Float
+    {
+        return 1.0 / (1.0 + self.lambda(cos_o.abs()) + self.lambda(cos_i.abs()));
+    }
+
+    // Microfacet normal around i_normal, distributed as D(h)·cos(h)
+    pub fn sample_h(&self, i_normal: Vec3) -> Vec3
+    {
+        let u1 = rand::random::<Float>();
+        let u2 = rand::random::<Float>();
+
+        let tan2 = match self.distribution
+        {
+            Distribution::Ggx      => self.alpha * self.alpha * u1 / (1.0 - u1).max(Float::EPSILON),
+            Distribution::Beckmann => -self.alpha * self.alpha * (1.0 - u1).max(Float::EPSILON).ln()
+        };
+
+        let cos_theta = 1.0 / (1.0 + tan2).sqrt();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi       = TAU * u2;
+
+        let (tangent, bitangent) = utils::orthonormal_basis(i_normal);
+        return tangent   * (sin_theta * phi.cos())
+             + bitangent * (sin_theta * phi.sin())
+             + i_normal  *  cos_theta;
+    }
+
+    pub fn pdf_h(&self, cos_h: Float) -> Float { self.d(cos_h) * cos_h }
+}
+
+// Unpolarized reflectance of a dielectric interface. eta: n_incident / n_transmitted
+pub fn fresnel_dielectric(cos_i: Float, eta: Float) -> Float
+{
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+
+    if sin2_t >= 1.0 { return 1.0; } // Total internal reflection
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_s   = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_p   = (eta * cos_t - cos_i) / (eta * cos_t + cos_i);
+
+    return 0.5 * (r_s * r_s + r_p * r_p);
+}
+
+// Reflectance of a conductor with complex index of refraction eta + ik, per color channel, from the air
+pub fn fresnel_conductor(cos_i: Float, eta: Vec3, k: Vec3) -> Vec3
+{
+    let channel = |eta: Float, k: Float| -> Float
+    {
+        let cos2 = cos_i.clamp(0.0, 1.0).powi(2);
+        let sin2 = 1.0 - cos2;
+        let eta2 = eta * eta;
+        let k2   = k * k;
+
+        let t0       = eta2 - k2 - sin2;
+        let a2_pl_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+        let t1       = a2_pl_b2 + cos2;
+        let a        = (0.5 * (a2_pl_b2 + t0)).max(0.0).sqrt();
+        let t2       = 2.0 * cos_i * a;
+        let r_s      = (t1 - t2) / (t1 + t2);
+
+        let t3  = cos2 * a2_pl_b2 + sin2 * sin2;
+        let t4  = t2 * sin2;
+        let r_p = r_s * (t3 - t4) / (t3 + t4);
+
+        0.5 * (r_p + r_s)
+    };
+
+    return Vec3::new(channel(eta.r(), k.r()),
+                     channel(eta.g(), k.g()),
+                     channel(eta.b(), k.b()));
+}
+
+// Reflectance of a tinted mirror, f0 being the color seen head-on
+pub fn fresnel_schlick(cos_i: Float, f0: Vec3) -> Vec3
+{
+    return f0 + (Vec3::one() - f0) * (1.0 - cos_i.clamp(0.0, 1.0)).powi(5);
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use float_cmp::approx_eq;
+
+    #[test]
+    fn distributions_are_normalized()
+    {
+        // ∫ D(h) cos(h) dω = 1
+        for distribution in &[Distribution::Ggx, Distribution::Beckmann]
+        {
+            for roughness in &[0.3, 0.6, 1.0]
+            {
+                let microfacet = Microfacet::from_roughness(*distribution, *roughness);
+
+                let steps    = 20000;
+                let d_theta  = 0.5 * PI / steps as Float;
+                let integral: Float = (0..steps).map(|i|
+                {
+                    let theta = (i as Float + 0.5) * d_theta;
+                    microfacet.d(theta.cos()) * theta.cos() * theta.sin() * TAU * d_theta
+                }).sum();
+
+                assert!( approx_eq!(Float, integral, 1.0, epsilon = 0.01),
+                         "{:?} with roughness {}: {}", distribution, roughness, integral );
+            }
+        }
+    }
+
+    #[test]
+    fn sampled_normals_are_in_the_hemisphere()
+    {
+        let normal     = Vec3::new(0.0, 1.0, 0.0);
+        let microfacet = Microfacet::from_roughness(Distribution::Ggx, 0.8);
+
+        for _ in 0..1000
+        {
+            let h = microfacet.sample_h(normal);
+            assert!( approx_eq!(Float, h.norm(), 1.0, epsilon = 0.0001) );
+            assert!( h.dot(normal) >= 0.0 );
+        }
+    }
+
+    #[test]
+    fn fresnel()
+    {
+        // Head-on glass reflects 4%
+        assert!( approx_eq!(Float, fresnel_dielectric(1.0, 1.0 / 1.5), 0.04, epsilon = 0.0001) );
+        // Grazing angles always reflect
+        assert!( approx_eq!(Float, fresnel_dielectric(0.0, 1.0 / 1.5), 1.0, epsilon = 0.0001) );
+        // Past the critical angle from inside
+        assert_eq!( fresnel_dielectric(0.5, 1.5), 1.0 );
+
+        // Head-on conductor: ((n-1)² + k²) / ((n+1)² + k²)
+        let eta = Vec3::new(0.2, 0.9, 1.1);
+        let k   = Vec3::new(3.9, 2.4, 2.1);
+        let f   = fresnel_conductor(1.0, eta, k);
+        let expected = ((0.2 as Float - 1.0).powi(2) + 3.9 * 3.9) / ((0.2 as Float + 1.0).powi(2) + 3.9 * 3.9);
+        assert!( approx_eq!(Float, f.r(), expected, epsilon = 0.0001) );
+    }
+}
@@ -0,0 +1,131 @@
+use super::Material;
+use super::super::ray::Ray;
+use super::super::utils::{ vec3::Vec3, Float };
+use super::super::hittables::hit_record::HitRecord;
+use super::super::textures::{ Texture, SolidTex };
+
+use std::rc::Rc;
+
+// Blends two materials, e.g. dust over metal. Each scattering event picks one of them.
+// Light going through is never absorbed, since the volume inside would be ambiguous.
+#[derive(Clone, Debug)]
+pub struct MixMat
+{
+    pub first:  Rc<dyn Material>,
+    pub second: Rc<dyn Material>,
+    pub factor: Rc<dyn Texture> // How much of the second material, only the red channel is used
+}
+
+impl MixMat
+{
+    #[allow(dead_code)]
+    pub fn new(first: Rc<dyn Material>, second: Rc<dyn Material>, factor: Float) -> Self
+    {
+        Self{ first, second, factor: SolidTex::scalar(factor) }
+    }
+
+    pub fn textured(first: Rc<dyn Material>, second: Rc<dyn Material>, factor: Rc<dyn Texture>) -> Self
+    {
+        Self{ first, second, factor }
+    }
+
+    fn factor_at(&self, i_record: &HitRecord) -> Float
+    {
+        return self.factor.value(i_record.uv, i_record.position).x().clamp(0.0, 1.0);
+    }
+}
+
+impl Material for MixMat
+{
+    fn scatter(&self,
+               i_ray: &Ray,
+               i_record: &HitRecord,
+               o_attenuation: &mut Vec3) -> Ray
+    {
+        // Picking each one as often as it contributes, its weight cancels out
+        if rand::random::<Float>() < self.factor_at(i_record) { self.second.scatter(i_ray, i_record, o_attenuation) }
+        else                                                { self.first.scatter(i_ray, i_record, o_attenuation)  }
+    }
+
+    fn albedo(&self, i_record: &HitRecord) -> Vec3
+    {
+        return Vec3::lerp(self.first.albedo(i_record), self.second.albedo(i_record), self.factor_at(i_record));
+    }
+
+    fn opacity(&self, i_record: &HitRecord) -> Float
+    {
+        let factor = self.factor_at(i_record);
+        return self.first.opacity(i_record) * (1.0 - factor) + self.second.opacity(i_record) * factor;
+    }
+
+    fn has_cutouts(&self) -> bool { self.first.has_cutouts() || self.second.has_cutouts() }
+
+    fn eval(&self, i_record: &HitRecord, i_wo: Vec3, i_wi: Vec3) -> Vec3
+    {
+        return Vec3::lerp(self.first.eval(i_record, i_wo, i_wi),
+                          self.second.eval(i_record, i_wo, i_wi),
+                          self.factor_at(i_record));
+    }
+
+    fn pdf(&self, i_record: &HitRecord, i_wo: Vec3, i_wi: Vec3) -> Float
+    {
+        let factor = self.factor_at(i_record);
+        return self.first.pdf(i_record, i_wo, i_wi) * (1.0 - factor) + self.second.pdf(i_record, i_wo, i_wi) * factor;
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::lambertian::LambertianMat;
+    use super::super::super::textures::CheckerTex;
+
+    fn red_and_blue() -> (Rc<dyn Material>, Rc<dyn Material>)
+    {
+        (Rc::new( LambertianMat{ albedo: Vec3::new(1.0, 0.0, 0.0) } ),
+         Rc::new( LambertianMat{ albedo: Vec3::new(0.0, 0.0, 1.0) } ))
+    }
+
+    fn new_record(material: &MixMat, position: Vec3) -> HitRecord
+    {
+        HitRecord::upwards(position, Rc::new(material.clone()))
+    }
+
+    #[test]
+    fn blends()
+    {
+        let (red, blue) = red_and_blue();
+        let mix    = MixMat::new(red, blue, 0.25);
+        let record = new_record(&mix, Vec3::zero());
+        let ray    = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+
+        let samples = 10000;
+        let mut sum = Vec3::zero();
+        for _ in 0..samples
+        {
+            let mut attenuation = Vec3::zero();
+            mix.scatter(&ray, &record, &mut attenuation);
+            sum += attenuation;
+        }
+        let average = sum / samples as Float;
+
+        assert!( (average - Vec3::new(0.75, 0.0, 0.25)).norm() < 0.03 );
+        assert!( (mix.albedo(&record) - Vec3::new(0.75, 0.0, 0.25)).norm() < 1e-6 );
+
+        let wo = Vec3::new(0.0, 1.0, 0.0);
+        let wi = Vec3::new(0.6, 0.8, 0.0);
+        assert!( (mix.eval(&record, wo, wi) - Vec3::new(0.75, 0.0, 0.25) * (0.8 / super::super::super::utils::PI)).norm() < 1e-6 );
+    }
+
+    #[test]
+    fn textured_factor()
+    {
+        let (red, blue) = red_and_blue();
+        let checker = CheckerTex::shared(SolidTex::scalar(1.0), SolidTex::scalar(0.0), 1.0);
+        let mix     = MixMat::textured(red, blue, checker);
+
+        assert_eq!( mix.albedo(&new_record(&mix, Vec3::new(0.5, 0.5, 0.5))), Vec3::new(1.0, 0.0, 0.0) );
+        assert_eq!( mix.albedo(&new_record(&mix, Vec3::new(1.5, 0.5, 0.5))), Vec3::new(0.0, 0.0, 1.0) );
+    }
+}
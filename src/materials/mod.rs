@@ -2,15 +2,54 @@ pub mod debug;
 pub mod metallic;
 pub mod lambertian;
 pub mod dielectric;
+pub mod microfacet;
+pub mod principled;
+pub mod mix;
+pub mod coated;
+pub mod normal_map;
+pub mod masked;
+pub mod subsurface;
+pub mod volume;
+pub mod light;
 
 use super::ray::Ray;
-use super::utils::vec3::Vec3;
+use super::utils::{ vec3::Vec3, Float };
 use super::hittables::hit_record::HitRecord;
+use super::media::Medium;
 
 pub trait Material: std::fmt::Debug
 {
+    // Rays leave from HitRecord::spawn_ray, which keeps them off the surface they scatter from
     fn scatter(&self,
                i_ray: &Ray,
                i_record: &HitRecord,
                o_attenuation: &mut Vec3) -> Ray;
+
+    // Surface color used by the auxiliary render passes
+    fn albedo(&self, i_record: &HitRecord) -> Vec3;
+
+    // BSDF times the cosine of i_wi, for light coming from i_wi and leaving along i_wo.
+    // Both directions point away from the surface. Perfectly specular surfaces can't be evaluated and return 0.
+    fn eval(&self, _i_record: &HitRecord, _i_wo: Vec3, _i_wi: Vec3) -> Vec3 { Vec3::zero() }
+
+    // Solid angle density scatter() picks i_wi with, given i_wo
+    fn pdf(&self, _i_record: &HitRecord, _i_wo: Vec3, _i_wi: Vec3) -> Float { 0.0 }
+
+    // Normal the material actually shades with, after any normal or bump mapping
+    fn shading_normal(&self, i_record: &HitRecord) -> Vec3 { i_record.shading_normal }
+
+    // Surfaces are skipped by intersections where this is under 0.5, see HitRecord::is_cut_out
+    fn opacity(&self, _i_record: &HitRecord) -> Float { 1.0 }
+
+    // Whether opacity() is ever under 1. If not, occlusion tests can skip making a HitRecord to check it.
+    fn has_cutouts(&self) -> bool { false }
+
+    // What fills the volume enclosed by the surface, if light goes through it
+    fn medium(&self) -> Option<Medium> { None }
+
+    // Radiance given off towards where the ray came from
+    fn emitted(&self, _i_record: &HitRecord) -> Vec3 { Vec3::zero() }
+
+    // Whether emitted() is ever above zero, what makes an object a light
+    fn is_emissive(&self) -> bool { false }
 }
\ No newline at end of file
@@ -0,0 +1,224 @@
+use super::Material;
+use super::super::ray::Ray;
+use super::super::utils::{ self, vec3::Vec3, Float };
+use super::super::hittables::hit_record::HitRecord;
+use super::super::media::Medium;
+use super::super::textures::Texture;
+
+use std::rc::Rc;
+
+// Step in UV space used to differentiate height maps
+const BUMP_DELTA: Float = 0.0005;
+
+#[derive(Clone, Debug)]
+pub enum SurfaceDetail
+{
+    NormalMap(Rc<dyn Texture>),                     // Tangent space normals, encoded as (n + 1) / 2
+    BumpMap{ height: Rc<dyn Texture>, scale: Float }  // Height along the normal, red channel
+}
+
+// Perturbs the shading normal of another material, adding detail without adding geometry.
+// The geometric normal is left alone, so which side is the front and where rays start from don't change.
+#[derive(Clone, Debug)]
+pub struct NormalMappedMat
+{
+    pub base:   Rc<dyn Material>,
+    pub detail: SurfaceDetail
+}
+
+impl NormalMappedMat
+{
+    #[allow(dead_code)]
+    pub fn normal_map(base: Rc<dyn Material>, normals: Rc<dyn Texture>) -> Self
+    {
+        Self{ base, detail: SurfaceDetail::NormalMap(normals) }
+    }
+
+    #[allow(dead_code)]
+    pub fn bump_map(base: Rc<dyn Material>, height: Rc<dyn Texture>, scale: Float) -> Self
+    {
+        Self{ base, detail: SurfaceDetail::BumpMap{ height, scale } }
+    }
+
+    // Tangent frame around the shading normal, following the direction of increasing u
+    fn tangent_frame(i_record: &HitRecord) -> (Vec3, Vec3)
+    {
+        let normal  = i_record.shading_normal;
+        let tangent = i_record.dpdu - normal * normal.dot(i_record.dpdu);
+
+        if tangent.norm2() < 1e-12 { return utils::orthonormal_basis(normal); }
+
+        let tangent = tangent.normalized();
+        return (tangent, normal.cross(tangent));
+    }
+
+    fn perturbed(&self, i_record: &HitRecord) -> HitRecord
+    {
+        let normal = i_record.shading_normal;
+        let (u, v) = i_record.uv;
+
+        let shading_normal = match &self.detail
+        {
+            SurfaceDetail::NormalMap(normals) =>
+            {
+                let local = normals.value(i_record.uv, i_record.position) * 2.0 - Vec3::one();
+                let (tangent, bitangent) = Self::tangent_frame(i_record);
+
+                tangent * local.x() + bitangent * local.y() + normal * local.z()
+            },
+            SurfaceDetail::BumpMap{ height, scale } =>
+            {
+                let height_at = |du: Float, dv: Float| -> Float
+                {
+                    let position = i_record.position + i_record.dpdu * du + i_record.dpdv * dv;
+                    height.value((u + du, v + dv), position).x() * scale
+                };
+
+                let center = height_at(0.0, 0.0);
+                let dhdu   = (height_at(BUMP_DELTA, 0.0) - center) / BUMP_DELTA;
+                let dhdv   = (height_at(0.0, BUMP_DELTA) - center) / BUMP_DELTA;
+
+                // Derivatives of the displaced surface p + h·n, ignoring how n itself changes
+                let dpdu = i_record.dpdu + normal * dhdu;
+                let dpdv = i_record.dpdv + normal * dhdv;
+                let bumped = dpdu.cross(dpdv);
+
+                if bumped.dot(normal) < 0.0 { -bumped } else { bumped }
+            }
+        };
+
+        let mut result = i_record.clone();
+        if shading_normal.norm2() > 0.0 { result.shading_normal = shading_normal.normalized(); }
+        return result;
+    }
+}
+
+impl Material for NormalMappedMat
+{
+    fn scatter(&self,
+               i_ray: &Ray,
+               i_record: &HitRecord,
+               o_attenuation: &mut Vec3) -> Ray
+    {
+        // Whatever the base material is, rays the new normal sends to the wrong side of the geometry are absorbed
+        let perturbed = self.perturbed(i_record);
+        let scattered = self.base.scatter(i_ray, &perturbed, o_attenuation);
+        if !perturbed.is_consistent(scattered.direction) { *o_attenuation = Vec3::zero(); }
+        return scattered;
+    }
+
+    fn albedo(&self, i_record: &HitRecord) -> Vec3 { self.base.albedo(i_record) }
+
+    fn eval(&self, i_record: &HitRecord, i_wo: Vec3, i_wi: Vec3) -> Vec3
+    {
+        let perturbed = self.perturbed(i_record);
+        if !perturbed.is_consistent(i_wi) { return Vec3::zero(); }
+        return self.base.eval(&perturbed, i_wo, i_wi);
+    }
+
+    fn pdf(&self, i_record: &HitRecord, i_wo: Vec3, i_wi: Vec3) -> Float
+    {
+        return self.base.pdf(&self.perturbed(i_record), i_wo, i_wi);
+    }
+
+    fn medium(&self) -> Option<Medium> { self.base.medium() }
+
+    fn emitted(&self, i_record: &HitRecord) -> Vec3 { self.base.emitted(i_record) }
+
+    fn is_emissive(&self) -> bool { self.base.is_emissive() }
+
+    fn opacity(&self, i_record: &HitRecord) -> Float { self.base.opacity(i_record) }
+
+    fn has_cutouts(&self) -> bool { self.base.has_cutouts() }
+
+    fn shading_normal(&self, i_record: &HitRecord) -> Vec3 { self.perturbed(i_record).shading_normal }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::lambertian::LambertianMat;
+    use super::super::metallic::MetallicMat;
+    use super::super::super::textures::SolidTex;
+
+    // Height growing along u
+    #[derive(Debug)]
+    struct RampTex;
+
+    impl Texture for RampTex
+    {
+        fn value(&self, i_uv: (Float, Float), _i_position: Vec3) -> Vec3 { Vec3::one() * i_uv.0 }
+    }
+
+    fn white() -> Rc<dyn Material> { Rc::new( LambertianMat{ albedo: Vec3::one() } ) }
+
+    #[test]
+    fn flat_maps_keep_the_normal()
+    {
+        let flat_normals = NormalMappedMat::normal_map(white(), SolidTex::color(Vec3::new(0.5, 0.5, 1.0)));
+        let flat_heights = NormalMappedMat::bump_map(white(), SolidTex::scalar(0.3), 1.0);
+        let record       = HitRecord::upwards(Vec3::zero(), white());
+
+        for material in &[flat_normals, flat_heights]
+        {
+            assert!( (material.perturbed(&record).shading_normal - record.normal).norm() < 1e-5 );
+        }
+    }
+
+    #[test]
+    fn normal_map_tilts_towards_the_tangent()
+    {
+        // 45 degrees towards +u
+        let tilted   = Vec3::new(1.0, 0.0, 1.0).normalized();
+        let material = NormalMappedMat::normal_map(white(), SolidTex::color((tilted + Vec3::one()) * 0.5));
+        let record   = HitRecord::upwards(Vec3::zero(), white());
+        let result   = material.perturbed(&record);
+
+        let expected = (record.dpdu.normalized() + record.normal).normalized();
+        assert!( (result.shading_normal - expected).norm() < 1e-5 );
+        // The geometry doesn't change
+        assert_eq!( result.normal, record.normal );
+        assert!( result.front_face );
+    }
+
+    #[test]
+    fn never_scatters_through_the_surface()
+    {
+        // Tilted 80 degrees, so a good part of the lobe around it is under the surface
+        let tilted   = Vec3::new(1.0, 0.0, 0.18).normalized();
+        let metal: Rc<dyn Material> = Rc::new( MetallicMat::new(0.5, Vec3::one()) );
+        let record   = HitRecord::upwards(Vec3::zero(), white());
+        let incoming = Ray::new(Vec3::new(1.0, 1.0, 0.0), Vec3::new(-1.0, -1.0, 0.0), 0.0);
+
+        for base in &[white(), metal]
+        {
+            let material = NormalMappedMat::normal_map(base.clone(), SolidTex::color((tilted + Vec3::one()) * 0.5));
+            let mut below = 0;
+            for _ in 0..1000
+            {
+                let mut attenuation = Vec3::one();
+                let scattered = material.scatter(&incoming, &record, &mut attenuation);
+                if scattered.direction.dot(record.normal) <= 0.0
+                {
+                    below += 1;
+                    assert_eq!( attenuation, Vec3::zero() );
+                    assert_eq!( material.eval(&record, -incoming.direction, scattered.direction), Vec3::zero() );
+                }
+            }
+            assert!( below > 0 );
+        }
+    }
+
+    #[test]
+    fn bump_map_tilts_away_from_the_slope()
+    {
+        let material = NormalMappedMat::bump_map(white(), Rc::new(RampTex), 1.0);
+        let record   = HitRecord::upwards(Vec3::zero(), white());
+        let result   = material.perturbed(&record).shading_normal;
+
+        // Rising one unit per unit along u is a 45 degree slope
+        let expected = (record.normal - record.dpdu.normalized()).normalized();
+        assert!( (result - expected).norm() < 1e-3, "{:?}", result );
+    }
+}
@@ -0,0 +1,358 @@
+use super::Material;
+use super::dielectric::DielectricMat;
+use super::microfacet::{ self, Microfacet, Distribution };
+use super::super::ray::Ray;
+use super::super::utils::{ vec3::Vec3, PI, Float };
+use super::super::hittables::hit_record::HitRecord;
+use super::super::textures::{ Texture, SolidTex };
+
+use std::rc::Rc;
+
+// Every lobe is kept rough enough to be evaluated, so they can all be combined
+const MIN_ROUGHNESS: Float = 0.05;
+const CLEARCOAT_ROUGHNESS: Float = 0.1;
+const CLEARCOAT_F0: Float = 0.04;
+
+// Disney style "principled" BSDF: a single material with artist friendly knobs, all in [0,1].
+// From bottom to top: a diffuse base with a sheen, a specular layer that becomes metal as metallic goes up,
+// rough glass replacing the diffuse base as transmission goes up, and a clear coat over everything.
+#[derive(Clone, Debug)]
+pub struct PrincipledMat
+{
+    pub base_color:   Rc<dyn Texture>,
+    pub metallic:     Rc<dyn Texture>, // Only the red channel is used
+    pub roughness:    Rc<dyn Texture>, // Only the red channel is used
+    pub specular:     Float,             // Reflectance of non metals, 0.5 is the usual 4%
+    pub clearcoat:    Float,
+    pub sheen:        Float,             // White rim at grazing angles, for cloth
+    pub transmission: Float,
+    pub ior:          Float              // Only used by the transmission
+}
+
+// The material's parameters at a given point, with the textures already looked up
+struct Surface
+{
+    base:      Vec3,
+    f0:        Vec3, // Of the specular layer, tinted when metallic
+    f0_dielectric: Float,
+    specular:  Microfacet,
+    clearcoat: Microfacet,
+    glass:     DielectricMat,
+    // Weights of each lobe
+    diffuse_w: Float,
+    glossy_w:  Float,
+    glass_w:   Float,
+    coat_w:    Float,
+    sheen_w:   Float,
+    // Probabilities of sampling each lobe
+    diffuse_p: Float,
+    glossy_p:  Float,
+    glass_p:   Float
+}
+
+impl PrincipledMat
+{
+    pub fn new(base_color: Vec3) -> Self
+    {
+        Self{ base_color:   SolidTex::color(base_color),
+              metallic:     SolidTex::scalar(0.0),
+              roughness:    SolidTex::scalar(0.5),
+              specular:     0.5,
+              clearcoat:    0.0,
+              sheen:        0.0,
+              transmission: 0.0,
+              ior:          1.5 }
+    }
+
+    fn surface(&self, i_record: &HitRecord) -> Surface
+    {
+        let base         = self.base_color.value(i_record.uv, i_record.position);
+        let metallic     = self.metallic.value(i_record.uv, i_record.position).x().clamp(0.0, 1.0);
+        let roughness    = self.roughness.value(i_record.uv, i_record.position).x().clamp(MIN_ROUGHNESS, 1.0);
+        let transmission = self.transmission.clamp(0.0, 1.0);
+        let dielectric   = 1.0 - metallic;
+
+        let diffuse_w = dielectric * (1.0 - transmission);
+        let glossy_w  = metallic + diffuse_w;
+        let glass_w   = dielectric * transmission;
+        let coat_w    = self.clearcoat.clamp(0.0, 1.0);
+
+        // Dielectric highlights are faint, so they get fewer samples than their weight
+        let diffuse_s = diffuse_w;
+        let glossy_s  = metallic + diffuse_w * 0.25;
+        let glass_s   = glass_w;
+        let coat_s    = coat_w * 0.25;
+        let total     = diffuse_s + glossy_s + glass_s + coat_s;
+
+        Surface{ base,
+                 f0:        Vec3::lerp(Vec3::one() * (0.08 * self.specular), base, metallic),
+                 f0_dielectric: 0.08 * self.specular,
+                 specular:  Microfacet::from_roughness(Distribution::Ggx, roughness),
+                 clearcoat: Microfacet::from_roughness(Distribution::Ggx, CLEARCOAT_ROUGHNESS),
+                 glass:     DielectricMat::new(self.ior, Vec3::one()).with_roughness(roughness),
+                 diffuse_w,
+                 glossy_w,
+                 glass_w,
+                 coat_w,
+                 sheen_w:   self.sheen * dielectric,
+                 diffuse_p: diffuse_s / total,
+                 glossy_p:  glossy_s  / total,
+                 glass_p:   glass_s   / total }
+    }
+}
+
+impl Surface
+{
+    // Light the clear coat reflects away before it reaches the layers below
+    fn coat_fresnel(&self, cos: Float) -> Float
+    {
+        return self.coat_w * microfacet::fresnel_schlick(cos, Vec3::one() * CLEARCOAT_F0).x();
+    }
+
+    fn reflection_pdf(microfacet: &Microfacet, i_wo: Vec3, i_wi: Vec3, normal: Vec3) -> Float
+    {
+        if i_wo.dot(normal) <= 0.0 || i_wi.dot(normal) <= 0.0 { return 0.0; }
+
+        let h = (i_wo + i_wi).normalized();
+        return microfacet.pdf_h(h.dot(normal)) / (4.0 * i_wo.dot(h));
+    }
+
+    fn eval(&self, i_record: &HitRecord, i_wo: Vec3, i_wi: Vec3) -> Vec3
+    {
+        let normal = i_record.facing_normal();
+        let cos_o  = i_wo.dot(normal);
+        let cos_i  = i_wi.dot(normal);
+        if cos_o <= 0.0 { return Vec3::zero(); }
+
+        let coat       = (1.0 - self.coat_fresnel(cos_o)) * (1.0 - self.coat_fresnel(cos_i.abs()));
+        let mut result = self.glass.eval(i_record, i_wo, i_wi) * self.base * self.glass_w;
+
+        if cos_i > 0.0
+        {
+            let h     = (i_wo + i_wi).normalized();
+            let cos_h = h.dot(normal);
+
+            // What the specular layer lets through, both on the way in and out
+            let f0      = Vec3::one() * self.f0_dielectric;
+            let through = (1.0 - microfacet::fresnel_schlick(cos_o, f0).x())
+                        * (1.0 - microfacet::fresnel_schlick(cos_i, f0).x());
+
+            let diffuse = self.base * (through / PI)
+                        + Vec3::one() * (self.sheen_w * (1.0 - i_wi.dot(h)).max(0.0).powi(5));
+            result += diffuse * (self.diffuse_w * cos_i);
+
+            let glossy = microfacet::fresnel_schlick(i_wo.dot(h), self.f0)
+                       * (self.specular.d(cos_h) * self.specular.g(cos_o, cos_i) / (4.0 * cos_o));
+            result += glossy * self.glossy_w;
+
+            let coat_lobe = self.coat_fresnel(i_wo.dot(h))
+                          * self.clearcoat.d(cos_h) * self.clearcoat.g(cos_o, cos_i) / (4.0 * cos_o);
+            return result * coat + Vec3::one() * coat_lobe;
+        }
+
+        return result * coat;
+    }
+
+    fn pdf(&self, i_record: &HitRecord, i_wo: Vec3, i_wi: Vec3) -> Float
+    {
+        let normal    = i_record.facing_normal();
+        let coat_p    = 1.0 - self.diffuse_p - self.glossy_p - self.glass_p;
+        let diffuse   = i_wi.dot(normal).max(0.0) / PI;
+        let glossy    = Self::reflection_pdf(&self.specular,  i_wo, i_wi, normal);
+        let clearcoat = Self::reflection_pdf(&self.clearcoat, i_wo, i_wi, normal);
+        let glass     = if self.glass_p > 0.0 { self.glass.pdf(i_record, i_wo, i_wi) } else { 0.0 };
+
+        return self.diffuse_p * diffuse + self.glossy_p * glossy + self.glass_p * glass + coat_p * clearcoat;
+    }
+}
+
+impl Material for PrincipledMat
+{
+    // Picks one of the lobes, but weights the direction by all of them (one-sample MIS)
+    fn scatter(&self,
+               i_ray: &Ray,
+               i_record: &HitRecord,
+               o_attenuation: &mut Vec3) -> Ray
+    {
+        let surface   = self.surface(i_record);
+        let normal    = i_record.facing_normal();
+        let input_dir = i_ray.direction.normalized();
+        let wo        = -input_dir;
+
+        // (direction, can it go through the surface)
+        let dice = rand::random::<Float>();
+        let (wi, transmits) = if dice < surface.diffuse_p
+        {
+            let dir = normal + Vec3::rand_unit();
+            (if dir.norm2() < 1e-8 { normal } else { dir.normalized() }, false)
+        }
+        else if dice < surface.diffuse_p + surface.glossy_p
+        {
+            (input_dir.reflect(surface.specular.sample_h(normal)), false)
+        }
+        else if dice < surface.diffuse_p + surface.glossy_p + surface.glass_p
+        {
+            let mut attenuation = Vec3::zero();
+            let scattered = surface.glass.scatter(i_ray, i_record, &mut attenuation);
+            if attenuation.norm2() == 0.0
+            {
+                *o_attenuation = Vec3::zero();
+                return scattered;
+            }
+            (scattered.direction.normalized(), true)
+        }
+        else
+        {
+            (input_dir.reflect(surface.clearcoat.sample_h(normal)), false)
+        };
+
+        // Reflections bounced into the surface are lost, otherwise they would be weighted as if they came from the glass
+        if !transmits && wi.dot(normal) <= 0.0
+        {
+            *o_attenuation = Vec3::zero();
+            return i_record.spawn_ray(wi, i_ray.time);
+        }
+
+        let pdf = surface.pdf(i_record, wo, wi);
+        *o_attenuation = if pdf > 0.0 { surface.eval(i_record, wo, wi) / pdf } else { Vec3::zero() };
+
+        return i_record.spawn_ray(wi, i_ray.time);
+    }
+
+    fn albedo(&self, i_record: &HitRecord) -> Vec3
+    {
+        return self.base_color.value(i_record.uv, i_record.position);
+    }
+
+    fn eval(&self, i_record: &HitRecord, i_wo: Vec3, i_wi: Vec3) -> Vec3
+    {
+        return self.surface(i_record).eval(i_record, i_wo, i_wi);
+    }
+
+    fn pdf(&self, i_record: &HitRecord, i_wo: Vec3, i_wi: Vec3) -> Float
+    {
+        return self.surface(i_record).pdf(i_record, i_wo, i_wi);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::metallic::MetallicMat;
+    use super::super::super::textures::CheckerTex;
+    use super::super::super::utils::{ rand_in_range, TAU };
+    use float_cmp::approx_eq;
+
+    fn new_record(material: &PrincipledMat) -> HitRecord
+    {
+        HitRecord::upwards(Vec3::new(0.5, 0.0, 0.5), Rc::new(material.clone()))
+    }
+
+    fn direction(cos_theta: Float, phi: Float) -> Vec3
+    {
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        return Vec3::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+    }
+
+    fn materials() -> Vec<PrincipledMat>
+    {
+        let base = Vec3::new(0.8, 0.5, 0.2);
+        vec![ PrincipledMat::new(base),
+              PrincipledMat{ metallic: SolidTex::scalar(1.0), roughness: SolidTex::scalar(0.3), ..PrincipledMat::new(base) },
+              PrincipledMat{ clearcoat: 1.0, sheen: 1.0, ..PrincipledMat::new(Vec3::one()) },
+              PrincipledMat{ transmission: 1.0, roughness: SolidTex::scalar(0.4), ..PrincipledMat::new(Vec3::one()) },
+              PrincipledMat{ metallic: SolidTex::scalar(0.3),
+                             transmission: 0.3,
+                             clearcoat:    0.5,
+                             sheen:        0.5,
+                             ..PrincipledMat::new(Vec3::one()) } ]
+    }
+
+    #[test]
+    fn energy_is_bounded()
+    {
+        for material in materials()
+        {
+            let record = new_record(&material);
+            for cos_o in &[1.0, 0.5, 0.1]
+            {
+                let wo  = direction(*cos_o, 0.0);
+                let ray = Ray::new(wo, -wo, 0.0);
+
+                let samples = 20000;
+                let mut sum = Vec3::zero();
+                for _ in 0..samples
+                {
+                    let mut attenuation = Vec3::zero();
+                    material.scatter(&ray, &record, &mut attenuation);
+                    sum += attenuation;
+                }
+
+                let albedo = sum / samples as Float;
+                assert!( albedo.x() <= 1.02 && albedo.y() <= 1.02 && albedo.z() <= 1.02,
+                         "{:?} at {}: {:?}", material, cos_o, albedo );
+            }
+        }
+    }
+
+    #[test]
+    fn eval_matches_sampling()
+    {
+        let material = &materials()[4];
+        let record   = new_record(material);
+        let wo       = direction(0.7, 0.0);
+        let ray      = Ray::new(wo, -wo, 0.0);
+
+        let samples = 200000;
+        let mut sampled    = 0.0;
+        let mut integrated = 0.0;
+        for _ in 0..samples
+        {
+            let mut attenuation = Vec3::zero();
+            material.scatter(&ray, &record, &mut attenuation);
+            sampled += attenuation.x();
+
+            // Uniformly over the sphere
+            let wi      = direction(rand_in_range(-1.0, 1.0), rand_in_range(0.0, TAU));
+            integrated += material.eval(&record, wo, wi).x() * 2.0 * TAU;
+        }
+
+        let sampled    = sampled    / samples as Float;
+        let integrated = integrated / samples as Float;
+        assert!( approx_eq!(Float, sampled, integrated, epsilon = 0.05), "{} vs {}", sampled, integrated );
+    }
+
+    #[test]
+    fn fully_metallic_is_a_metal()
+    {
+        let base      = Vec3::new(0.9, 0.6, 0.3);
+        let principled = PrincipledMat{ metallic: SolidTex::scalar(1.0), roughness: SolidTex::scalar(0.4), ..PrincipledMat::new(base) };
+        let metal      = MetallicMat::new(0.4, base);
+        let record     = new_record(&principled);
+
+        for _ in 0..100
+        {
+            let wo = direction(rand_in_range(0.1, 1.0), rand_in_range(0.0, TAU));
+            let wi = direction(rand_in_range(0.1, 1.0), rand_in_range(0.0, TAU));
+
+            let expected = metal.eval(&record, wo, wi);
+            assert!( (principled.eval(&record, wo, wi) - expected).norm() <= 1e-4 * expected.norm().max(1.0) );
+        }
+    }
+
+    #[test]
+    fn textured_inputs()
+    {
+        let red     = Vec3::new(1.0, 0.0, 0.0);
+        let blue    = Vec3::new(0.0, 0.0, 1.0);
+        let checker = CheckerTex::shared(SolidTex::color(red), SolidTex::color(blue), 1.0);
+        let material = PrincipledMat{ base_color: checker, ..PrincipledMat::new(Vec3::one()) };
+
+        let mut record = new_record(&material);
+        assert_eq!( material.albedo(&record), blue );
+
+        record.position = Vec3::new(1.5, 0.0, 0.5);
+        assert_eq!( material.albedo(&record), red );
+    }
+}
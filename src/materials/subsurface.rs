@@ -0,0 +1,121 @@
+use super::Material;
+use super::dielectric::DielectricMat;
+use super::super::ray::Ray;
+use super::super::utils::{ vec3::Vec3, Float };
+use super::super::hittables::hit_record::HitRecord;
+use super::super::media::Medium;
+
+// Translucent materials like skin, wax, marble or milk. Light refracts into the object and the ray tracer
+// follows it on a random walk through the scattering volume inside until it leaves somewhere else.
+// Needs a closed surface, otherwise rays that got in never find their way out.
+#[derive(Copy, Clone, Debug)]
+pub struct SubsurfaceMat
+{
+    interface:      DielectricMat,
+    color:          Vec3, // What the object looks like from afar, after all the bounces inside
+    mean_free_path: Vec3, // How far light goes between scattering events, per channel
+    anisotropy:     Float
+}
+
+impl SubsurfaceMat
+{
+    pub fn new(color: Vec3, mean_free_path: Vec3, refraction_idx: Float) -> Self
+    {
+        Self{ interface: DielectricMat::new(refraction_idx, Vec3::one()),
+              color,
+              mean_free_path,
+              anisotropy: 0.0 }
+    }
+
+    #[allow(dead_code)]
+    pub fn with_roughness(mut self, roughness: Float) -> Self
+    {
+        self.interface = self.interface.with_roughness(roughness);
+        return self;
+    }
+
+    // Henyey-Greenstein g of the volume, skin scatters mostly forward
+    pub fn with_anisotropy(mut self, anisotropy: Float) -> Self
+    {
+        self.anisotropy = anisotropy;
+        return self;
+    }
+
+    // Chance of scattering instead of being absorbed at each event that makes the random walk end up
+    // with i_color overall. Fit from Chiang et al. 2016, "Practical and Controllable Subsurface Scattering
+    // for Production Path Tracing".
+    fn single_scattering_albedo(i_color: Float) -> Float
+    {
+        let a = i_color.clamp(0.0, 1.0);
+        let s = 4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt();
+        return 1.0 - s * s;
+    }
+}
+
+impl Material for SubsurfaceMat
+{
+    fn scatter(&self,
+               i_ray: &Ray,
+               i_record: &HitRecord,
+               o_attenuation: &mut Vec3) -> Ray
+    {
+        return self.interface.scatter(i_ray, i_record, o_attenuation);
+    }
+
+    fn albedo(&self, _i_record: &HitRecord) -> Vec3 { self.color }
+
+    fn eval(&self, i_record: &HitRecord, i_wo: Vec3, i_wi: Vec3) -> Vec3
+    {
+        return self.interface.eval(i_record, i_wo, i_wi);
+    }
+
+    fn pdf(&self, i_record: &HitRecord, i_wo: Vec3, i_wi: Vec3) -> Float
+    {
+        return self.interface.pdf(i_record, i_wo, i_wi);
+    }
+
+    fn medium(&self) -> Option<Medium>
+    {
+        let extinction = |mfp: Float| 1.0 / mfp.max(1e-4);
+        let extinction = Vec3::new(extinction(self.mean_free_path.x()),
+                                   extinction(self.mean_free_path.y()),
+                                   extinction(self.mean_free_path.z()));
+        let albedo     = Vec3::new(Self::single_scattering_albedo(self.color.r()),
+                                   Self::single_scattering_albedo(self.color.g()),
+                                   Self::single_scattering_albedo(self.color.b()));
+
+        Some( Medium{ absorption: extinction * (Vec3::one() - albedo),
+                      scattering: extinction * albedo,
+                      anisotropy: self.anisotropy,
+                      density:    None } )
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use float_cmp::approx_eq;
+
+    #[test]
+    fn albedo_inversion()
+    {
+        assert!( approx_eq!(Float, SubsurfaceMat::single_scattering_albedo(0.0), 0.0, epsilon = 1e-4) );
+        assert!( approx_eq!(Float, SubsurfaceMat::single_scattering_albedo(1.0), 1.0, epsilon = 0.01) );
+
+        // Light only gets dark after many bounces, so even dark colors scatter a fair share at each one
+        let samples = [0.1, 0.3, 0.5, 0.7, 0.9].iter().map(|a| SubsurfaceMat::single_scattering_albedo(*a)).collect::<Vec<_>>();
+        assert!( samples.windows(2).all(|w| w[0] < w[1]) );
+        assert!( samples[0] > 0.3 );
+    }
+
+    #[test]
+    fn medium_follows_the_mean_free_path()
+    {
+        let wax    = SubsurfaceMat::new(Vec3::new(0.9, 0.8, 0.6), Vec3::new(0.5, 0.25, 0.1), 1.4);
+        let medium = wax.medium().unwrap();
+
+        assert_eq!( medium.extinction(), Vec3::new(2.0, 4.0, 10.0) );
+        assert!( medium.scatters() );
+    }
+}
@@ -0,0 +1,46 @@
+use super::Material;
+use super::super::ray::Ray;
+use super::super::utils::{ vec3::Vec3, Float };
+use super::super::hittables::hit_record::HitRecord;
+use super::super::media::Medium;
+
+// Invisible boundary of a participating medium, like smoke or a cloud.
+// Rays cross it untouched, all the scattering happens in the medium inside.
+#[derive(Clone, Debug)]
+pub struct VolumeMat
+{
+    pub medium: Medium
+}
+
+impl VolumeMat
+{
+    pub fn new(medium: Medium) -> Self
+    {
+        Self{ medium }
+    }
+}
+
+impl Material for VolumeMat
+{
+    fn scatter(&self,
+               i_ray: &Ray,
+               i_record: &HitRecord,
+               o_attenuation: &mut Vec3) -> Ray
+    {
+        *o_attenuation = Vec3::one();
+        return i_record.spawn_ray(i_ray.direction, i_ray.time);
+    }
+
+    // Chance of scattering at each collision
+    fn albedo(&self, _i_record: &HitRecord) -> Vec3
+    {
+        let extinction = self.medium.extinction();
+        let ratio      = |s: Float, t: Float| if t > 0.0 { s / t } else { 0.0 };
+
+        return Vec3::new(ratio(self.medium.scattering.x(), extinction.x()),
+                         ratio(self.medium.scattering.y(), extinction.y()),
+                         ratio(self.medium.scattering.z(), extinction.z()));
+    }
+
+    fn medium(&self) -> Option<Medium> { Some(self.medium.clone()) }
+}
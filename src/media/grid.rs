@@ -0,0 +1,244 @@
+use super::super::utils::{ vec3::Vec3, Float };
+
+use std::fs;
+
+// Densities sampled on a regular 3D grid, stretched over an axis aligned box in world space.
+// Values are interpolated trilinearly between cell centers, and are 0 outside of the box.
+#[derive(Clone)]
+pub struct DensityGrid
+{
+    resolution:  [usize; 3],
+    values:      Vec<Float>, // x changes fastest, then y, then z
+    max_density: Float,
+    min:         Vec3,
+    max:         Vec3
+}
+
+// The values themselves would flood any debug output
+impl std::fmt::Debug for DensityGrid
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        write!(f, "DensityGrid{{ resolution: {:?}, min: {:?}, max: {:?} }}", self.resolution, self.min, self.max)
+    }
+}
+
+impl DensityGrid
+{
+    // Fills the unit cube around the origin until placed somewhere else
+    pub fn new(resolution: [usize; 3], values: Vec<Float>) -> Result<Self, String>
+    {
+        let count = resolution[0] * resolution[1] * resolution[2];
+        if count == 0             { return Err(format!("Empty grid {:?}", resolution)); }
+        if values.len() != count  { return Err(format!("Expected {} values, found {}", count, values.len())); }
+
+        let max_density = values.iter().cloned().fold(0.0, Float::max);
+        Ok( Self{ resolution, values, max_density, min: Vec3::one() * -0.5, max: Vec3::one() * 0.5 } )
+    }
+
+    // Raw grid file, like a binary PPM: a text line "nx ny nz" followed by nx·ny·nz little endian f32
+    pub fn from_file(i_file_name: &str) -> Result<Self, String>
+    {
+        let contents = fs::read(i_file_name).map_err(|e| format!("{}: {}", i_file_name, e))?;
+        return Self::parse(&contents).map_err(|e| format!("{}: {}", i_file_name, e));
+    }
+
+    pub fn parse(i_contents: &[u8]) -> Result<Self, String>
+    {
+        let header_end = i_contents.iter().position(|c| *c == b'\n').ok_or("Missing header")?;
+        let header     = std::str::from_utf8(&i_contents[..header_end]).map_err(|e| e.to_string())?;
+
+        let sizes: Vec<usize> = header.split_whitespace()
+                                      .map(str::parse::<usize>)
+                                      .collect::<Result<_, _>>()
+                                      .map_err(|e| format!("Header: {}", e))?;
+        if sizes.len() != 3 { return Err(format!("Header: expected 3 sizes, found {}", sizes.len())); }
+
+        let body = &i_contents[header_end+1..];
+        if body.len() % 4 != 0 { return Err(format!("{} bytes of data isn't a whole number of f32", body.len())); }
+
+        let values = body.chunks_exact(4)
+                         .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as Float)
+                         .collect();
+        return Self::new([sizes[0], sizes[1], sizes[2]], values);
+    }
+
+    // Billowing cloud: fractal value noise, faded out towards a sphere inscribed in the grid
+    pub fn from_noise(resolution: usize, frequency: Float, seed: u32) -> Self
+    {
+        let mut values = Vec::with_capacity(resolution * resolution * resolution);
+        for z in 0..resolution
+        {
+            for y in 0..resolution
+            {
+                for x in 0..resolution
+                {
+                    // Cell center, in [0, 1]
+                    let p = Vec3::new(x as Float + 0.5, y as Float + 0.5, z as Float + 0.5) * (1.0 / resolution as Float);
+
+                    let falloff = 1.0 - (p - Vec3::one() * 0.5).norm() * 2.0;
+                    let noise   = fractal_noise(p * frequency, seed);
+                    values.push( ((noise + falloff - 0.6) * 2.5).clamp(0.0, 1.0) );
+                }
+            }
+        }
+        return Self::new([resolution; 3], values).unwrap();
+    }
+
+    pub fn placed(mut self, min: Vec3, max: Vec3) -> Self
+    {
+        self.min = min;
+        self.max = max;
+        return self;
+    }
+
+    pub fn bounds(&self) -> (Vec3, Vec3) { (self.min, self.max) }
+
+    // Upper bound of density_at, what delta and ratio tracking need
+    pub fn max_density(&self) -> Float { self.max_density }
+
+    pub fn density_at(&self, i_position: Vec3) -> Float
+    {
+        let size  = self.max - self.min;
+        let local = i_position - self.min;
+        let local = [local.x() / size.x(), local.y() / size.y(), local.z() / size.z()];
+        if local.iter().any(|l| !(0.0..=1.0).contains(l)) { return 0.0; }
+
+        // Lower corner of the cell centers around the position, and how far along it is to the next ones
+        let mut lower = [0; 3];
+        let mut upper = [0; 3];
+        let mut t     = [0.0; 3];
+        for axis in 0..3
+        {
+            let res   = self.resolution[axis];
+            let coord = (local[axis] * res as Float - 0.5).clamp(0.0, (res - 1) as Float);
+
+            lower[axis] = (coord.floor() as usize).min(res - 1);
+            upper[axis] = (lower[axis] + 1).min(res - 1);
+            t[axis]     = coord - lower[axis] as Float;
+        }
+
+        let value = |x: usize, y: usize, z: usize| self.values[(z * self.resolution[1] + y) * self.resolution[0] + x];
+        let lerp  = |a: Float, b: Float, t: Float| a + (b - a) * t;
+
+        let x00 = lerp(value(lower[0], lower[1], lower[2]), value(upper[0], lower[1], lower[2]), t[0]);
+        let x10 = lerp(value(lower[0], upper[1], lower[2]), value(upper[0], upper[1], lower[2]), t[0]);
+        let x01 = lerp(value(lower[0], lower[1], upper[2]), value(upper[0], lower[1], upper[2]), t[0]);
+        let x11 = lerp(value(lower[0], upper[1], upper[2]), value(upper[0], upper[1], upper[2]), t[0]);
+
+        return lerp(lerp(x00, x10, t[1]), lerp(x01, x11, t[1]), t[2]);
+    }
+}
+
+// Pseudo random value in [0, 1] for a lattice point
+fn lattice_value(x: i32, y: i32, z: i32, seed: u32) -> Float
+{
+    let mut h = (x as u32).wrapping_mul(0x8da6_b343)
+              ^ (y as u32).wrapping_mul(0xd816_3841)
+              ^ (z as u32).wrapping_mul(0xcb1a_b31f)
+              ^ seed.wrapping_mul(0x1656_67b1);
+    h ^= h >> 13;
+    h  = h.wrapping_mul(0x5bd1_e995);
+    h ^= h >> 15;
+    return (h & 0x00ff_ffff) as Float / 0x00ff_ffff as Float;
+}
+
+// Smoothly interpolated lattice values
+fn value_noise(i_position: Vec3, seed: u32) -> Float
+{
+    let floor  = [i_position.x().floor(), i_position.y().floor(), i_position.z().floor()];
+    let corner = [floor[0] as i32, floor[1] as i32, floor[2] as i32];
+    let smooth = |t: Float| t * t * (3.0 - 2.0 * t);
+    let t      = [smooth(i_position.x() - floor[0]), smooth(i_position.y() - floor[1]), smooth(i_position.z() - floor[2])];
+    let lerp   = |a: Float, b: Float, t: Float| a + (b - a) * t;
+
+    let value = |dx: i32, dy: i32, dz: i32| lattice_value(corner[0] + dx, corner[1] + dy, corner[2] + dz, seed);
+
+    let x00 = lerp(value(0, 0, 0), value(1, 0, 0), t[0]);
+    let x10 = lerp(value(0, 1, 0), value(1, 1, 0), t[0]);
+    let x01 = lerp(value(0, 0, 1), value(1, 0, 1), t[0]);
+    let x11 = lerp(value(0, 1, 1), value(1, 1, 1), t[0]);
+
+    return lerp(lerp(x00, x10, t[1]), lerp(x01, x11, t[1]), t[2]);
+}
+
+// Octaves of value noise, each twice the frequency and half the amplitude of the last. In [0, 1].
+fn fractal_noise(i_position: Vec3, seed: u32) -> Float
+{
+    let mut result    = 0.0;
+    let mut amplitude = 0.5;
+    let mut position  = i_position;
+    for octave in 0..4
+    {
+        result    += value_noise(position, seed.wrapping_add(octave)) * amplitude;
+        amplitude *= 0.5;
+        position  *= 2.0;
+    }
+    return result / (1.0 - amplitude * 2.0);
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use float_cmp::approx_eq;
+
+    fn raw_file(resolution: [usize; 3], values: &[f32]) -> Vec<u8>
+    {
+        let mut result = format!("{} {} {}\n", resolution[0], resolution[1], resolution[2]).into_bytes();
+        for value in values { result.extend_from_slice(&value.to_le_bytes()); }
+        return result;
+    }
+
+    #[test]
+    fn parse()
+    {
+        let grid = DensityGrid::parse(&raw_file([2, 1, 1], &[0.25, 2.0])).unwrap();
+
+        assert_eq!( grid.resolution, [2, 1, 1] );
+        assert_eq!( grid.max_density(), 2.0 );
+
+        assert!( DensityGrid::parse(&raw_file([2, 2, 1], &[0.25, 2.0])).is_err() );
+        assert!( DensityGrid::parse(b"2 1\n").is_err() );
+        assert!( DensityGrid::parse(b"no header").is_err() );
+    }
+
+    #[test]
+    fn from_file()
+    {
+        let file_name = std::env::temp_dir().join("raytracing_grid_test.raw");
+        fs::write(&file_name, raw_file([1, 2, 1], &[0.5, 1.5])).unwrap();
+
+        let grid = DensityGrid::from_file(file_name.to_str().unwrap());
+        fs::remove_file(&file_name).unwrap();
+
+        assert_eq!( grid.unwrap().resolution, [1, 2, 1] );
+        assert!( DensityGrid::from_file("missing_grid.raw").is_err() );
+    }
+
+    #[test]
+    fn trilinear_interpolation()
+    {
+        let grid = DensityGrid::new([2, 1, 1], vec![1.0, 3.0]).unwrap().placed(Vec3::zero(), Vec3::new(2.0, 1.0, 1.0));
+
+        // Cell centers, halfway between them, and clamped past them
+        assert!( approx_eq!(Float, grid.density_at(Vec3::new(0.5, 0.5, 0.5)), 1.0) );
+        assert!( approx_eq!(Float, grid.density_at(Vec3::new(1.5, 0.2, 0.9)), 3.0) );
+        assert!( approx_eq!(Float, grid.density_at(Vec3::new(1.0, 0.5, 0.5)), 2.0) );
+        assert!( approx_eq!(Float, grid.density_at(Vec3::new(0.1, 0.5, 0.5)), 1.0) );
+
+        assert_eq!( grid.density_at(Vec3::new(2.5, 0.5, 0.5)), 0.0 );
+        assert_eq!( grid.density_at(Vec3::new(1.0, -0.1, 0.5)), 0.0 );
+    }
+
+    #[test]
+    fn noise_cloud_is_bounded()
+    {
+        let grid = DensityGrid::from_noise(16, 4.0, 7);
+
+        assert!( grid.max_density() > 0.0 && grid.max_density() <= 1.0 );
+        // Thick in the middle, nothing in the corners
+        assert!( grid.density_at(Vec3::zero()) > 0.0 );
+        assert_eq!( grid.density_at(Vec3::one() * 0.49), 0.0 );
+    }
+}
@@ -0,0 +1,278 @@
+pub mod grid;
+
+use super::ray::Ray;
+use super::utils::{ self, vec3::Vec3, Float };
+use grid::DensityGrid;
+
+use std::rc::Rc;
+
+// Participating medium filling the inside of a closed surface.
+// Coefficients are per unit of distance, and per color channel.
+#[derive(Clone, Debug)]
+pub struct Medium
+{
+    pub absorption: Vec3,
+    pub scattering: Vec3,
+    pub anisotropy: Float,                    // Henyey-Greenstein g, -1 scatters back, 0 everywhere, 1 forward
+    pub density:    Option<Rc<DensityGrid>> // Scales the coefficients, which are then those at density 1
+}
+
+impl Medium
+{
+    // Only absorbs, like colored glass
+    pub fn absorbing(absorption: Vec3) -> Self
+    {
+        Self{ absorption, scattering: Vec3::zero(), anisotropy: 0.0, density: None }
+    }
+
+    pub fn extinction(&self) -> Vec3 { self.absorption + self.scattering }
+
+    pub fn scatters(&self) -> bool { self.scattering.norm2() > 0.0 }
+
+    // Extinction no point inside goes over, for tracking through heterogeneous media
+    fn majorant(&self, i_grid: &DensityGrid) -> Float
+    {
+        let extinction = self.extinction();
+        return extinction.x().max(extinction.y()).max(extinction.z()) * i_grid.max_density();
+    }
+
+    // Fraction of the light that makes it i_max_d along the ray without being absorbed or scattered away.
+    // Estimated with ratio tracking through heterogeneous media, exact for homogeneous ones.
+    pub fn transmittance(&self, i_ray: &Ray, i_max_d: Float) -> Vec3
+    {
+        let grid = match &self.density
+        {
+            None       => return (-self.extinction() * i_max_d).exp(),
+            Some(grid) => grid
+        };
+
+        let majorant = self.majorant(grid);
+        if majorant <= 0.0 { return Vec3::one(); }
+
+        // Steps to tentative collisions as if the medium were as thick as the majorant everywhere,
+        // keeping the chance that each one was a null collision instead of a real one
+        let mut result   = Vec3::one();
+        let mut distance = 0.0;
+        loop
+        {
+            distance -= (1.0 - rand::random::<Float>()).ln() / majorant;
+            if distance >= i_max_d { return result; }
+
+            let extinction = self.extinction() * grid.density_at(i_ray.at(distance));
+            result *= Vec3::one() - extinction * (1.0 / majorant);
+        }
+    }
+
+    // Samples how far a ray travels before scattering, up to i_max_d where it leaves the medium.
+    // Returns the throughput weight, and the distance if the ray scattered before leaving.
+    pub fn sample_distance(&self, i_ray: &Ray, i_max_d: Float) -> (Vec3, Option<Float>)
+    {
+        match &self.density
+        {
+            None       => self.sample_homogeneous(i_max_d),
+            Some(grid) => self.delta_tracking(grid, i_ray, i_max_d)
+        }
+    }
+
+    // The distance follows the extinction of one channel picked at random, weighted by the average pdf
+    // of all of them so channels with very different mean free paths don't get noisy (spectral MIS)
+    fn sample_homogeneous(&self, i_max_d: Float) -> (Vec3, Option<Float>)
+    {
+        let extinction = self.extinction();
+        let channel    = (rand::random::<Float>() * 3.0).min(2.0) as usize;
+        let sigma_t    = [extinction.x(), extinction.y(), extinction.z()][channel];
+        let distance   = -(1.0 - rand::random::<Float>()).ln() / sigma_t;
+
+        let average = |v: Vec3| (v.x() + v.y() + v.z()) / 3.0;
+
+        if distance < i_max_d
+        {
+            let transmittance = (-extinction * distance).exp();
+            let pdf           = average(extinction * transmittance);
+            if pdf <= 0.0 { return (Vec3::zero(), Some(distance)); }
+
+            return (self.scattering * transmittance * (1.0 / pdf), Some(distance));
+        }
+
+        let transmittance = (-extinction * i_max_d).exp();
+        let pdf           = average(transmittance);
+        if pdf <= 0.0 { return (Vec3::zero(), None); }
+
+        return (transmittance * (1.0 / pdf), None);
+    }
+
+    // Delta tracking through a medium made homogeneous by null collisions, Woodcock et al. 1965.
+    // Channels don't share their collision probabilities, so scattering and null collisions are picked
+    // proportionally to their average over the path throughput and weighted for each channel, as in
+    // Kutz et al. 2017, "Spectral and Decomposition Tracking for Rendering Heterogeneous Volumes".
+    // Absorption never ends the walk, it lowers the weight instead.
+    fn delta_tracking(&self, i_grid: &DensityGrid, i_ray: &Ray, i_max_d: Float) -> (Vec3, Option<Float>)
+    {
+        let majorant = self.majorant(i_grid);
+        if majorant <= 0.0 { return (Vec3::one(), None); }
+
+        let average = |v: Vec3| (v.x() + v.y() + v.z()) / 3.0;
+
+        let mut weight   = Vec3::one();
+        let mut distance = 0.0;
+        loop
+        {
+            distance -= (1.0 - rand::random::<Float>()).ln() / majorant;
+            if distance >= i_max_d { return (weight, None); }
+
+            let density    = i_grid.density_at(i_ray.at(distance));
+            let scattering = self.scattering * density;
+            let null       = Vec3::one() * majorant - self.extinction() * density;
+
+            let p_scatter = average(scattering * weight);
+            let p_null    = average(null * weight);
+            if p_scatter + p_null <= 0.0 { return (Vec3::zero(), None); }
+
+            let p_scatter = p_scatter / (p_scatter + p_null);
+            if rand::random::<Float>() < p_scatter
+            {
+                return (weight * scattering * (1.0 / (majorant * p_scatter)), Some(distance));
+            }
+            weight *= null * (1.0 / (majorant * (1.0 - p_scatter)));
+        }
+    }
+
+    // New direction for a ray travelling along i_direction, exactly proportional to the phase function
+    pub fn sample_phase(&self, i_direction: Vec3) -> Vec3
+    {
+        let g = self.anisotropy;
+        let u = rand::random::<Float>();
+
+        let cos_theta = if g.abs() < 1e-3 { 1.0 - 2.0 * u }
+                        else
+                        {
+                            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+                            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+                        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi       = rand::random::<Float>() * utils::TAU;
+
+        let forward            = i_direction.normalized();
+        let (tangent, binormal) = utils::orthonormal_basis(forward);
+
+        return tangent * (sin_theta * phi.cos()) + binormal * (sin_theta * phi.sin()) + forward * cos_theta;
+    }
+
+    // Phase function for turning by an angle with the given cosine, per steradian
+    #[allow(dead_code)]
+    pub fn phase(&self, i_cos_theta: Float) -> Float
+    {
+        let g     = self.anisotropy;
+        let denom = 1.0 + g * g - 2.0 * g * i_cos_theta;
+        return (1.0 - g * g) / (2.0 * utils::TAU * denom * denom.sqrt());
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use float_cmp::approx_eq;
+
+    fn milk() -> Medium
+    {
+        Medium{ absorption: Vec3::new(0.1, 0.5, 2.0), scattering: Vec3::new(2.0, 1.0, 0.5), anisotropy: 0.0, density: None }
+    }
+
+    // The same medium, but thickening along the ray
+    fn milk_gradient() -> Medium
+    {
+        let grid = DensityGrid::new([1, 1, 4], vec![0.0, 0.5, 1.0, 1.5]).unwrap().placed(Vec3::zero(), Vec3::one());
+        Medium{ density: Some(Rc::new(grid)), ..milk() }
+    }
+
+    fn along_z() -> Ray { Ray::new(Vec3::new(0.5, 0.5, 0.0), Vec3::new(0.0, 0.0, 1.0), 0.0) }
+
+    // What a ray along z keeps and what scatters somewhere, per sample_distance
+    fn sampled_fractions(medium: &Medium, length: Float, samples: u32) -> (Vec3, Vec3)
+    {
+        let mut transmitted = Vec3::zero();
+        let mut scattered   = Vec3::zero();
+        for _ in 0..samples
+        {
+            match medium.sample_distance(&along_z(), length)
+            {
+                (weight, None)    => transmitted += weight,
+                (weight, Some(_)) => scattered   += weight
+            }
+        }
+        return (transmitted / samples as Float, scattered / samples as Float);
+    }
+
+    // Transmittance and scattered fraction by numerical integration along z
+    fn integrated_fractions(medium: &Medium, length: Float) -> (Vec3, Vec3)
+    {
+        let steps         = 10000;
+        let step          = length / steps as Float;
+        let mut optical   = Vec3::zero();
+        let mut scattered = Vec3::zero();
+        for i in 0..steps
+        {
+            let density = medium.density.as_ref().map_or(1.0, |grid| grid.density_at(along_z().at((i as Float + 0.5) * step)));
+            let before  = (-optical).exp();
+            optical    += medium.extinction() * (density * step);
+            scattered  += medium.scattering * (density * step) * (before + (-optical).exp()) * 0.5;
+        }
+        return ((-optical).exp(), scattered);
+    }
+
+    #[test]
+    fn phase_is_normalized()
+    {
+        for g in &[-0.7, 0.0, 0.3, 0.9]
+        {
+            let medium = Medium{ anisotropy: *g, ..milk() };
+
+            let steps    = 100000;
+            let integral = (0..steps).map(|i| medium.phase(-1.0 + 2.0 * (i as Float + 0.5) / steps as Float))
+                                     .sum::<Float>() * (2.0 / steps as Float) * utils::TAU;
+            assert!( approx_eq!(Float, integral, 1.0, epsilon = 1e-3), "{}: {}", g, integral );
+        }
+    }
+
+    #[test]
+    fn phase_sampling_has_the_right_mean_cosine()
+    {
+        let direction = Vec3::new(1.0, 2.0, -0.5);
+        for g in &[-0.5, 0.0, 0.8]
+        {
+            let medium = Medium{ anisotropy: *g, ..milk() };
+
+            let samples = 50000;
+            let mean    = (0..samples).map(|_| medium.sample_phase(direction).dot(direction.normalized()))
+                                      .sum::<Float>() / samples as Float;
+            assert!( approx_eq!(Float, mean, *g, epsilon = 0.02), "{}: {}", g, mean );
+        }
+    }
+
+    #[test]
+    fn distance_sampling_is_unbiased()
+    {
+        for medium in &[milk(), milk_gradient()]
+        {
+            let (transmitted, scattered) = sampled_fractions(medium, 0.8, 200000);
+            let (expected_t, expected_s) = integrated_fractions(medium, 0.8);
+
+            assert!( (transmitted - expected_t).norm() < 0.01, "{:?} vs {:?}", transmitted, expected_t );
+            assert!( (scattered - expected_s).norm() < 0.01, "{:?} vs {:?}", scattered, expected_s );
+        }
+    }
+
+    #[test]
+    fn ratio_tracking_is_unbiased()
+    {
+        let medium  = milk_gradient();
+        let samples = 100000;
+
+        let estimated = (0..samples).fold(Vec3::zero(), |sum, _| sum + medium.transmittance(&along_z(), 0.8)) / samples as Float;
+        let expected  = integrated_fractions(&medium, 0.8).0;
+
+        assert!( (estimated - expected).norm() < 0.01, "{:?} vs {:?}", estimated, expected );
+        assert_eq!( milk().transmittance(&along_z(), 0.8), (-milk().extinction() * 0.8).exp() );
+    }
+}
@@ -0,0 +1,299 @@
+use super::scene_generator::Preset;
+use super::integrators::IntegratorKind;
+use super::camera::{ Projection, Lens };
+use super::utils::{ vec3::Vec3, Float };
+use super::animation::{ Interpolation, FrameRange };
+
+pub struct Options
+{
+    pub width:         u32,
+    pub height:        u32,
+    pub sample_count:  i32,
+    pub fov:           Float,
+    pub aperture:      Float,
+    pub auto_focus:    bool,
+    pub up:            Vec3,
+    pub roll:          Float,
+    pub projection:    Projection,
+    pub lens:          Lens,
+    pub camera_path:   Option<String>, // "turntable" or a keyframe file
+    pub interpolation: Interpolation,
+    pub frames:        FrameRange,
+    pub scene:         Preset,
+    pub volume:        Option<String>, // Density grid file for the smoke scene's cloud
+    pub integrator:    IntegratorKind,
+    pub photon_count:  usize,
+    pub ao_samples:    u32,
+    pub ao_distance:   Float,
+    pub output:        String,
+    pub write_aovs:    bool,
+    pub denoise:       bool,
+    pub spectral:      bool
+}
+
+impl Default for Options
+{
+    fn default() -> Self
+    {
+        #[cfg(debug_assertions)]
+            let sample_count = 1;
+        #[cfg(not(debug_assertions))]
+            let sample_count = 64;
+
+        Self
+        {
+            width:         800,
+            height:        600,
+            sample_count,
+            fov:           90.0,
+            aperture:      0.1,
+            auto_focus:    false,
+            up:            Vec3::new(0.0, 1.0, 0.0),
+            roll:          0.0,
+            projection:    Projection::Perspective,
+            lens:          Lens::default(),
+            camera_path:   None,
+            interpolation: Interpolation::CatmullRom,
+            frames:        FrameRange{ first: 0, last: 47, fps: 24.0, shutter: 0.5 },
+            scene:         Preset::Rand,
+            volume:        None,
+            integrator:    IntegratorKind::Path,
+            photon_count:  200_000,
+            ao_samples:    16,
+            ao_distance:   1.0,
+            output:        String::from("out.ppm"),
+            write_aovs:    false,
+            denoise:       false,
+            spectral:      false
+        }
+    }
+}
+
+impl Options
+{
+    // Unknown flags are reported and ignored so a typo never aborts a long render
+    pub fn from_args<I: Iterator<Item = String>>(mut i_args: I) -> Self
+    {
+        let mut result = Self::default();
+
+        while let Some(arg) = i_args.next()
+        {
+            match arg.as_str()
+            {
+                "--width"                => result.width                     = Self::parse_value(&arg, i_args.next(), result.width),
+                "--height"               => result.height                    = Self::parse_value(&arg, i_args.next(), result.height),
+                "--samples"              => result.sample_count              = Self::parse_value(&arg, i_args.next(), result.sample_count),
+                "--fov"                  => result.fov                       = Self::parse_value(&arg, i_args.next(), result.fov),
+                "--aperture"             => result.aperture                  = Self::parse_value(&arg, i_args.next(), result.aperture),
+                "--autofocus"            => result.auto_focus                = true,
+                "--up"                   => result.up                        = Self::parse_direction(&arg, i_args.next(), result.up),
+                "--roll"                 => result.roll                      = Self::parse_value(&arg, i_args.next(), result.roll),
+                "--output"               => result.output                    = i_args.next().unwrap_or(result.output),
+                "--aovs"                 => result.write_aovs                = true,
+                "--denoise"              => result.denoise                   = true,
+                "--spectral"             => result.spectral                  = true,
+                "--photons"              => result.photon_count              = Self::parse_value(&arg, i_args.next(), result.photon_count),
+                "--ao-samples"           => result.ao_samples                = Self::parse_value(&arg, i_args.next(), result.ao_samples),
+                "--ao-distance"          => result.ao_distance               = Self::parse_value(&arg, i_args.next(), result.ao_distance),
+                "--blades"               => result.lens.blades               = Self::parse_value(&arg, i_args.next(), result.lens.blades),
+                "--blade-rotation"       => result.lens.blade_rotation       = Self::parse_value(&arg, i_args.next(), result.lens.blade_rotation),
+                "--vignetting"           => result.lens.vignetting           = Self::parse_value(&arg, i_args.next(), result.lens.vignetting),
+                "--chromatic-aberration" => result.lens.chromatic_aberration = Self::parse_value(&arg, i_args.next(), result.lens.chromatic_aberration),
+                "--camera-path"          => result.camera_path               = i_args.next(),
+                "--volume"               => result.volume                    = i_args.next(),
+                "--fps"                  => result.frames.fps                = Self::parse_value(&arg, i_args.next(), result.frames.fps),
+                "--shutter"              => result.frames.shutter            = Self::parse_value(&arg, i_args.next(), result.frames.shutter),
+                "--frames"               => result.frames                    = Self::parse_frame_range(&arg, i_args.next(), result.frames),
+                "--ortho-height"         =>
+                {
+                    let height = Self::parse_value(&arg, i_args.next(), 0.0);
+                    if height > 0.0 { result.projection = Projection::Orthographic{ height: Some(height) }; }
+                },
+                "--interpolation"        =>
+                {
+                    let name = i_args.next().unwrap_or_default();
+                    match Interpolation::from_name(&name)
+                    {
+                        Some(interpolation) => result.interpolation = interpolation,
+                        None => println!("WARNING: Unknown interpolation '{}'", name)
+                    }
+                },
+                "--projection"           =>
+                {
+                    let name = i_args.next().unwrap_or_default();
+                    match Projection::from_name(&name)
+                    {
+                        Some(projection) => result.projection = projection,
+                        None => println!("WARNING: Unknown projection '{}'", name)
+                    }
+                },
+                "--scene"                =>
+                {
+                    let name = i_args.next().unwrap_or_default();
+                    match Preset::from_name(&name)
+                    {
+                        Some(preset) => result.scene = preset,
+                        None => println!("WARNING: Unknown scene '{}'", name)
+                    }
+                },
+                "--integrator"           =>
+                {
+                    let name = i_args.next().unwrap_or_default();
+                    match IntegratorKind::from_name(&name)
+                    {
+                        Some(integrator) => result.integrator = integrator,
+                        None => println!("WARNING: Unknown integrator '{}'", name)
+                    }
+                },
+                _ => println!("WARNING: Unknown option '{}'", arg)
+            }
+        }
+        return result;
+    }
+
+    // Expects "x,y,z"
+    fn parse_vec3(i_flag: &str, i_value: Option<String>, i_default: Vec3) -> Vec3
+    {
+        let components: Vec<Float> = i_value.unwrap_or_default()
+                                          .split(',')
+                                          .filter_map(|c| c.trim().parse::<Float>().ok())
+                                          .collect();
+
+        if components.len() != 3
+        {
+            println!("WARNING: Invalid value for '{}'", i_flag);
+            return i_default;
+        }
+        return Vec3::new(components[0], components[1], components[2]);
+    }
+
+    // Same as parse_vec3, but a zero vector has no direction
+    fn parse_direction(i_flag: &str, i_value: Option<String>, i_default: Vec3) -> Vec3
+    {
+        let result = Self::parse_vec3(i_flag, i_value, i_default);
+        if result == Vec3::zero()
+        {
+            println!("WARNING: Invalid value for '{}'", i_flag);
+            return i_default;
+        }
+        return result;
+    }
+
+    // Expects "first-last", both inclusive
+    fn parse_frame_range(i_flag: &str, i_value: Option<String>, i_default: FrameRange) -> FrameRange
+    {
+        let value  = i_value.unwrap_or_default();
+        let bounds: Vec<u32> = value.split('-')
+                                    .filter_map(|b| b.trim().parse::<u32>().ok())
+                                    .collect();
+
+        if bounds.len() != 2 || bounds[0] > bounds[1]
+        {
+            println!("WARNING: Invalid value for '{}'", i_flag);
+            return i_default;
+        }
+        return FrameRange{ first: bounds[0], last: bounds[1], ..i_default };
+    }
+
+    fn parse_value<T: std::str::FromStr>(i_flag: &str, i_value: Option<String>, i_default: T) -> T
+    {
+        match i_value.as_deref().map(str::parse::<T>)
+        {
+            Some(Ok(value)) => value,
+            _ =>
+            {
+                println!("WARNING: Invalid value for '{}'", i_flag);
+                i_default
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn args(i_args: &[&str]) -> impl Iterator<Item = String>
+    {
+        i_args.iter()
+              .map(|a| a.to_string())
+              .collect::<Vec<_>>()
+              .into_iter()
+    }
+
+    #[test]
+    fn defaults_without_args()
+    {
+        let options = Options::from_args( args(&[]) );
+
+        assert_eq!( options.width,  800 );
+        assert_eq!( options.height, 600 );
+        assert!( !options.write_aovs );
+        assert!( !options.denoise );
+    }
+
+    #[test]
+    fn parse_flags()
+    {
+        let options = Options::from_args( args(&["--width", "64", "--samples", "8", "--aovs", "--scene", "simple", "--integrator", "ao", "--ao-distance", "0.5"]) );
+
+        assert_eq!( options.width, 64 );
+        assert_eq!( options.sample_count, 8 );
+        assert!( options.write_aovs );
+        assert!( matches!(options.scene, Preset::Simple) );
+        assert!( matches!(options.integrator, IntegratorKind::AmbientOcclusion) );
+        assert_eq!( options.ao_distance, 0.5 );
+
+        let options = Options::from_args( args(&["--scene", "smoke", "--volume", "cloud.raw"]) );
+        assert_eq!( options.volume.as_deref(), Some("cloud.raw") );
+
+        let options = Options::from_args( args(&["--integrator", "bdpt"]) );
+        assert!( matches!(options.integrator, IntegratorKind::Bidirectional) );
+    }
+
+    #[test]
+    fn invalid_values_keep_defaults()
+    {
+        let options = Options::from_args( args(&["--height", "tall", "--up", "0,1"]) );
+        assert_eq!( options.height, 600 );
+        assert_eq!( options.up, Vec3::new(0.0, 1.0, 0.0) );
+
+        let options = Options::from_args( args(&["--up", "0,0,0"]) );
+        assert_eq!( options.up, Vec3::new(0.0, 1.0, 0.0) );
+    }
+
+    #[test]
+    fn parse_animation()
+    {
+        let options = Options::from_args( args(&["--camera-path", "turntable", "--frames", "10-20", "--fps", "30"]) );
+
+        assert_eq!( options.camera_path.as_deref(), Some("turntable") );
+        assert_eq!( options.frames.first, 10 );
+        assert_eq!( options.frames.last,  20 );
+        assert_eq!( options.frames.fps,   30.0 );
+
+        let options = Options::from_args( args(&["--frames", "20-10"]) );
+        assert_eq!( options.frames.first, 0 );
+    }
+
+    #[test]
+    fn parse_projections()
+    {
+        let options = Options::from_args( args(&["--projection", "orthographic"]) );
+        assert_eq!( options.projection, Projection::Orthographic{ height: None } );
+
+        let options = Options::from_args( args(&["--ortho-height", "2.5"]) );
+        assert_eq!( options.projection, Projection::Orthographic{ height: Some(2.5) } );
+
+        let options = Options::from_args( args(&["--ortho-height", "-1"]) );
+        assert_eq!( options.projection, Projection::Perspective );
+    }
+
+    #[test]
+    fn parse_vectors()
+    {
+        let options = Options::from_args( args(&["--up", "0, 0,1"]) );
+        assert_eq!( options.up, Vec3::new(0.0, 0.0, 1.0) );
+    }
+}
@@ -1,21 +1,23 @@
-use super::utils::vec3::Vec3;
+use super::utils::{ vec3::Vec3, Float };
 
 #[derive(Copy, Clone, Debug)]
 pub struct Ray
 {
-    pub time:      f32,
-    pub origin:    Vec3,
-    pub direction: Vec3
+    pub time:       Float,
+    pub wavelength: Float, // Nanometers, 0 when the ray carries RGB
+    pub origin:     Vec3,
+    pub direction:  Vec3
 }
 
 impl Ray
 {
-    pub fn new(origin: Vec3, d: Vec3, time: f32) -> Ray
+    pub fn new(origin: Vec3, d: Vec3, time: Float) -> Ray
     {
         Ray {origin,
              direction: d.normalized(),
-             time}
+             time,
+             wavelength: 0.0}
     }
 
-    pub fn at(&self, t: f32) -> Vec3 { return self.origin + self.direction * t; }
+    pub fn at(&self, t: Float) -> Vec3 { return self.origin + self.direction * t; }
 }
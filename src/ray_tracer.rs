@@ -1,75 +1,111 @@
 use rand::random;
-use super::scene_generator;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
 use super::ray::Ray;
-use super::hittables::{ Hittable,
-                        Intersectionable,
-                        hit_record::HitRecord };
+use super::hittables::{ Intersectionable, hit_record::HitRecord };
 use super::camera::{ Rect, Camera };
-use super::utils::{ vec3::Vec3,
-                    ppm::ImagePPM };
+use super::film::{ AovSample, Film };
+use super::denoiser::Denoiser;
+use super::options::Options;
+use super::animation::CameraPath;
+use super::integrators::{ Integrator, path::PathIntegrator };
+use super::utils::{ vec3::Vec3, spectrum, Float };
 
-const MAX_DEPTH:   i32 = 50;
-const SHADOW_BIAS: f32 = 0.001;
+pub const CAMERA_POSITION: Vec3 = Vec3::new(7.5, 2.0, -3.0);
+pub const CAMERA_TARGET:   Vec3 = Vec3::new(0.0, 0.0,  0.0);
 
 pub struct RayTracer
 {
-    sample_count: i32,
-    output_size:  Rect,
-    camera:       Camera,
-    scene:        Vec<Intersectionable>
+    sample_count:  i32,
+    output_size:   Rect,
+    camera:        Camera,
+    scene:         Vec<Intersectionable>,
+    lights:        Vec<usize>,
+    object_ids:    Vec<u32>, // First ID of each object, sets take one per object in them
+    material_ids:  HashMap<usize, u32>,
+    integrator:    Box<dyn Integrator>,
+    sky_intensity: Float,
+    denoiser:      Option<Denoiser>,
+    auto_focus:    bool,
+    spectral:      bool,
+    splats:        RefCell<Vec<Vec3>> // Light integrators sent to other pixels than the one being rendered
 }
 
 impl RayTracer
 {
     // PUBLIC
-    // TODO: Customizable constructors
-    pub fn new() -> Self
-    {
-        let w = 800;
-        let h = 600;
-
-        #[cfg(debug_assertions)]
-            let sample_count = 1;
-        #[cfg(not(debug_assertions))]
-            let sample_count = 64;
-
-        let cam_aspect_ratio = (w as f32) / (h as f32);
-        let cam_fov          = 90.0;
-        let cam_aperture     = 0.1;
-        let cam_target       = Vec3::zero();
-        let cam_pos          = Vec3::new(7.5, 2.0, -3.0);
+    pub fn new(i_options: &Options) -> Self
+    {
+        let w = i_options.width;
+        let h = i_options.height;
+
+        let cam_fov          = i_options.fov;
+        let cam_target       = CAMERA_TARGET;
+        let cam_pos          = CAMERA_POSITION;
         let cam_focus_dist   = (cam_pos - cam_target).norm();
         let cam_shutter_t0   = 0.0;
         let cam_shutter_t1   = 1.0;
 
         let mut camera = Camera::new(cam_fov,
-                                     cam_aspect_ratio,
-                                     cam_aperture,
+                                     1.0,
+                                     0.0,
                                      cam_focus_dist,
                                      cam_shutter_t0,
                                      cam_shutter_t1);
 
+        // The output and lens options go through the same setters as any later change would
+        camera.resize( w, h );
+        camera.set_aperture( i_options.aperture );
         camera.move_to( cam_pos );
-        camera.look_at( cam_target );
+        camera.look_at_with_up( cam_target, i_options.up );
+        camera.set_roll( i_options.roll );
+        camera.set_projection( i_options.projection );
+        camera.set_lens( i_options.lens );
+
+        let scene        = i_options.scene.build( i_options.volume.as_deref() );
+        if i_options.auto_focus { camera.auto_focus(&scene); }
 
-        let scene = scene_generator::rand();
-        //let scene = scene_generator::simple();
+        let object_ids   = Self::enumerate_objects(&scene);
+        let material_ids = Self::enumerate_materials(&scene);
+        let lights       = Self::enumerate_lights(&scene);
 
-        Self{sample_count,
-             output_size: Rect{width: w as f32, height: h as f32},
-             camera,
-             scene}
+        let mut result = Self{sample_count: i_options.sample_count,
+                              output_size: Rect{width: w as Float, height: h as Float},
+                              camera,
+                              scene,
+                              lights,
+                              object_ids,
+                              material_ids,
+                              integrator: Box::new( PathIntegrator::default() ),
+                              sky_intensity: i_options.scene.sky_intensity(),
+                              denoiser: if i_options.denoise { Some(Denoiser::default()) } else { None },
+                              auto_focus: i_options.auto_focus,
+                              spectral: i_options.spectral,
+                              splats: RefCell::new(Vec::new())};
+
+        // Some integrators look at the whole scene before rendering
+        result.integrator = i_options.integrator.build(&result, i_options);
+        return result;
     }
 
-    pub fn render(&self) -> ImagePPM
+    // Moves the camera along the path for an animation frame, with the shutter open during [t0, t1]
+    pub fn set_camera_path(&mut self, i_path: Rc<CameraPath>, t0: Float, t1: Float)
     {
-        let mut result = ImagePPM::new_filled(self.output_size.width  as u32,
-                                              self.output_size.height as u32,
-                                              Vec3::zero());
+        self.camera.set_shutter( t0, t1 );
+        self.camera.set_path( i_path );
+
+        if self.auto_focus { self.camera.auto_focus(&self.scene); }
+    }
 
+    pub fn render(&self) -> Film
+    {
         let h = self.output_size.height as u32;
         let w = self.output_size.width  as u32;
 
+        let mut result = Film::new(w, h);
+        *self.splats.borrow_mut() = vec![Vec3::zero(); (w * h) as usize];
+
         for y in 0..h
         {
             #[cfg(debug_assertions)]
@@ -78,68 +114,279 @@ impl RayTracer
             for x in 0..w
             {
                 let mut color = Vec3::zero();
-                for _ in 0..self.sample_count
+                let mut aovs  = AovSample::background();
+                for s in 0..self.sample_count
                 {
-                    let offset = if self.sample_count > 1 { random::<f32>() } else { 0.0 };
+                    let offset = if self.sample_count > 1 { random::<Float>() } else { 0.0 };
                     // * 0,0 is lower left
-                    let u = (x as f32 + offset) / (w-1) as f32;
-                    let v = ((h-y) as f32 + offset) / (h-1) as f32;
+                    let u = (x as Float + offset) / (w-1) as Float;
+                    let v = ((h-y) as Float + offset) / (h-1) as Float;
 
-                    color += self.compute_ray(self.camera.get_ray(u, v),
-                                              &self.scene);
+                    let (sample_color, sample_aovs) = match self.camera.sample_ray(u, v)
+                    {
+                        Some((mut ray, weight)) =>
+                        {
+                            if self.spectral { ray.wavelength = spectrum::sample_wavelength(); }
+
+                            let (color, aovs) = self.compute_ray(ray);
+                            (color * weight, aovs)
+                        },
+                        None => (Vec3::zero(), AovSample::background())
+                    };
+                    color += sample_color;
+
+                    if s == 0 { aovs = sample_aovs; }
+                    else      { aovs.accumulate(&sample_aovs); }
                 }
 
-                // Gamma2 correction
-                color = (color * (1.0 / self.sample_count as f32)).sqrt();
-                result.set_pixel(x, y, color);
+                aovs.average(self.sample_count);
+                result.set_pixel(x, y, color * (1.0 / self.sample_count as Float), &aovs);
             }
         }
+
+        // Splats are estimates over every light path traced, which there are as many of per pixel as samples.
+        // They only go into the beauty pass.
+        for (pixel, splat) in result.radiance.pixels.iter_mut().zip(self.splats.borrow().iter())
+        {
+            *pixel += *splat * (1.0 / self.sample_count as Float);
+        }
+
+        if let Some(denoiser) = &self.denoiser
+        {
+            result.radiance = denoiser.denoise(&result);
+        }
         return result;
     }
 
     // PRIVATE
-    fn sample_skybox(i_ray: &Ray) -> Vec3
+    fn material_key(i_material: &Rc<dyn crate::materials::Material>) -> usize
+    {
+        return Rc::as_ptr(i_material) as *const () as usize;
+    }
+
+    // One ID per object, so the spheres of a set can be told apart too
+    fn enumerate_objects(i_scene: &[Intersectionable]) -> Vec<u32>
+    {
+        let mut next_id = 1;
+        return i_scene.iter().map(|obj|
+        {
+            let id = next_id;
+            next_id += obj.parts() as u32;
+            id
+        }).collect();
+    }
+
+    // Materials are shared between objects, so their IDs follow the order they first appear in
+    fn enumerate_materials(i_scene: &[Intersectionable]) -> HashMap<usize, u32>
+    {
+        let mut result = HashMap::new();
+        for obj in i_scene
+        {
+            for material in obj.materials()
+            {
+                let next_id = result.len() as u32 + 1;
+                result.entry( Self::material_key(material) ).or_insert(next_id);
+            }
+        }
+        return result;
+    }
+
+    // Objects that can be sampled as area lights
+    fn enumerate_lights(i_scene: &[Intersectionable]) -> Vec<usize>
+    {
+        return (0..i_scene.len()).filter(|idx| i_scene[*idx].area().is_some() && i_scene[*idx].materials().iter().any(|material| material.is_emissive()))
+                                 .collect();
+    }
+
+    fn compute_ray(&self, i_ray: Ray) -> (Vec3, AovSample)
+    {
+        return self.integrator.compute_ray(self, i_ray);
+    }
+
+    // Tiny render of i_scene under a sky as bright as i_sky_intensity, for testing
+    #[cfg(test)]
+    pub fn for_scene(i_scene: Vec<Intersectionable>, i_sky_intensity: Float) -> Self
+    {
+        let options = Options{ width: 4,
+                               height: 4,
+                               sample_count: 1,
+                               scene: super::scene_generator::Preset::Simple,
+                               ..Options::default() };
+
+        let mut result = Self::new(&options);
+        result.object_ids    = Self::enumerate_objects(&i_scene);
+        result.material_ids  = Self::enumerate_materials(&i_scene);
+        result.lights        = Self::enumerate_lights(&i_scene);
+        result.scene         = i_scene;
+        result.sky_intensity = i_sky_intensity;
+        return result;
+    }
+
+    // Renders with i_integrator and i_sample_count samples per pixel instead, for testing
+    #[cfg(test)]
+    pub fn with_integrator(mut self, i_integrator: Box<dyn Integrator>, i_sample_count: i32) -> Self
+    {
+        self.integrator   = i_integrator;
+        self.sample_count = i_sample_count;
+        return self;
+    }
+
+    #[cfg(test)]
+    pub fn camera_mut(&mut self) -> &mut Camera { &mut self.camera }
+
+    // FOR INTEGRATORS
+    pub fn scene(&self) -> &[Intersectionable] { &self.scene }
+
+    pub fn camera(&self) -> &Camera { &self.camera }
+
+    pub fn lights(&self) -> &[usize] { &self.lights }
+
+    // Density per unit solid angle of camera rays going along i_direction, 0 if light tracing can't reach the camera.
+    // Also the camera's importance, what light reaching it that way is weighted by.
+    pub fn camera_pdf(&self, i_direction: Vec3) -> Float
+    {
+        if !self.camera.is_connectible() { return 0.0; }
+
+        // render() spreads the samples of a pixel over one step past its u,v, so the image is a bit more than [0,1]
+        let w = self.output_size.width;
+        let h = self.output_size.height;
+        return self.camera.importance(i_direction) * ((w - 1.0) / w) * ((h - 1.0) / h);
+    }
+
+    // Where light leaving i_point would reach the camera: a point on its lens and the pixel it lands in.
+    // None if it misses the image, or the camera can't be reached by light tracing.
+    pub fn sample_camera(&self, i_point: Vec3) -> Option<(Vec3, usize)>
+    {
+        if !self.camera.is_connectible() { return None; }
+
+        let (origin, u, v) = self.camera.sample_towards(i_point)?;
+
+        // Inverse of the u,v render() picks for a pixel
+        let w   = self.output_size.width;
+        let h   = self.output_size.height;
+        let x   = (u * (w - 1.0)).floor();
+        let row = (v * (h - 1.0)).floor();
+        let y   = h - row;
+        if x < 0.0 || x >= w || y < 0.0 || y >= h { return None; }
+
+        return Some((origin, y as usize * w as usize + x as usize));
+    }
+
+    // Adds light to a pixel other than the one being rendered, scaled like a sample of it.
+    // Only meaningful while rendering.
+    pub fn splat(&self, i_pixel: usize, i_radiance: Vec3)
+    {
+        if let Some(pixel) = self.splats.borrow_mut().get_mut(i_pixel) { *pixel += i_radiance; }
+    }
+
+    pub fn sample_skybox(&self, i_ray: &Ray) -> Vec3
     {
         let dir = i_ray.direction.normalized();
         let t   = 0.5 * (dir.y() + 1.0);
 
-        return Vec3::lerp(Vec3::one()*0.75, Vec3::new(0.0, 0.3, 1.0), t);
+        return Vec3::lerp(Vec3::one()*0.75, Vec3::new(0.0, 0.3, 1.0), t) * self.sky_intensity;
     }
 
-    fn compute_ray(&self, mut i_ray: Ray, i_scene: &Vec<Intersectionable>) -> Vec3
+    // Auxiliary values of the surface a camera ray hit first
+    pub fn first_hit_aovs(&self, i_ray: &Ray, i_idx: usize, i_hit: &HitRecord) -> AovSample
     {
-        let mut depth  = MAX_DEPTH;
-        let mut result = Vec3::one();
-        while depth > 0
-        {
-            let mut closest_hit: Option<HitRecord> = None;
+        AovSample{ normal:      i_hit.p_material.shading_normal(i_hit),
+                   albedo:      i_hit.p_material.albedo(i_hit),
+                   position:    i_hit.position,
+                   depth:       i_hit.distance * i_ray.direction.dot(self.camera.forward()),
+                   object_id:   self.object_ids[i_idx] + i_hit.part as u32,
+                   material_id: self.material_ids[&Self::material_key(&i_hit.p_material)],
+                   ..AovSample::background() }
+    }
+}
 
-            for obj in i_scene
-            {
-                let hit = obj.hit(&i_ray, SHADOW_BIAS, 10.0);
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::hittables::{ closest_hit, prism::Prism, sphere::Sphere, sphere_set::SphereSet };
+    use super::super::materials::dielectric::DielectricMat;
+    use super::super::materials::subsurface::SubsurfaceMat;
+
+    fn new_test_tracer(i_scene: Vec<Intersectionable>) -> RayTracer
+    {
+        RayTracer::for_scene(i_scene, 1.0)
+    }
 
-                if hit.is_none() { continue; }
+    // With an index of refraction of 1 glass neither bends nor reflects head-on rays
+    fn glass_slab(i_thickness: Float, i_glass: DielectricMat) -> Vec<Intersectionable>
+    {
+        let material = Rc::new( i_glass );
+        vec![ Intersectionable::Prism( Prism::new(Vec3::zero(), 4.0, i_thickness, material) ) ]
+    }
 
-                let hit = hit.unwrap();
-                let ch  = closest_hit.unwrap_or( hit.clone() );
+    fn trace_through(i_scene: Vec<Intersectionable>) -> Vec3
+    {
+        let tracer = new_test_tracer(i_scene);
+        let ray    = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        return tracer.compute_ray(ray).0;
+    }
 
-                closest_hit = if hit.distance < ch.distance { Some(hit) }
-                              else { Some(ch) };
-            }
+    #[test]
+    fn beer_lambert_absorption()
+    {
+        let color = Vec3::new(0.5, 0.8, 1.0);
+        let sky   = trace_through( Vec::new() );
+        let thin  = trace_through( glass_slab(1.0, DielectricMat::tinted(1.0, color, 1.0)) );
+        let thick = trace_through( glass_slab(2.0, DielectricMat::tinted(1.0, color, 1.0)) );
 
-            if closest_hit.is_some()
-            {
-                let hit = closest_hit.unwrap();
-                let mut attenuation = Vec3::one();
+        assert_eq!( thin,  sky * color );
+        assert_eq!( thick, sky * color * color );
+    }
 
-                i_ray = hit.p_material.scatter(&i_ray, &hit, &mut attenuation);
+    #[test]
+    fn clear_glass_doesnt_absorb()
+    {
+        let sky   = trace_through( Vec::new() );
+        let clear = trace_through( glass_slab(2.0, DielectricMat::new(1.0, Vec3::one())) );
 
-                result *= attenuation;
-                depth  -= 1;
-            }
-            else { return result * Self::sample_skybox(&i_ray); }
+        assert_eq!( clear, sky );
+    }
+
+    #[test]
+    fn spheres_of_a_set_have_their_own_ids()
+    {
+        let glass   = Rc::new( DielectricMat::new(1.5, Vec3::one()) );
+        let sphere  = |x: Float| Sphere::new(0.4, Vec3::new(x, 0.0, 0.0), glass.clone());
+        let scene   = vec![ Intersectionable::Sphere( sphere(-2.0) ),
+                            Intersectionable::SphereSet( SphereSet::new(vec![sphere(-1.0), sphere(0.0), sphere(1.0)]) ),
+                            Intersectionable::Sphere( sphere(2.0) ) ];
+        let tracer  = new_test_tracer(scene);
+
+        let ids: Vec<u32> = (-2..=2).map(|x|
+        {
+            let ray        = Ray::new(Vec3::new(x as Float, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+            let (idx, hit) = closest_hit(tracer.scene(), &ray, 0.0, Float::INFINITY).unwrap();
+            tracer.first_hit_aovs(&ray, idx, &hit).object_id
+        }).collect();
+
+        assert_eq!( ids, vec![1, 2, 3, 4, 5] );
+    }
+
+    #[test]
+    fn random_walk_finds_its_way_out()
+    {
+        // Scattering without absorbing: whatever gets in leaves again and picks up the color of the sky
+        let material = Rc::new( SubsurfaceMat::new(Vec3::one(), Vec3::one() * 0.2, 1.0) );
+        let scene    = vec![ Intersectionable::Prism( Prism::new(Vec3::zero(), 2.0, 2.0, material) ) ];
+        let tracer   = new_test_tracer(scene);
+
+        let samples = 2000;
+        let mut sum = Vec3::zero();
+        for _ in 0..samples
+        {
+            let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+            sum += tracer.compute_ray(ray).0;
         }
+        let average = sum / samples as Float;
 
-        return Vec3::zero();
+        // Somewhere between the horizon and the zenith of the sky
+        assert!( average.g() > 0.29 && average.g() < 0.76, "{:?}", average );
+        assert!( average.b() > 0.74 && average.b() < 1.01, "{:?}", average );
     }
-}
\ No newline at end of file
+}
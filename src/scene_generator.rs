@@ -1,11 +1,75 @@
-use super::utils::{vec3::Vec3};
-use super::hittables::{ Intersectionable, sphere::Sphere};
+use super::utils::{vec3::Vec3, Float };
+use super::hittables::{ Intersectionable, sphere::Sphere, sphere_set::SphereSet, prism::Prism, quad::Quad, volume::Volume };
 use super::materials::{Material,
                        lambertian::LambertianMat,
                        metallic::MetallicMat,
-                       dielectric::DielectricMat};
+                       principled::PrincipledMat,
+                       mix::MixMat,
+                       coated::CoatedMat,
+                       masked::MaskedMat,
+                       microfacet::Distribution,
+                       subsurface::SubsurfaceMat,
+                       light::DiffuseLightMat,
+                       dielectric::{ DielectricMat, Dispersion }};
+use super::textures::{ SolidTex, CheckerTex };
+use super::media::{ Medium, grid::DensityGrid };
 use std::rc::Rc;
 
+#[derive(Copy, Clone, Debug)]
+pub enum Preset
+{
+    Simple,
+    Rand,
+    Prism,
+    Principled,
+    Microfacet,
+    Smoke,
+    Caustics
+}
+
+impl Preset
+{
+    pub fn from_name(i_name: &str) -> Option<Self>
+    {
+        match i_name
+        {
+            "simple"     => Some(Self::Simple),
+            "rand"       => Some(Self::Rand),
+            "prism"      => Some(Self::Prism),
+            "principled" => Some(Self::Principled),
+            "microfacet" => Some(Self::Microfacet),
+            "smoke"      => Some(Self::Smoke),
+            "caustics"   => Some(Self::Caustics),
+            _            => None
+        }
+    }
+
+    // i_volume: density grid file, for the scenes with a volume
+    pub fn build(&self, i_volume: Option<&str>) -> Vec<Intersectionable>
+    {
+        match self
+        {
+            Self::Simple     => simple(),
+            Self::Rand       => rand(),
+            Self::Prism      => prism(),
+            Self::Principled => principled(),
+            Self::Microfacet => microfacet(),
+            Self::Smoke      => smoke(i_volume),
+            Self::Caustics   => caustics()
+        }
+    }
+
+    // Scenes lit by their own lights dim the sky so it doesn't drown them out
+    pub fn sky_intensity(&self) -> Float
+    {
+        match self
+        {
+            Self::Caustics => 0.05,
+            _              => 1.0
+        }
+    }
+}
+
 #[allow(dead_code)]
 pub fn simple() -> Vec<Intersectionable>
 {
@@ -33,19 +97,21 @@ pub fn rand() -> Vec<Intersectionable>
                                                         Vec3::new(0.0, -1000.0, 0.0),
                                                         ground_mat.clone()) ) );
 
+    // Hundreds of them, tested a few at a time
+    let mut small_spheres: Vec<Sphere> = Vec::new();
     for a in -11..11 {
         for b in -11..11
         {
-            let center = Vec3::new(a as f32 + 0.9 * rand::random::<f32>(),
+            let center = Vec3::new(a as Float + 0.9 * rand::random::<Float>(),
                                    0.2,
-                                   b as f32 + 0.9 * rand::random::<f32>());
+                                   b as Float + 0.9 * rand::random::<Float>());
 
             if (center - Vec3::new(4.0, 0.2, 0.0)).norm() <= 0.9 { continue; }
 
             let albedo:  Vec3;
             let new_mat: Rc<dyn Material>;
 
-            let dice = rand::random::<f32>();
+            let dice = rand::random::<Float>();
 
             if dice < 0.8
             { // Diffuse
@@ -54,7 +120,7 @@ pub fn rand() -> Vec<Intersectionable>
             }
             else if dice < 0.95
             { // Metal
-                let rgh = super::utils::rand_f32_in_range(0.0, 0.5);
+                let rgh = super::utils::rand_in_range(0.0, 0.5);
                 albedo  = Vec3::rand(0.5, 1.0);
                 new_mat = Rc::new( MetallicMat::new(rgh, albedo) );
             }
@@ -64,9 +130,10 @@ pub fn rand() -> Vec<Intersectionable>
                 new_mat = Rc::new( DielectricMat::new(1.5, albedo) );
             }
 
-            result.push( Intersectionable::Sphere( Sphere::new(0.2, center, new_mat) ) );
+            small_spheres.push( Sphere::new(0.2, center, new_mat) );
         }
     }
+    result.push( Intersectionable::SphereSet( SphereSet::new(small_spheres) ) );
 
     let material1 = Rc::new( DielectricMat::new( 1.5, Vec3::one()) );
     result.push( Intersectionable::Sphere( Sphere::new(1.0, Vec3::new(0.0, 1.0, 0.0), material1) ) );
@@ -78,4 +145,182 @@ pub fn rand() -> Vec<Intersectionable>
     result.push( Intersectionable::Sphere( Sphere::new(1.0, Vec3::new(4.0, 1.0, 0.0), material3) ) );
 
     return result;
-}
\ No newline at end of file
+}
+
+// Dense flint glass prism in front of a few colored spheres, meant for the spectral mode
+pub fn prism() -> Vec<Intersectionable>
+{
+    let mut result: Vec<Intersectionable> = Vec::new();
+
+    let ground_mat = Rc::new( LambertianMat{ albedo: Vec3::new(0.8, 0.8, 0.8) } );
+    result.push( Intersectionable::Sphere( Sphere::new( 1000.0,
+                                                        Vec3::new(0.0, -1000.0, 0.0),
+                                                        ground_mat.clone()) ) );
+
+    let glass = Rc::new( DielectricMat::with_dispersion(Dispersion::sf11(), Vec3::one()) );
+    result.push( Intersectionable::Prism( Prism::new(Vec3::new(0.0, 1.2, 0.0), 2.0, 3.0, glass) ) );
+
+    let colors = [Vec3::new(0.9, 0.1, 0.1), Vec3::new(0.9, 0.9, 0.9), Vec3::new(0.1, 0.2, 0.9)];
+    for (i, albedo) in colors.iter().enumerate()
+    {
+        let material = Rc::new( LambertianMat{ albedo: *albedo } );
+        let center   = Vec3::new(-3.0, 0.5, -1.5 + 1.5 * i as Float);
+        result.push( Intersectionable::Sphere( Sphere::new(0.5, center, material) ) );
+    }
+
+    // Colored glass, darker the thicker it gets
+    let green_glass = Rc::new( DielectricMat::tinted(1.5, Vec3::new(0.4, 0.9, 0.5), 1.0) );
+    result.push( Intersectionable::Sphere( Sphere::new(0.6, Vec3::new(1.5, 0.6, -2.0), green_glass) ) );
+
+    return result;
+}
+
+// A row of spheres showing off the principled material's knobs and layered materials, on a checkered floor
+// and in front of a lattice cut out of a single quad
+pub fn principled() -> Vec<Intersectionable>
+{
+    let mut result: Vec<Intersectionable> = Vec::new();
+
+    let checker    = CheckerTex::shared(SolidTex::color(Vec3::new(0.2, 0.2, 0.2)), SolidTex::color(Vec3::new(0.8, 0.8, 0.8)), 1.0);
+    let ground_mat = Rc::new( PrincipledMat{ base_color: checker, roughness: SolidTex::scalar(0.8), ..PrincipledMat::new(Vec3::one()) } );
+    result.push( Intersectionable::Sphere( Sphere::new( 1000.0,
+                                                        Vec3::new(0.0, -1000.0, 0.0),
+                                                        ground_mat) ) );
+
+    let red      = Vec3::new(0.8, 0.1, 0.1);
+    let plastic  = PrincipledMat::new(red);
+    let gold     = PrincipledMat{ metallic:     SolidTex::scalar(1.0),
+                                  roughness:    SolidTex::scalar(0.3),
+                                  ..PrincipledMat::new(Vec3::new(1.0, 0.77, 0.34)) };
+    let paint    = PrincipledMat{ clearcoat:    1.0,
+                                  roughness:    SolidTex::scalar(0.6),
+                                  ..PrincipledMat::new(red) };
+    let velvet   = PrincipledMat{ sheen:        1.0,
+                                  roughness:    SolidTex::scalar(1.0),
+                                  ..PrincipledMat::new(Vec3::new(0.2, 0.2, 0.6)) };
+    let frosted  = PrincipledMat{ transmission: 1.0,
+                                  roughness:    SolidTex::scalar(0.15),
+                                  ..PrincipledMat::new(Vec3::new(0.9, 1.0, 0.9)) };
+
+    // Varnished checkers and copper under a patchy layer of dust
+    let wood_tex = CheckerTex::shared(SolidTex::color(Vec3::new(0.4, 0.2, 0.1)), SolidTex::color(Vec3::new(0.6, 0.35, 0.15)), 0.25);
+    let wood     = Rc::new( PrincipledMat{ base_color: wood_tex, roughness: SolidTex::scalar(1.0), ..PrincipledMat::new(Vec3::one()) } );
+    let dust     = Rc::new( LambertianMat{ albedo: Vec3::new(0.6, 0.6, 0.55) } );
+    let patches  = CheckerTex::shared(SolidTex::scalar(0.7), SolidTex::scalar(0.2), 0.3);
+
+    let materials: Vec<Rc<dyn Material>> = vec![ Rc::new(plastic),
+                                                 Rc::new(gold),
+                                                 Rc::new(paint),
+                                                 Rc::new(velvet),
+                                                 Rc::new(frosted),
+                                                 Rc::new( CoatedMat::new(wood, 1.5).with_roughness(0.1).with_tint(Vec3::new(0.9, 0.75, 0.5)) ),
+                                                 Rc::new( MixMat::textured(Rc::new(MetallicMat::copper(0.3)), dust, patches) ) ];
+
+    for (i, material) in materials.into_iter().enumerate()
+    {
+        let center = Vec3::new(0.0, 0.7, -4.8 + 1.6 * i as Float);
+        result.push( Intersectionable::Sphere( Sphere::new(0.7, center, material) ) );
+    }
+
+    // Translucent marble and wax in front, light bleeds through their edges
+    let marble = SubsurfaceMat::new(Vec3::new(0.9, 0.9, 0.85), Vec3::new(0.2, 0.2, 0.15), 1.5);
+    let wax    = SubsurfaceMat::new(Vec3::new(0.95, 0.7, 0.4), Vec3::new(0.08, 0.04, 0.02), 1.4).with_anisotropy(0.3);
+    result.push( Intersectionable::Sphere( Sphere::new(0.4, Vec3::new(2.0, 0.4, -1.0), Rc::new(marble)) ) );
+    result.push( Intersectionable::Sphere( Sphere::new(0.4, Vec3::new(2.0, 0.4,  1.0), Rc::new(wax)) ) );
+
+    let lattice = CheckerTex::shared(SolidTex::scalar(0.0), SolidTex::scalar(1.0), 0.4);
+    let wall    = Rc::new( MaskedMat::new(Rc::new( LambertianMat{ albedo: Vec3::new(0.7, 0.6, 0.5) } ), lattice) );
+    result.push( Intersectionable::Quad( Quad::new(Vec3::new(-1.5, 0.0, -5.5),
+                                                   Vec3::new(0.0, 3.0, 0.0),
+                                                   Vec3::new(0.0, 0.0, 11.0),
+                                                   wall) ) );
+
+    return result;
+}
+
+// A wall of spheres, from left to right glass, gold, copper, silver and aluminium, the last one with a Beckmann
+// distribution instead of GGX. They get rougher from the bottom up.
+pub fn microfacet() -> Vec<Intersectionable>
+{
+    let mut result: Vec<Intersectionable> = Vec::new();
+
+    let ground_mat = Rc::new( LambertianMat{ albedo: Vec3::new(0.5, 0.5, 0.5) } );
+    result.push( Intersectionable::Sphere( Sphere::new( 1000.0,
+                                                        Vec3::new(0.0, -1000.0, 0.0),
+                                                        ground_mat) ) );
+
+    let columns: [fn(Float) -> Rc<dyn Material>; 5] = [ |rgh| Rc::new( DielectricMat::new(1.5, Vec3::one()).with_roughness(rgh) ),
+                                                        |rgh| Rc::new( MetallicMat::gold(rgh) ),
+                                                        |rgh| Rc::new( MetallicMat::copper(rgh) ),
+                                                        |rgh| Rc::new( MetallicMat::silver(rgh) ),
+                                                        |rgh| Rc::new( MetallicMat::aluminium(rgh).with_distribution(Distribution::Beckmann) ) ];
+
+    for (column, material) in columns.iter().enumerate()
+    {
+        for (row, roughness) in [0.0, 0.15, 0.3, 0.5].iter().enumerate()
+        {
+            let center = Vec3::new(0.0, 0.6 + 1.4 * row as Float, -2.8 + 1.4 * column as Float);
+            result.push( Intersectionable::Sphere( Sphere::new(0.6, center, material(*roughness)) ) );
+        }
+    }
+
+    return result;
+}
+
+// A cloud drifting between a few spheres, made of noise unless a grid file is given
+pub fn smoke(i_volume: Option<&str>) -> Vec<Intersectionable>
+{
+    let mut result: Vec<Intersectionable> = Vec::new();
+
+    let ground_mat = Rc::new( LambertianMat{ albedo: Vec3::new(0.5, 0.5, 0.5) } );
+    result.push( Intersectionable::Sphere( Sphere::new( 1000.0,
+                                                        Vec3::new(0.0, -1000.0, 0.0),
+                                                        ground_mat) ) );
+
+    result.push( Intersectionable::Sphere( Sphere::new(0.6, Vec3::new(-1.0, 0.6, -2.5), Rc::new( LambertianMat{ albedo: Vec3::new(0.8, 0.2, 0.1) } )) ) );
+    result.push( Intersectionable::Sphere( Sphere::new(0.6, Vec3::new(-1.5, 0.6,  2.5), Rc::new( MetallicMat::gold(0.2) )) ) );
+
+    // Mostly scattering, and a bit more forward than backward like water droplets
+    let cloud  = match i_volume.map(DensityGrid::from_file)
+    {
+        Some(Ok(grid)) => grid,
+        Some(Err(e))   => { println!("WARNING: Invalid volume, using noise instead. {}", e); DensityGrid::from_noise(64, 3.0, 1) },
+        None           => DensityGrid::from_noise(64, 3.0, 1)
+    };
+    let cloud  = cloud.placed(Vec3::new(-2.0, 0.1, -2.0), Vec3::new(2.0, 3.1, 2.0));
+    let medium = Medium{ absorption: Vec3::one() * 0.2,
+                         scattering: Vec3::one() * 6.0,
+                         anisotropy: 0.5,
+                         density:    None };
+    result.push( Intersectionable::Volume( Volume::new(Rc::new(cloud), medium) ) );
+
+    return result;
+}
+
+// Glass under a small light, and a lamp hidden behind a ledge washing the wall.
+// Both are hard on a path tracer, see --integrator bdpt.
+pub fn caustics() -> Vec<Intersectionable>
+{
+    let mut result: Vec<Intersectionable> = Vec::new();
+
+    let white = Rc::new( LambertianMat{ albedo: Vec3::new(0.8, 0.8, 0.8) } );
+    result.push( Intersectionable::Sphere( Sphere::new( 1000.0,
+                                                        Vec3::new(0.0, -1000.0, 0.0),
+                                                        white.clone()) ) );
+    result.push( Intersectionable::Quad( Quad::new(Vec3::new(-2.0, 0.0, -4.0), Vec3::new(0.0, 4.0, 0.0), Vec3::new(0.0, 0.0, 8.0), white.clone()) ) );
+
+    result.push( Intersectionable::Sphere( Sphere::new(0.8, Vec3::new(0.0, 0.8,  0.0), Rc::new( DielectricMat::new(1.5, Vec3::one()) )) ) );
+    result.push( Intersectionable::Sphere( Sphere::new(0.6, Vec3::new(0.0, 0.6, -2.2), Rc::new( LambertianMat{ albedo: Vec3::new(0.7, 0.2, 0.1) } )) ) );
+    result.push( Intersectionable::Sphere( Sphere::new(0.6, Vec3::new(0.0, 0.6,  2.2), Rc::new( MetallicMat::gold(0.3) )) ) );
+
+    // Facing down from above the glass
+    let overhead = Rc::new( DiffuseLightMat::new(Vec3::one() * 15.0) );
+    result.push( Intersectionable::Quad( Quad::new(Vec3::new(-0.5, 3.5, -0.5), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), overhead) ) );
+
+    // Facing up, behind a ledge the camera can't see past
+    let uplight = Rc::new( DiffuseLightMat::new(Vec3::new(1.0, 0.7, 0.4) * 4.0) );
+    result.push( Intersectionable::Quad( Quad::new(Vec3::new(-1.8, 0.01, -3.0), Vec3::new(0.0, 0.0, 6.0), Vec3::new(0.4, 0.0, 0.0), uplight) ) );
+    result.push( Intersectionable::Quad( Quad::new(Vec3::new(-1.2, 0.0, -3.0), Vec3::new(0.0, 0.5, 0.0), Vec3::new(0.0, 0.0, 6.0), white) ) );
+
+    return result;
+}
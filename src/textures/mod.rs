@@ -0,0 +1,77 @@
+use super::utils::{ vec3::Vec3, Float };
+use std::rc::Rc;
+
+pub trait Texture: std::fmt::Debug
+{
+    // i_uv: Surface coordinates of the hit
+    // i_position: World space position of the hit
+    fn value(&self, i_uv: (Float, Float), i_position: Vec3) -> Vec3;
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct SolidTex
+{
+    pub color: Vec3
+}
+
+impl SolidTex
+{
+    pub fn color(color: Vec3) -> Rc<dyn Texture> { Rc::new( Self{ color } ) }
+
+    // Same value on every channel, for scalar inputs like roughness
+    pub fn scalar(value: Float) -> Rc<dyn Texture> { Self::color( Vec3::one() * value ) }
+}
+
+impl Texture for SolidTex
+{
+    fn value(&self, _i_uv: (Float, Float), _i_position: Vec3) -> Vec3 { self.color }
+}
+
+// 3D checkerboard, so it doesn't depend on how each object maps its UVs
+#[derive(Clone, Debug)]
+pub struct CheckerTex
+{
+    pub odd:  Rc<dyn Texture>,
+    pub even: Rc<dyn Texture>,
+    pub size: Float // Side of each cell, in world units
+}
+
+impl CheckerTex
+{
+    pub fn shared(odd: Rc<dyn Texture>, even: Rc<dyn Texture>, size: Float) -> Rc<dyn Texture>
+    {
+        Rc::new( Self{ odd, even, size } )
+    }
+}
+
+impl Texture for CheckerTex
+{
+    fn value(&self, i_uv: (Float, Float), i_position: Vec3) -> Vec3
+    {
+        let cell = (i_position.x() / self.size).floor()
+                 + (i_position.y() / self.size).floor()
+                 + (i_position.z() / self.size).floor();
+
+        if cell.rem_euclid(2.0) == 0.0 { self.even.value(i_uv, i_position) }
+        else                           { self.odd.value(i_uv, i_position)  }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn checker()
+    {
+        let black   = Vec3::zero();
+        let white   = Vec3::one();
+        let checker = CheckerTex::shared(SolidTex::color(black), SolidTex::color(white), 0.5);
+
+        assert_eq!( checker.value((0.0, 0.0), Vec3::new( 0.25, 0.25, 0.25)), white );
+        assert_eq!( checker.value((0.0, 0.0), Vec3::new( 0.75, 0.25, 0.25)), black );
+        assert_eq!( checker.value((0.0, 0.0), Vec3::new(-0.25, 0.25, 0.25)), black );
+        assert_eq!( checker.value((0.0, 0.0), Vec3::new(-0.25, -0.25, 0.25)), white );
+    }
+}
@@ -1,16 +1,51 @@
 pub mod ppm;
 pub mod vec3;
+pub mod spectrum;
 
 use vec3::Vec3;
 
+// Scalar used for geometry and shading, f64 with the f64 feature for scenes too large for f32
+#[cfg(not(feature = "f64"))]
+pub type Float = f32;
+#[cfg(feature = "f64")]
+pub type Float = f64;
+
 #[allow(dead_code)]
-pub const PI:  f32 = 3.1415926535897932385;
+pub const PI:  Float = std::f64::consts::PI as Float;
 #[allow(dead_code)]
-pub const TAU: f32 = 6.2831853071;
+pub const TAU: Float = std::f64::consts::TAU as Float;
 
 #[allow(dead_code)]
-pub fn radians_to_degrees(i_radians: f32) -> f32 { i_radians * 180.0 / PI }
-pub fn degrees_to_radians(i_degrees: f32) -> f32 { i_degrees * PI / 180.0 }
+pub fn radians_to_degrees(i_radians: Float) -> Float { i_radians * 180.0 / PI }
+pub fn degrees_to_radians(i_degrees: Float) -> Float { i_degrees * PI / 180.0 }
+
+// Bound on the relative error after n rounded operations in a row, γn in PBRT (3.9)
+pub fn gamma(n: i32) -> Float
+{
+    let epsilon = Float::EPSILON * 0.5;
+    return n as Float * epsilon / (1.0 - n as Float * epsilon);
+}
+
+// The closest float above i_value, NextFloatUp in PBRT (3.9.4). Steps through the bits, positive floats are ordered
+// like their bit patterns and negative ones the other way round.
+pub fn next_float_up(i_value: Float) -> Float
+{
+    if i_value.is_infinite() && i_value > 0.0 { return i_value; }
+
+    let value = if i_value == 0.0 { 0.0 } else { i_value }; // -0 goes up to the smallest positive float too
+    let bits  = value.to_bits();
+    return Float::from_bits( if value >= 0.0 { bits + 1 } else { bits - 1 } );
+}
+
+// The closest float below i_value
+pub fn next_float_down(i_value: Float) -> Float
+{
+    if i_value.is_infinite() && i_value < 0.0 { return i_value; }
+
+    let value = if i_value == 0.0 { -0.0 } else { i_value };
+    let bits  = value.to_bits();
+    return Float::from_bits( if value > 0.0 { bits - 1 } else { bits + 1 } );
+}
 
 pub fn rand_point_in_unit_sphere() -> Vec3
 {
@@ -22,26 +57,167 @@ pub fn rand_point_in_unit_sphere() -> Vec3
     }
 }
 
+#[allow(dead_code)]
 pub fn rand_point_in_unit_hemisphere(i_normal: Vec3) -> Vec3
 {
     let point_in_sphere = rand_point_in_unit_sphere();
-    return point_in_sphere * 1.0_f32.copysign( point_in_sphere.dot(i_normal) );
+    return point_in_sphere * (1.0 as Float).copysign( point_in_sphere.dot(i_normal) );
 }
 
-pub fn rand_f32_in_range(min: f32, max: f32) -> f32
+pub fn rand_in_range(min: Float, max: Float) -> Float
 {
-    return min + rand::random::<f32>() * (max - min);
+    return min + rand::random::<Float>() * (max - min);
 }
 
 pub fn rand_point_in_unit_disk() -> Vec3
 {
     loop
     {
-        let p = Vec3::new(rand_f32_in_range(-1.0, 1.0),
-                          rand_f32_in_range(-1.0, 1.0),
+        let p = Vec3::new(rand_in_range(-1.0, 1.0),
+                          rand_in_range(-1.0, 1.0),
                           0.0);
 
         if p.norm2() >= 1.0 { continue; }
         return p;
     }
 }
+
+// Real roots of a·t² + 2·half_b·t + c = 0, smallest first. The discriminant half_b² - ac is left to the caller,
+// who can usually find a way to compute it that doesn't cancel out, like Sphere::roots.
+// Never subtracts numbers of about the same size like the textbook formula does, which loses most of the digits of
+// the root closer to 0 when b² is much larger than 4ac (Numerical Recipes, 5.6).
+pub fn quadratic_roots(a: Float, half_b: Float, c: Float, discriminant: Float) -> Option<(Float, Float)>
+{
+    if a == 0.0
+    {
+        if half_b == 0.0 { return None; }
+        let root = -c / (2.0 * half_b);
+        return Some((root, root));
+    }
+
+    if discriminant < 0.0 { return None; }
+
+    // Both terms have the same sign
+    let q = -(half_b + discriminant.sqrt().copysign(half_b));
+    if q == 0.0 { return Some((0.0, 0.0)); }
+
+    let (t0, t1) = (q / a, c / q);
+    return Some( if t0 <= t1 { (t0, t1) } else { (t1, t0) } );
+}
+
+// Two unit vectors perpendicular to i_normal and to each other (Duff et al. 2017)
+pub fn orthonormal_basis(i_normal: Vec3) -> (Vec3, Vec3)
+{
+    let sign = (1.0 as Float).copysign(i_normal.z());
+    let a    = -1.0 / (sign + i_normal.z());
+    let b    = i_normal.x() * i_normal.y() * a;
+
+    let tangent   = Vec3::new(1.0 + sign * i_normal.x() * i_normal.x() * a, sign * b, -sign * i_normal.x());
+    let bitangent = Vec3::new(b, sign + i_normal.y() * i_normal.y() * a, -i_normal.y());
+
+    return (tangent, bitangent);
+}
+
+// Uniform point inside a regular polygon inscribed in the unit circle
+pub fn rand_point_in_regular_polygon(i_sides: u32, i_rotation: Float) -> Vec3
+{
+    // Pick one of the triangles fanning out from the center, then a point inside it
+    let side   = (rand::random::<Float>() * i_sides as Float).floor().min(i_sides as Float - 1.0);
+    let step   = TAU / i_sides as Float;
+    let angle0 = i_rotation + side * step;
+    let angle1 = angle0 + step;
+
+    let mut a = rand::random::<Float>();
+    let mut b = rand::random::<Float>();
+    if a + b > 1.0 { a = 1.0 - a; b = 1.0 - b; }
+
+    let v0 = Vec3::new(angle0.cos(), angle0.sin(), 0.0);
+    let v1 = Vec3::new(angle1.cos(), angle1.sin(), 0.0);
+
+    return v0 * a + v1 * b;
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn orthonormal_basis_is_orthonormal()
+    {
+        for normal in &[Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0), Vec3::rand_unit(), Vec3::rand_unit()]
+        {
+            let (t, b) = orthonormal_basis(*normal);
+
+            assert!( (t.norm() - 1.0).abs() < 1e-5 );
+            assert!( (b.norm() - 1.0).abs() < 1e-5 );
+            assert!( t.dot(b).abs()       < 1e-5 );
+            assert!( t.dot(*normal).abs() < 1e-5 );
+            assert!( b.dot(*normal).abs() < 1e-5 );
+        }
+    }
+
+    #[test]
+    fn quadratic_roots()
+    {
+        let solve = |a: Float, half_b: Float, c: Float| super::quadratic_roots(a, half_b, c, half_b * half_b - a * c);
+
+        // (t - 1)(t - 3)
+        assert_eq!( solve(1.0, -2.0, 3.0), Some((1.0, 3.0)) );
+        assert_eq!( solve(-1.0, 2.0, -3.0), Some((1.0, 3.0)) );
+        // Touching at a single point, and not at all
+        assert_eq!( solve(1.0, -2.0, 4.0), Some((2.0, 2.0)) );
+        assert_eq!( solve(1.0, 0.0, 1.0), None );
+        // Not really a quadratic
+        assert_eq!( solve(0.0, 1.0, -4.0), Some((2.0, 2.0)) );
+        assert_eq!( solve(0.0, 0.0, 1.0), None );
+
+        // t² + 2e4·t + 1, the textbook formula gets the root closer to 0 wrong by a lot in f32
+        let (far, near) = solve(1.0, 1e4, 1.0).unwrap();
+        assert!( ((far - -2e4) / 2e4).abs() < 1e-4 );
+        assert!( ((near - -5e-5) / 5e-5).abs() < 1e-4 );
+    }
+
+    #[test]
+    fn next_floats_are_one_ulp_away()
+    {
+        for value in &[1.0, -1.0, 0.0, -0.0, 3.5e-3, -7.0e12, Float::MIN_POSITIVE]
+        {
+            let up   = next_float_up(*value);
+            let down = next_float_down(*value);
+            assert!( up > *value && down < *value );
+            // Nothing fits in between
+            assert!( (*value + up) / 2.0 == *value || (*value + up) / 2.0 == up );
+            assert!( (*value + down) / 2.0 == *value || (*value + down) / 2.0 == down );
+        }
+
+        assert_eq!( next_float_up(0.0), Float::from_bits(1) );
+        assert_eq!( next_float_down(-0.0), -Float::from_bits(1) );
+        assert_eq!( next_float_up(Float::MAX), Float::INFINITY );
+        assert_eq!( next_float_up(Float::INFINITY), Float::INFINITY );
+        assert_eq!( next_float_down(Float::NEG_INFINITY), Float::NEG_INFINITY );
+    }
+
+    #[test]
+    fn rand_point_in_regular_polygon_stays_inside()
+    {
+        let sides    = 5;
+        let rotation = 0.3;
+        let apothem  = (PI / sides as Float).cos();
+
+        for _ in 0..1000
+        {
+            let p = rand_point_in_regular_polygon(sides, rotation);
+            assert!( p.z() == 0.0 );
+
+            // Inside every edge's half-plane
+            for side in 0..sides
+            {
+                let mid_angle = rotation + (side as Float + 0.5) * TAU / sides as Float;
+                let edge_norm = Vec3::new(mid_angle.cos(), mid_angle.sin(), 0.0);
+                assert!( p.dot(edge_norm) <= apothem + 1e-5 );
+            }
+        }
+    }
+}
+
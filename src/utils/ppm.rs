@@ -33,7 +33,6 @@ impl ImagePPM
         self.pixels[idx] = i_val;
     }
 
-    #[allow(dead_code)]
     pub fn get_pixel(&self, x: u32, y: u32) -> Vec3
     {
         let idx = ((y * self.width) + x) as usize;
@@ -47,7 +46,7 @@ impl ImagePPM
         let mut i = 0;
         for pixel in &self.pixels
         {
-            result[i+0] = (pixel.r() * 255.0) as u8;
+            result[i]   = (pixel.r() * 255.0) as u8;
             result[i+1] = (pixel.g() * 255.0) as u8;
             result[i+2] = (pixel.b() * 255.0) as u8;
 
@@ -63,8 +62,40 @@ impl ImagePPM
         let mut file   = File::create(path).unwrap();
         let     header = format!("P6 {} {} 255\n", self.width, self.height);
 
-        file.write(header.as_bytes()).unwrap();
-        file.write(self.get_data().as_slice()).unwrap();
+        file.write_all(header.as_bytes()).unwrap();
+        file.write_all(self.get_data().as_slice()).unwrap();
+    }
+
+    // Portable Float Map: same layout as the PPM but keeps the raw linear values
+    pub fn to_pfm_file(&self, i_file_name: &str)
+    {
+        let     path   = Path::new(i_file_name);
+        let mut file   = File::create(path).unwrap();
+        // Negative scale means little endian
+        let     header = format!("PF\n{} {}\n-1.0\n", self.width, self.height);
+
+        file.write_all(header.as_bytes()).unwrap();
+        file.write_all(self.get_float_data().as_slice()).unwrap();
+    }
+
+    pub fn get_float_data(&self) -> Vec<u8>
+    {
+        let mut result = Vec::with_capacity(self.size() * 3 * 4);
+
+        // PFM scanlines go from bottom to top
+        for y in (0..self.height).rev()
+        {
+            for x in 0..self.width
+            {
+                let pixel = self.get_pixel(x, y);
+                for channel in &[pixel.r(), pixel.g(), pixel.b()]
+                {
+                    // Always 32 bits, whatever the renderer uses
+                    result.extend_from_slice( &(*channel as f32).to_le_bytes() );
+                }
+            }
+        }
+        return result;
     }
 }
 
@@ -96,4 +127,17 @@ mod tests
         assert_eq!( g, (rand_color.g() * 255.0) as u8 );
         assert_eq!( b, (rand_color.b() * 255.0) as u8 );
     }
+
+    #[test]
+    fn get_float_data_flips_rows()
+    {
+        let mut image = ImagePPM::new_filled(1, 2, Vec3::zero());
+        image.set_pixel(0, 1, Vec3::new(1.0, 2.0, 3.0));
+
+        let raw_data = image.get_float_data();
+        let first    = f32::from_le_bytes([raw_data[0], raw_data[1], raw_data[2], raw_data[3]]);
+
+        assert_eq!( raw_data.len(), 2 * 3 * 4 );
+        assert_eq!( first, 1.0 );
+    }
 }
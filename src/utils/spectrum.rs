@@ -0,0 +1,151 @@
+use std::sync::OnceLock;
+use super::vec3::Vec3;
+use super::Float;
+
+// Visible range sampled by the spectral mode, in nanometers
+pub const MIN_WAVELENGTH: Float = 380.0;
+pub const MAX_WAVELENGTH: Float = 730.0;
+
+// Integral of the CIE y color matching function over the visible range
+const CIE_Y_INTEGRAL: Float = 106.917;
+
+pub fn sample_wavelength() -> Float
+{
+    return super::rand_in_range(MIN_WAVELENGTH, MAX_WAVELENGTH);
+}
+
+// Piecewise gaussian used by the CIE fit, with a different spread at each side of the peak
+fn gaussian(x: Float, mu: Float, sigma_left: Float, sigma_right: Float) -> Float
+{
+    let sigma = if x < mu { sigma_left } else { sigma_right };
+    let t     = (x - mu) / sigma;
+    return (-0.5 * t * t).exp();
+}
+
+// CIE 1931 2° color matching functions, multi-lobe fit from Wyman, Sloan & Shirley (2013)
+pub fn cie_xyz(wavelength: Float) -> Vec3
+{
+    let x = 1.056 * gaussian(wavelength, 599.8, 37.9, 31.0)
+          + 0.362 * gaussian(wavelength, 442.0, 16.0, 26.7)
+          - 0.065 * gaussian(wavelength, 501.1, 20.4, 26.2);
+    let y = 0.821 * gaussian(wavelength, 568.8, 46.9, 40.5)
+          + 0.286 * gaussian(wavelength, 530.9, 16.3, 31.1);
+    let z = 1.217 * gaussian(wavelength, 437.0, 11.8, 36.0)
+          + 0.681 * gaussian(wavelength, 459.0, 26.0, 13.8);
+
+    return Vec3::new(x, y, z);
+}
+
+// To linear sRGB (D65)
+pub fn xyz_to_rgb(xyz: Vec3) -> Vec3
+{
+    Vec3::new( 3.2406 * xyz.x() - 1.5372 * xyz.y() - 0.4986 * xyz.z(),
+              -0.9689 * xyz.x() + 1.8758 * xyz.y() + 0.0415 * xyz.z(),
+               0.0557 * xyz.x() - 0.2040 * xyz.y() + 1.0570 * xyz.z())
+}
+
+fn logistic(x: Float, center: Float, width: Float) -> Float
+{
+    return 1.0 / (1.0 + (-(x - center) / width).exp());
+}
+
+// Smooth spectrum for an RGB color, built from three overlapping bands that add up to 1,
+// so greys stay flat and reflectances in [0,1] never go outside of it
+pub fn rgb_to_spectral(rgb: Vec3, wavelength: Float) -> Float
+{
+    let blue  = 1.0 - logistic(wavelength, 490.0, 10.0);
+    let red   = logistic(wavelength, 585.0, 10.0);
+    let green = 1.0 - red - blue;
+
+    return rgb.r() * red + rgb.g() * green + rgb.b() * blue;
+}
+
+// RGB a flat spectrum ends up as, used to keep white white
+fn white_point() -> Vec3
+{
+    static WHITE: OnceLock<Vec3> = OnceLock::new();
+    return *WHITE.get_or_init(||
+    {
+        let steps  = 1000;
+        let step   = (MAX_WAVELENGTH - MIN_WAVELENGTH) / steps as Float;
+        let mut xyz = Vec3::zero();
+        for i in 0..steps
+        {
+            xyz += cie_xyz(MIN_WAVELENGTH + (i as Float + 0.5) * step) * step;
+        }
+        xyz_to_rgb(xyz / CIE_Y_INTEGRAL)
+    });
+}
+
+// Film contribution of a radiance sample carried by a single, uniformly sampled, wavelength
+pub fn spectral_to_rgb(radiance: Float, wavelength: Float) -> Vec3
+{
+    let inv_pdf = MAX_WAVELENGTH - MIN_WAVELENGTH;
+    let rgb     = xyz_to_rgb(cie_xyz(wavelength) * (radiance * inv_pdf / CIE_Y_INTEGRAL));
+    let white   = white_point();
+
+    return Vec3::new(rgb.r() / white.r(), rgb.g() / white.g(), rgb.b() / white.b());
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use float_cmp::approx_eq;
+
+    fn integrate_rgb(color: Vec3) -> Vec3
+    {
+        let steps = 2000;
+        let step  = (MAX_WAVELENGTH - MIN_WAVELENGTH) / steps as Float;
+
+        let mut result = Vec3::zero();
+        for i in 0..steps
+        {
+            let wavelength = MIN_WAVELENGTH + (i as Float + 0.5) * step;
+            result += spectral_to_rgb(rgb_to_spectral(color, wavelength), wavelength);
+        }
+        return result / steps as Float;
+    }
+
+    #[test]
+    fn cie_y_integral()
+    {
+        let steps = 1000;
+        let step  = (MAX_WAVELENGTH - MIN_WAVELENGTH) / steps as Float;
+        let sum: Float = (0..steps).map(|i| cie_xyz(MIN_WAVELENGTH + (i as Float + 0.5) * step).y() * step)
+                                 .sum();
+
+        assert!( approx_eq!(Float, sum, CIE_Y_INTEGRAL, epsilon = 0.01) );
+    }
+
+    #[test]
+    fn white_round_trip()
+    {
+        let white = integrate_rgb(Vec3::one());
+        assert!( (white - Vec3::one()).norm() < 0.0001 );
+    }
+
+    #[test]
+    fn primaries_keep_their_hue()
+    {
+        let red   = integrate_rgb(Vec3::new(1.0, 0.0, 0.0));
+        let green = integrate_rgb(Vec3::new(0.0, 1.0, 0.0));
+        let blue  = integrate_rgb(Vec3::new(0.0, 0.0, 1.0));
+
+        assert!( red.r()   > red.g()   && red.r()   > red.b() );
+        assert!( green.g() > green.r() && green.g() > green.b() );
+        assert!( blue.b()  > blue.r()  && blue.b()  > blue.g() );
+    }
+
+    #[test]
+    fn rgb_to_spectral_stays_in_range()
+    {
+        let mut wavelength = MIN_WAVELENGTH;
+        while wavelength < MAX_WAVELENGTH
+        {
+            let value = rgb_to_spectral(Vec3::new(0.2, 1.0, 0.6), wavelength);
+            assert!( (0.2..=1.0).contains(&value) );
+            wavelength += 1.0;
+        }
+    }
+}
@@ -1,35 +1,36 @@
 use std::ops::{ Add, AddAssign, Mul, MulAssign, Div, Sub, Neg };
 use float_cmp::approx_eq;
-use super::TAU;
+use super::{ TAU, Float };
 
 #[derive(Copy, Clone, Debug)]
 pub struct Vec3
 {
-    data: [f32; 3]
+    data: [Float; 3]
 }
 
 impl Vec3
 {
-    pub fn zero() -> Self { Self{ data: [0.0, 0.0, 0.0] } }
-    pub fn one()  -> Self { Self{ data: [1.0, 1.0, 1.0] } }
+    pub const fn zero() -> Self { Self{ data: [0.0, 0.0, 0.0] } }
+    pub const fn one()  -> Self { Self{ data: [1.0, 1.0, 1.0] } }
+    #[allow(dead_code)]
     pub fn debug_color() -> Self { Self{ data: [1.0, 0.0, 1.0] } }
-    pub fn new(x: f32, y: f32, z: f32) -> Self { Self{ data: [x,y,z] } }
+    pub const fn new(x: Float, y: Float, z: Float) -> Self { Self{ data: [x,y,z] } }
 
-    //pub fn set(&mut self, x: f32, y: f32, z: f32)
+    //pub fn set(&mut self, x: Float, y: Float, z: Float)
     //{
     //    self.data[0] = x;
     //    self.data[1] = y;
     //    self.data[2] = z;
     //}
 
-    pub fn r(&self) -> f32 { self.data[0] }
-    pub fn g(&self) -> f32 { self.data[1] }
-    pub fn b(&self) -> f32 { self.data[2] }
-    pub fn x(&self) -> f32 { self.data[0] }
-    pub fn y(&self) -> f32 { self.data[1] }
-    pub fn z(&self) -> f32 { self.data[2] }
+    pub fn r(&self) -> Float { self.data[0] }
+    pub fn g(&self) -> Float { self.data[1] }
+    pub fn b(&self) -> Float { self.data[2] }
+    pub fn x(&self) -> Float { self.data[0] }
+    pub fn y(&self) -> Float { self.data[1] }
+    pub fn z(&self) -> Float { self.data[2] }
 
-    pub fn dot(&self, other: Self) -> f32
+    pub fn dot(&self, other: Self) -> Float
     {
         return self.x() * other.x() + self.y() * other.y() + self.z() * other.z();
     }
@@ -44,30 +45,30 @@ impl Vec3
         ]}
     }
 
-    pub fn norm2(&self)      -> f32  { return self.dot( *self );   }
-    pub fn norm(&self)       -> f32  { return self.norm2().sqrt(); }
+    pub fn norm2(&self)      -> Float  { return self.dot( *self );   }
+    pub fn norm(&self)       -> Float  { return self.norm2().sqrt(); }
     pub fn normalized(&self) -> Self { return *self / self.norm(); }
 
-    pub fn lerp(a: Self, b: Self, t: f32) -> Self
+    pub fn lerp(a: Self, b: Self, t: Float) -> Self
     {
         if      t >= 1.0 { return b; }
         else if t <= 0.0 { return a; }
         else             { return a * (1.0-t) + b * t;}
     }
 
-    pub fn rand(min: f32, max: f32) -> Self
+    pub fn rand(min: Float, max: Float) -> Self
     {
-        let x = min + rand::random::<f32>() * (max-min);
-        let y = min + rand::random::<f32>() * (max-min);
-        let z = min + rand::random::<f32>() * (max-min);
+        let x = min + rand::random::<Float>() * (max-min);
+        let y = min + rand::random::<Float>() * (max-min);
+        let z = min + rand::random::<Float>() * (max-min);
 
         return Vec3::new(x,y,z);
     }
 
     pub fn rand_unit() -> Self
     {
-        let a = rand::random::<f32>() * (TAU);
-        let z = -1.0 + rand::random::<f32>() * 2.0;
+        let a = rand::random::<Float>() * (TAU);
+        let z = -1.0 + rand::random::<Float>() * 2.0;
         let r = (1.0 - z*z).sqrt();
 
         return Vec3::new(r*a.cos(), r*a.sin(), z);
@@ -82,6 +83,16 @@ impl Vec3
         return Vec3::new(x,y,z);
     }
 
+    pub fn exp(&self) -> Self
+    {
+        return Vec3::new(self.x().exp(), self.y().exp(), self.z().exp());
+    }
+
+    pub fn abs(&self) -> Self
+    {
+        return Vec3::new(self.x().abs(), self.y().abs(), self.z().abs());
+    }
+
     pub fn reflect(&self, n: Vec3) -> Vec3
     {
         return *self - n * self.dot(n) * 2.0;
@@ -89,7 +100,7 @@ impl Vec3
 
     // n: Surface normal
     // eta: Refraction index of the first medium over the second's (ni/nr)
-    pub fn refract(&self, n: Vec3, eta: f32) -> Vec3
+    pub fn refract(&self, n: Vec3, eta: Float) -> Vec3
     {
         let cos_theta    = (-*self).dot(n);
         let r_orthogonal = (*self + n * cos_theta) * eta;
@@ -133,10 +144,10 @@ impl Sub for Vec3
     }
 }
 
-impl Mul<f32> for Vec3
+impl Mul<Float> for Vec3
 {
     type Output = Vec3;
-    fn mul(self, val: f32) -> Vec3
+    fn mul(self, val: Float) -> Vec3
     {
         Vec3 { data:
         [
@@ -161,9 +172,9 @@ impl Mul<Vec3> for Vec3
     }
 }
 
-impl MulAssign<f32> for Vec3
+impl MulAssign<Float> for Vec3
 {
-    fn mul_assign(&mut self, val: f32) { *self = *self * val }
+    fn mul_assign(&mut self, val: Float) { *self = *self * val }
 }
 
 impl MulAssign<Vec3> for Vec3
@@ -171,10 +182,10 @@ impl MulAssign<Vec3> for Vec3
     fn mul_assign(&mut self, other: Vec3) { *self = *self * other }
 }
 
-impl Div<f32> for Vec3
+impl Div<Float> for Vec3
 {
     type Output = Vec3;
-    fn div(self, val: f32) -> Vec3 { self * (1.0/val) }
+    fn div(self, val: Float) -> Vec3 { self * (1.0/val) }
 }
 
 impl Neg for Vec3
@@ -187,9 +198,9 @@ impl PartialEq for Vec3
 {
     fn eq(&self, other: &Vec3) -> bool
     {
-        approx_eq!(f32, self.x(), other.x()) &&
-        approx_eq!(f32, self.y(), other.y()) &&
-        approx_eq!(f32, self.z(), other.z())
+        approx_eq!(Float, self.x(), other.x()) &&
+        approx_eq!(Float, self.y(), other.y()) &&
+        approx_eq!(Float, self.z(), other.z())
     }
 }
 
@@ -205,7 +216,7 @@ mod tests
                         .normalized()
                         .norm();
 
-        assert!( approx_eq!( f32, norm, 1.0 ) );
+        assert!( approx_eq!( Float, norm, 1.0 ) );
     }
 
     #[test]
@@ -214,9 +225,9 @@ mod tests
         let vec_x = Vec3::new(1.0, 0.0, 0.0);
         let vec_y = Vec3::new(0.0, 1.0, 0.0);
 
-        assert!( approx_eq!(f32, vec_x.dot( vec_y ), 0.0) );
-        assert!( approx_eq!(f32, vec_x.dot( vec_x ), 1.0) );
-        assert!( approx_eq!(f32, vec_x.dot( -vec_x ), -1.0) );
+        assert!( approx_eq!(Float, vec_x.dot( vec_y ), 0.0) );
+        assert!( approx_eq!(Float, vec_x.dot( vec_x ), 1.0) );
+        assert!( approx_eq!(Float, vec_x.dot( -vec_x ), -1.0) );
     }
 
     #[test]
@@ -233,7 +244,7 @@ mod tests
     fn random_unit_vector()
     {
         let vector = Vec3::rand_unit();
-        assert!( approx_eq!(f32, vector.norm(), 1.0) );
+        assert!( approx_eq!(Float, vector.norm(), 1.0) );
     }
 
     #[test]
@@ -266,9 +277,9 @@ mod tests
                                 .normalized();
 
         let cos_angle = inciding.dot( refracted );
-        let expected  = (28.1255 as f32).cos();
+        let expected  = (28.1255 as Float).cos();
 
         println!("TRACE! Actual: {} vs Expected: {}", cos_angle, expected);
-        assert!( approx_eq!( f32, cos_angle, expected ) );
+        assert!( approx_eq!( Float, cos_angle, expected ) );
     }
 }